@@ -0,0 +1,250 @@
+//! `compute_statistics` and cache round trips over a synthetic 10,000-block cache, and JSON
+//! against MessagePack `cache_format` load and save times for a 100,000-block one.
+//!
+//! Run with `cargo bench --features bench`; the feature also lets the mostly unmatched
+//! corpus be timed with and without the bloom filter pre-check. `compute_statistics` is
+//! timed on rayon's default pool and on a single thread; before timing, the number of
+//! allocations and bytes allocated by one call on each is printed to stderr, since
+//! criterion only measures time.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use pepper_sync::keys::transparent::{self, TransparentScope};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs,
+    hint::black_box,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use uview_miner_stats_lib::{
+    BlockCache, CacheFormat, CachedBlock, CoinbaseOutput, MinerStatsConfig, SKIP_BLOOM_PRECHECK,
+    compute_statistics,
+};
+use zcash_primitives::legacy::keys::NonHardenedChildIndex;
+use zingolib::config::chain_from_str;
+use zingolib::wallet::keys::unified::UnifiedKeyStore;
+
+const BLOCKS: u64 = 10_000;
+const LARGE_CACHE_BLOCKS: u64 = 100_000;
+
+/// Counts allocations so the derivation pass can be compared by memory churn as well as time.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn report_allocations(name: &str, run: impl FnOnce()) {
+    let (count, bytes) = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        ALLOCATED_BYTES.load(Ordering::Relaxed),
+    );
+    run();
+    eprintln!(
+        "{name}: {} allocations, {} bytes",
+        ALLOCATIONS.load(Ordering::Relaxed) - count,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes
+    );
+}
+
+/// Synthetic regtest UFVKs.
+const MINER_UFVKS: [&str; 5] = [
+    "uviewregtest1w5eddcgh298pcg7p4uda6u2fdtgz8pvpm96mvjm8nwhr7zefr08h3aptk5gdl5cn8kt7k2vm0wrw02tm4vuv87c9j84nn5jytdhkl3s8w3lqf89fcnqc8wk4y4slq68dqmuvadjxhamnr2lv2waqx4cq4s8uwmsqw7dnt8r8gmznyrqgd2g5glrav3aqzf23pxtdw58dj9f40sg8rfxvag4xgnnj56wnpflkuwkyd9x4xu65ff5m4v2yzzuurzhnknemx679ndslw2tlqcm2yw49aujdfuqe8nec6elwxl4lhm85l5c9ke0h2952dy5y20ncmke39fahyqr34fk9w2lahrh",
+    "uviewregtest1h6rdhy9f0ah5p3j8p0tjqnq08yq6mlwu3p3ktpqrvf7gmukqvdav8sdmcmhny8ltux8xn3vdvh27urm4wm2vutzljjpd3elc7dq34zh06wt9hffcjyt8tc35dukfu4z96ymrczyuq4qnpjml2lht5c0dqfpdp3frxsmfxjytgtqqw9f0p48syadsy82wzatu7zxmrv26v93kf5lj2mgfsz02rjwjhatvqcnjqh4f2wv5lz6ckt2sh9ep7mhpqylrlxxz0mzk4j8rs3sntralwhqaefm8yul2lyz9ktjw59np463vzs048gjh8elw8tjvw7nm042pn5qvpfmxajtyk9tva9m",
+    "uviewregtest1cdnnajm006h7z3dyvrlscuzh8ufsqakvwl8ap0lj53m4p8627k2nchuzd83uae3xqxa8m4k80y9m23jl53nu6g0x8pem6xx3h0lln5x8qxn58qu5evlcqegn5gcmclxmcxnrqpadnqmurswjzscsu7r0mxcth06tzg38gpe2hja8j8m8cjmlc7xkmc58g04pfn5k88h4ahwkw6wmknz6gan54thhnul23xh46wfvjygnthhrwgkjwauk0uwvseq58f2k42jkm7yv6ptmhp4ztsp5k7cmaxz6zghzs5dm0gxea6s50cg5egyz3d5fv8wx4l0dfg3emvalgy2zu3kv5pdcj8q",
+    "uviewregtest1ar5aelv7krpwa5zaqs8ru6fsm5ydcy3rwtr53zl9vvetss0tzgzl4g6chf7p5gzr6ugscvhp96axzmk4l6qpygdqjywe2ngnffpc6s3lmr7s0najwuu5323qlwpyyvpercdevwhfvtm8cu9kpeavlm5fhvhlfpy9pxq67fp0x4fk7uk3h69h3d8ajqyq9waaz4dpng6skr8l5m3q3e72s9jv322xyvmpvdp3xwsv0a7nwt8mu9z57tgnkx4jycuxms8pxfvyj42ye38rgs6qlq56knraxz95qlnwk37j96jlsh28zeer5vd3cv0gka3nr6nsp3mp3w88lw7uhtl2qa5ryvh",
+    "uviewregtest1nnj2mwlyylrdy68y29gp9kr6u3mwsd3j992qlzckzgrkm7pk6psqryyaux88mnsg2hhv82sg9s6cvuxnwmzzedfk7cjfzalz3gg06sm2l7u92xk74gyuuypfmsnk7tk73vh9hjusqkhxfznw8fpyv8tlwrwp5xyxwlp6da246wc8ya6z8gyrgg8fajaqptpr06eqnue29m653m9kchxdc6lhaudw9wyl66kj8ns6zkz2ukuct427hjunlmxlspe04s0agj62axjpwwlxkxenwfpju2awn6t67z42s4jsxu0tna337x8uffa386d8yrx6kmurw272uqsa3pr9jha3c2qt2nz",
+];
+
+/// Deterministic xorshift so every run benchmarks the same corpus.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn address(&mut self) -> String {
+        format!("tmRandom{:026x}", self.next())
+    }
+}
+
+fn work_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("uview-miner-stats-bench-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn load_config(dir: &Path) -> MinerStatsConfig {
+    let ufvks: String = MINER_UFVKS
+        .iter()
+        .enumerate()
+        .map(|(i, key)| format!("    {{ label = \"Miner {i}\", key = \"{key}\" }},\n"))
+        .collect();
+    let path = dir.join("config.toml");
+    fs::write(
+        &path,
+        format!(
+            "start_height = 0\n             chain = \"regtest\"\n             rpc_url = \"http://127.0.0.1:1\"\n             cache_file = \"{}\"\n             output_file = \"{}\"\n             ufvks = [\n{ufvks}]\n",
+            dir.join("cache.json").display(),
+            dir.join("report.json").display(),
+        ),
+    )
+    .unwrap();
+    MinerStatsConfig::from_file(&path).unwrap()
+}
+
+/// `blocks` blocks, `miner_percent` percent of which pay one of the configured miners at its
+/// derived address; the rest pay random addresses. Every block also carries one or two
+/// unrelated outputs.
+fn synthetic_cache(miner_percent: u64, blocks: u64) -> BlockCache {
+    let chain = chain_from_str("regtest").unwrap();
+    let key_stores: Vec<UnifiedKeyStore> = MINER_UFVKS
+        .iter()
+        .map(|key| UnifiedKeyStore::new_from_ufvk(&chain, key.to_string()).unwrap())
+        .collect();
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let mut cache = BlockCache {
+        last_tip: Some(blocks - 1),
+        ..BlockCache::default()
+    };
+    for height in 0..blocks {
+        let roll = rng.next() % 100;
+        let winner = (roll < miner_percent).then(|| &key_stores[roll as usize % key_stores.len()]);
+        let payout = match winner {
+            Some(key_store) => {
+                let index = NonHardenedChildIndex::from_index(height as u32).unwrap();
+                let address = key_store
+                    .generate_transparent_address(index, TransparentScope::External)
+                    .unwrap();
+                transparent::encode_address(&chain, address)
+            }
+            None => rng.address(),
+        };
+        let mut outputs = vec![CoinbaseOutput {
+            value_zat: 500_000_000,
+            addresses: vec![payout],
+            hex: None,
+        }];
+        for _ in 0..1 + rng.next() % 2 {
+            outputs.push(CoinbaseOutput {
+                value_zat: (rng.next() % 125_000_000) as i64,
+                addresses: vec![rng.address()],
+                hex: None,
+            });
+        }
+        cache.blocks.insert(
+            height,
+            CachedBlock {
+                height,
+                hash: format!("{:064x}", rng.next()),
+                time: 1_700_000_000 + height as u32 * 75,
+                outputs,
+                subsidy_zat: None,
+            },
+        );
+    }
+    cache
+}
+
+fn benches(c: &mut Criterion) {
+    let dir = work_dir();
+    let cfg = load_config(&dir);
+    let cache = synthetic_cache(50, BLOCKS);
+    // Mostly blocks of unknown miners: the case the bloom filter pre-check speeds up.
+    let sparse = synthetic_cache(2, BLOCKS);
+
+    // `one_thread` runs the address derivation and the scan on a single thread, as
+    // `compute_statistics` did before both became parallel passes over the miners.
+    let one_thread = rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("compute_statistics/10k_blocks_5_miners");
+    for (name, pool) in [("parallel", None), ("one_thread", Some(&one_thread))] {
+        let run = || match pool {
+            Some(pool) => pool.install(|| compute_statistics(&cfg, black_box(&cache), BLOCKS - 1)),
+            None => compute_statistics(&cfg, black_box(&cache), BLOCKS - 1),
+        };
+        report_allocations(
+            &format!("compute_statistics/10k_blocks_5_miners/{name}"),
+            || {
+                run().unwrap();
+            },
+        );
+        group.bench_function(name, |b| b.iter(|| run().unwrap()));
+    }
+    group.finish();
+
+    // `without_bloom` scans every block, as `compute_statistics` did before the pre-check.
+    let mut group = c.benchmark_group("compute_statistics/10k_blocks_mostly_unmatched");
+    for (name, skip) in [("with_bloom", false), ("without_bloom", true)] {
+        SKIP_BLOOM_PRECHECK.store(skip, Ordering::Relaxed);
+        group.bench_function(name, |b| {
+            b.iter(|| compute_statistics(&cfg, black_box(&sparse), BLOCKS - 1).unwrap())
+        });
+    }
+    SKIP_BLOOM_PRECHECK.store(false, Ordering::Relaxed);
+    group.finish();
+
+    let mut group = c.benchmark_group("cache_round_trip/10k_blocks");
+    for compressed in [false, true] {
+        let path = dir.join(format!("cache-{compressed}.json"));
+        let name = if compressed { "zstd" } else { "plain" };
+        cache.save(&path, compressed).unwrap();
+        eprintln!(
+            "cache_round_trip/10k_blocks/{name}: {} bytes",
+            fs::metadata(&path).unwrap().len()
+        );
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                cache.save(&path, compressed).unwrap();
+                BlockCache::load(black_box(&path)).unwrap().0
+            })
+        });
+    }
+    group.finish();
+
+    let large = synthetic_cache(2, LARGE_CACHE_BLOCKS);
+    let mut group = c.benchmark_group("cache_format/100k_blocks");
+    group.sample_size(10);
+    for (name, format) in [
+        ("json", CacheFormat::Json),
+        ("msgpack", CacheFormat::Msgpack),
+    ] {
+        let path = dir.join(format!("cache-large.{name}"));
+        large.save_as(&path, format, false).unwrap();
+        eprintln!(
+            "cache_format/100k_blocks/{name}: {} bytes",
+            fs::metadata(&path).unwrap().len()
+        );
+        group.bench_function(format!("save/{name}"), |b| {
+            b.iter(|| black_box(&large).save_as(&path, format, false).unwrap())
+        });
+        group.bench_function(format!("load/{name}"), |b| {
+            b.iter(|| BlockCache::load(black_box(&path)).unwrap().0)
+        });
+    }
+    group.finish();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+criterion_group!(stats, benches);
+criterion_main!(stats);
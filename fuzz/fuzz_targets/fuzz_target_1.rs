@@ -0,0 +1,10 @@
+//! Arbitrary bytes as a plain JSON block cache: parsing may fail but must not panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uview_miner_stats_lib::BlockCache;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = serde_json::from_slice::<BlockCache>(data);
+});
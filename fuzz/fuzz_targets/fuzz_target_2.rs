@@ -0,0 +1,12 @@
+//! Arbitrary bytes as a configuration file: parsing may fail but must not panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use uview_miner_stats_lib::ConfigFile;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(raw) = std::str::from_utf8(data) {
+        let _ = toml::from_str::<ConfigFile>(raw);
+    }
+});
@@ -0,0 +1,106 @@
+//! On-disk cache of derived transparent addresses (`derived_address_cache`), so repeated
+//! runs over the same range skip the key derivation. Independent of the block cache and
+//! versioned separately.
+
+use crate::{
+    error::{Error, Result},
+    write_atomic,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+use tracing::warn;
+
+/// Schema version written to new address caches.
+pub const ADDRESS_CACHE_VERSION: u32 = 1;
+
+/// Leading characters of a UFVK used to file its addresses. Past the HRP the encoding is
+/// F4Jumbled, so even a short prefix tells keys apart without storing them in full.
+const KEY_ID_LEN: usize = 32;
+
+/// Encoded external transparent address per key and height.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DerivedAddressCache {
+    version: u32,
+    /// Key prefix → height → encoded address. JSON maps need string keys, so the
+    /// `(key, height)` pair is split into two levels.
+    addresses: BTreeMap<String, BTreeMap<u64, String>>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl Default for DerivedAddressCache {
+    fn default() -> Self {
+        Self {
+            version: ADDRESS_CACHE_VERSION,
+            addresses: BTreeMap::new(),
+            dirty: false,
+        }
+    }
+}
+
+impl DerivedAddressCache {
+    /// Reads the cache at `path`; a missing file yields an empty cache. Since every entry
+    /// can be derived again, a cache from another version is discarded with a warning
+    /// rather than rejected.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let raw = fs::read(path).map_err(Error::io("reading address cache", path))?;
+        let cache: Self = serde_json::from_slice(&raw).map_err(|source| Error::CacheCorrupt {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        if cache.version != ADDRESS_CACHE_VERSION {
+            warn!(
+                found = cache.version,
+                supported = ADDRESS_CACHE_VERSION,
+                "address cache {} has another version; deriving every address again",
+                path.display()
+            );
+            return Ok(Self::default());
+        }
+        Ok(cache)
+    }
+
+    /// Writes the cache to `path` if anything was added since it was loaded or last saved.
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let json = serde_json::to_vec(self).map_err(|source| Error::Serialize {
+            what: format!("address cache {}", path.display()),
+            source,
+        })?;
+        write_atomic(path, &json)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str, height: u64) -> Option<&str> {
+        self.addresses
+            .get(key_id(key))?
+            .get(&height)
+            .map(String::as_str)
+    }
+
+    pub fn insert(&mut self, key: &str, height: u64, address: String) {
+        let by_height = self.addresses.entry(key_id(key).to_string()).or_default();
+        if by_height.insert(height, address).is_none() {
+            self.dirty = true;
+        }
+    }
+
+    /// Number of cached addresses across all keys.
+    pub fn len(&self) -> usize {
+        self.addresses.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+}
+
+fn key_id(key: &str) -> &str {
+    key.get(..KEY_ID_LEN).unwrap_or(key)
+}
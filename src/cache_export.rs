@@ -0,0 +1,84 @@
+//! `cache-export-csv` subcommand: the cached blocks as CSV, for spreadsheets and pandas.
+
+use crate::{
+    BlockCache, MinerStatsConfig, blocks_progress_bar, checked_zat_sum,
+    error::{Error, Result},
+    push_csv_row,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+use tracing::info;
+
+const BLOCK_COLUMNS: [&str; 4] = ["height", "hash", "num_outputs", "total_coinbase_zat"];
+/// Added after [`BLOCK_COLUMNS`] with `--expand-outputs`.
+const OUTPUT_COLUMNS: [&str; 3] = ["output_index", "value_zat", "addresses"];
+
+pub fn run(config_path: &Path, output: &Path, expand_outputs: bool) -> Result<()> {
+    let cfg = MinerStatsConfig::from_file(config_path)?;
+    let (cache, _) = cfg.cache_backend(false)?.load()?;
+    write_csv(&cache, output, expand_outputs)?;
+    info!(
+        "Exported {} cached blocks to {}.",
+        cache.blocks.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Writes one RFC 4180 row per cached block, lowest height first, after a header row. With
+/// `expand_outputs` there is one row per coinbase output instead, repeating the block
+/// columns; a block without outputs keeps a single row with the output columns empty.
+/// Addresses of one output are separated by spaces.
+pub fn write_csv(cache: &BlockCache, path: &Path, expand_outputs: bool) -> Result<()> {
+    let file = File::create(path).map_err(Error::io("creating cache CSV", path))?;
+    let mut out = BufWriter::new(file);
+    let mut row = String::new();
+    let mut header = BLOCK_COLUMNS.to_vec();
+    if expand_outputs {
+        header.extend(OUTPUT_COLUMNS);
+    }
+    push_csv_row(&mut row, &header);
+    out.write_all(row.as_bytes())
+        .map_err(Error::io("writing cache CSV", path))?;
+    row.clear();
+    let progress = blocks_progress_bar(cache.blocks.len());
+    for (height, block) in &cache.blocks {
+        let total = checked_zat_sum(block.outputs.iter().map(|o| o.value_zat), || {
+            format!("coinbase of block {height}")
+        })?;
+        let block_fields = [
+            height.to_string(),
+            block.hash.clone(),
+            block.outputs.len().to_string(),
+            total.to_string(),
+        ];
+        let block_fields = block_fields.each_ref().map(String::as_str);
+        if !expand_outputs {
+            push_csv_row(&mut row, &block_fields);
+        } else if block.outputs.is_empty() {
+            push_csv_row(&mut row, &[block_fields.as_slice(), &["", "", ""]].concat());
+        } else {
+            for (index, output) in block.outputs.iter().enumerate() {
+                let output_fields = [
+                    index.to_string(),
+                    output.value_zat.to_string(),
+                    output.addresses.join(" "),
+                ];
+                let output_fields = output_fields.each_ref().map(String::as_str);
+                push_csv_row(
+                    &mut row,
+                    &[block_fields.as_slice(), output_fields.as_slice()].concat(),
+                );
+            }
+        }
+        out.write_all(row.as_bytes())
+            .map_err(Error::io("writing cache CSV", path))?;
+        row.clear();
+        progress.inc(1);
+    }
+    progress.finish_and_clear();
+    out.flush().map_err(Error::io("writing cache CSV", path))
+}
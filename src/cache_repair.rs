@@ -0,0 +1,115 @@
+//! `cache-repair` subcommand: re-fetches cached blocks that are missing, have no coinbase
+//! outputs, or no longer match the node's chain.
+
+use crate::{
+    BlockCache, MinerStatsConfig, RpcClient, Semaphore,
+    error::{Error, Result},
+    fetch_into, node_client, with_shared,
+};
+use rayon::prelude::*;
+use std::{path::Path, sync::atomic::AtomicBool};
+use tracing::{info, warn};
+
+/// What [`repair_cache`] re-fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepairSummary {
+    /// Heights of the configured range that were not cached at all.
+    pub missing_blocks: usize,
+    /// Cached blocks without any coinbase output, typically left by a failed fetch.
+    pub empty_blocks: usize,
+    /// Cached blocks whose hash differs from the node's block at that height.
+    pub mismatched_blocks: usize,
+}
+
+impl RepairSummary {
+    pub fn repaired_blocks(&self) -> usize {
+        self.missing_blocks + self.empty_blocks + self.mismatched_blocks
+    }
+}
+
+pub fn run(config_path: &Path) -> Result<()> {
+    let cfg = MinerStatsConfig::from_file(config_path)?;
+    if cfg.cache_readonly {
+        return Err(Error::config(
+            "cache_readonly",
+            "cache-repair rewrites the cache; unset cache_readonly to run it",
+        ));
+    }
+    let client = node_client(&cfg, None)?;
+    let backend = cfg.cache_backend(false)?;
+    let (mut cache, migrated) = backend.load()?;
+    let summary = repair_cache(&client, &mut cache, cfg.start_height, cfg.rpc_batch_size)?;
+    if migrated || summary.repaired_blocks() > 0 {
+        backend.save(&cache)?;
+    }
+    info!(
+        "Repaired {} blocks: {} missing, {} without outputs, {} with a stale hash.",
+        summary.repaired_blocks(),
+        summary.missing_blocks,
+        summary.empty_blocks,
+        summary.mismatched_blocks
+    );
+    Ok(())
+}
+
+/// Checks every height from `start_height` to the client's tip and re-fetches the blocks
+/// that are missing from `cache`, have no outputs, or whose hash the client no longer
+/// reports. Unlike the re-org check of [`sync_cache`](crate::sync_cache), every cached
+/// hash in the range is verified, not only the most recent ones.
+pub fn repair_cache<R: RpcClient>(
+    client: &R,
+    cache: &mut BlockCache,
+    start_height: u64,
+    batch_size: usize,
+) -> Result<RepairSummary> {
+    let tip_height = client.block_count()?;
+    let mut missing = Vec::new();
+    let mut empty = Vec::new();
+    let mut cached = Vec::new();
+    for height in start_height..=tip_height {
+        match cache.blocks.get(&height) {
+            None => missing.push(height),
+            Some(block) if block.outputs.is_empty() => empty.push(height),
+            Some(_) => cached.push(height),
+        }
+    }
+
+    let slots = Semaphore::new(client.max_concurrent());
+    let mismatched: Vec<u64> = cached
+        .par_chunks(batch_size.max(1))
+        .map(|chunk| slots.run(|| client.verify_block_hashes(chunk, cache)))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    if !mismatched.is_empty() {
+        warn!(
+            blocks = mismatched.len(),
+            "cached block hashes do not match the node; re-fetching"
+        );
+    }
+
+    let mut refetch: Vec<u64> = missing
+        .iter()
+        .chain(&empty)
+        .chain(&mismatched)
+        .copied()
+        .collect();
+    refetch.sort_unstable();
+    with_shared(cache, |cache| {
+        fetch_into(
+            client,
+            cache,
+            &refetch,
+            batch_size,
+            usize::MAX,
+            &AtomicBool::new(false),
+            &mut |_| Ok(()),
+        )
+    })?;
+    Ok(RepairSummary {
+        missing_blocks: missing.len(),
+        empty_blocks: empty.len(),
+        mismatched_blocks: mismatched.len(),
+    })
+}
@@ -0,0 +1,106 @@
+//! `cache-stats` subcommand: read-only summary of the configured block cache.
+
+use crate::{
+    BlockCache, MinerStatsConfig,
+    error::{Error, Result},
+};
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct CacheStats {
+    blocks: usize,
+    /// Lowest and highest cached height.
+    height_range: Option<[u64; 2]>,
+    /// Inclusive ranges of heights missing between the lowest and highest cached height.
+    gaps: Vec<[u64; 2]>,
+    /// Size of the cache serialized as (pretty-printed, uncompressed) JSON.
+    json_bytes: usize,
+    blocks_without_outputs: usize,
+    last_tip: Option<u64>,
+}
+
+pub fn run(config_path: &Path, json: bool) -> Result<()> {
+    let cfg = MinerStatsConfig::from_file(config_path)?;
+    let (cache, _) = cfg.cache_backend(false)?.load()?;
+    let stats = collect(&cache)?;
+    if json {
+        let out = serde_json::to_string_pretty(&stats).map_err(|source| Error::Serialize {
+            what: "cache stats".to_string(),
+            source,
+        })?;
+        println!("{out}");
+    } else if tracing::enabled!(tracing::Level::INFO) {
+        print_stats(&stats);
+    }
+    Ok(())
+}
+
+fn collect(cache: &BlockCache) -> Result<CacheStats> {
+    let json_bytes = serde_json::to_vec_pretty(cache)
+        .map_err(|source| Error::Serialize {
+            what: "cache".to_string(),
+            source,
+        })?
+        .len();
+    let gaps = match (cache.blocks.keys().next(), cache.blocks.keys().next_back()) {
+        (Some(first), Some(last)) => cache
+            .coverage_gaps(*first, *last)
+            .into_iter()
+            .map(|gap| [*gap.start(), *gap.end()])
+            .collect(),
+        _ => Vec::new(),
+    };
+    Ok(CacheStats {
+        blocks: cache.blocks.len(),
+        height_range: cache
+            .blocks
+            .keys()
+            .next()
+            .zip(cache.blocks.keys().next_back())
+            .map(|(first, last)| [*first, *last]),
+        gaps,
+        json_bytes,
+        blocks_without_outputs: cache
+            .blocks
+            .values()
+            .filter(|block| block.outputs.is_empty())
+            .count(),
+        last_tip: cache.last_tip,
+    })
+}
+
+fn print_stats(stats: &CacheStats) {
+    let range = stats
+        .height_range
+        .map(|[first, last]| format!("{first}-{last}"))
+        .unwrap_or_else(|| "-".to_string());
+    let last_tip = stats
+        .last_tip
+        .map(|tip| tip.to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let missing: u64 = stats.gaps.iter().map(|[from, to]| to - from + 1).sum();
+    println!("\nBlock cache:");
+    println!("+--------------------------+----------------------+");
+    for (name, value) in [
+        ("Cached blocks", stats.blocks.to_string()),
+        ("Height range", range),
+        ("Gaps", format!("{} ({missing} heights)", stats.gaps.len())),
+        ("JSON size (bytes)", stats.json_bytes.to_string()),
+        (
+            "Blocks without outputs",
+            stats.blocks_without_outputs.to_string(),
+        ),
+        ("Last tip", last_tip),
+    ] {
+        println!("| {name:<24} | {value:>20} |");
+    }
+    println!("+--------------------------+----------------------+");
+    for [from, to] in &stats.gaps {
+        if from == to {
+            println!("  missing {from}");
+        } else {
+            println!("  missing {from}-{to}");
+        }
+    }
+}
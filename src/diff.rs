@@ -0,0 +1,132 @@
+//! `diff` subcommand: per-miner comparison of two JSON reports.
+
+use crate::{
+    MinerStatsReport,
+    error::{Error, Result},
+};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Serialize)]
+struct ReportDiff {
+    old_range: [u64; 2],
+    new_range: [u64; 2],
+    miners: Vec<MinerDiff>,
+}
+
+#[derive(Serialize)]
+struct MinerDiff {
+    label: String,
+    status: MinerDiffStatus,
+    matched_blocks_delta: i64,
+    total_value_wec_delta: Decimal,
+    share_percent_delta: f64,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum MinerDiffStatus {
+    /// Present in both reports.
+    Common,
+    /// Only present in the new report.
+    Gained,
+    /// Only present in the old report.
+    Lost,
+}
+
+impl MinerDiffStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Common => "",
+            Self::Gained => "gained",
+            Self::Lost => "lost",
+        }
+    }
+}
+
+pub fn run(old_path: &Path, new_path: &Path, json: bool) -> Result<()> {
+    let old = MinerStatsReport::read(old_path)?;
+    let new = MinerStatsReport::read(new_path)?;
+    let diff = compare(&old, &new);
+    if json {
+        let out = serde_json::to_string_pretty(&diff).map_err(|source| Error::Serialize {
+            what: "report diff".to_string(),
+            source,
+        })?;
+        println!("{out}");
+    } else if tracing::enabled!(tracing::Level::INFO) {
+        print_diff(&diff);
+    }
+    Ok(())
+}
+
+/// Matches miners by label. Miners keep the old report's order, followed by miners that
+/// only appear in the new report.
+fn compare(old: &MinerStatsReport, new: &MinerStatsReport) -> ReportDiff {
+    let mut miners = Vec::new();
+    for before in &old.miners {
+        let after = new.miners.iter().find(|m| m.label == before.label);
+        miners.push(match after {
+            Some(after) => MinerDiff {
+                label: before.label.clone(),
+                status: MinerDiffStatus::Common,
+                matched_blocks_delta: i64::from(after.matched_blocks)
+                    - i64::from(before.matched_blocks),
+                total_value_wec_delta: after.total_value_wec - before.total_value_wec,
+                share_percent_delta: round2(after.share_percent - before.share_percent),
+            },
+            None => MinerDiff {
+                label: before.label.clone(),
+                status: MinerDiffStatus::Lost,
+                matched_blocks_delta: -i64::from(before.matched_blocks),
+                total_value_wec_delta: -before.total_value_wec,
+                share_percent_delta: round2(-before.share_percent),
+            },
+        });
+    }
+    for after in &new.miners {
+        if old.miners.iter().all(|m| m.label != after.label) {
+            miners.push(MinerDiff {
+                label: after.label.clone(),
+                status: MinerDiffStatus::Gained,
+                matched_blocks_delta: i64::from(after.matched_blocks),
+                total_value_wec_delta: after.total_value_wec,
+                share_percent_delta: round2(after.share_percent),
+            });
+        }
+    }
+    ReportDiff {
+        old_range: [old.start_height, old.end_height],
+        new_range: [new.start_height, new.end_height],
+        miners,
+    }
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+fn print_diff(diff: &ReportDiff) {
+    println!(
+        "\nReport diff: heights {}-{} -> {}-{}",
+        diff.old_range[0], diff.old_range[1], diff.new_range[0], diff.new_range[1]
+    );
+    println!("+----------------------+------------+------------+------------+----------+");
+    println!(
+        "| {:<20} | {:>10} | {:>10} | {:>10} | {:<8} |",
+        "Label", "Blocks Δ", "WEC Δ", "Share Δ", "Status"
+    );
+    println!("+----------------------+------------+------------+------------+----------+");
+    for miner in &diff.miners {
+        println!(
+            "| {:<20} | {:>+10} | {:>+10.2} | {:>+9.2}% | {:<8} |",
+            miner.label,
+            miner.matched_blocks_delta,
+            miner.total_value_wec_delta,
+            miner.share_percent_delta,
+            miner.status.as_str()
+        );
+    }
+    println!("+----------------------+------------+------------+------------+----------+");
+}
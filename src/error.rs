@@ -0,0 +1,267 @@
+//! Error type returned by every fallible operation in the crate.
+//!
+//! The library itself does not depend on `anyhow`; it is behind the default `anyhow`
+//! feature, which the binary requires. [`Error`] implements [`std::error::Error`], so with
+//! `anyhow` in the build `?` converts it into an `anyhow::Error` with the full source chain
+//! preserved.
+
+use std::{
+    error::Error as StdError,
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A filesystem operation on `path` failed; `operation` reads like "reading cache".
+    Io {
+        operation: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The config file is not valid TOML or does not match the expected schema.
+    ConfigParse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// A config value parsed but is not usable.
+    ConfigInvalid { field: String, reason: String },
+    /// `address_map_file` is not a JSON object of address to label strings.
+    AddressMapParse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// The block cache exists but does not contain valid cache JSON.
+    CacheCorrupt {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// The block cache exists but does not contain a valid MessagePack cache.
+    CacheCorruptMsgpack {
+        path: PathBuf,
+        source: rmp_serde::decode::Error,
+    },
+    /// Encoding the block cache at `path` as MessagePack failed.
+    CacheEncode {
+        path: PathBuf,
+        source: rmp_serde::encode::Error,
+    },
+    /// `cache-shard` read back shards that lack the block at `height` of the cache at `path`.
+    CacheShardMismatch { path: PathBuf, height: u64 },
+    /// The block cache was written by a newer version with a schema this build cannot read.
+    CacheVersionUnsupported {
+        path: PathBuf,
+        found: u32,
+        supported: u32,
+    },
+    /// The SQLite block cache could not be opened, read or written.
+    #[cfg(feature = "sqlite")]
+    Sqlite {
+        path: PathBuf,
+        source: rusqlite::Error,
+    },
+    /// A report passed to `diff` or `merge` is not valid report JSON.
+    ReportCorrupt {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// Serializing `what` (e.g. "report out.json") to JSON failed.
+    Serialize {
+        what: String,
+        source: serde_json::Error,
+    },
+    /// The configured CA certificate is not valid PEM.
+    TlsCertificate {
+        path: PathBuf,
+        source: reqwest::Error,
+    },
+    /// The HTTP client could not be constructed.
+    HttpClient(reqwest::Error),
+    /// The request could not be sent or the response body could not be decoded.
+    RpcTransport {
+        method: String,
+        source: reqwest::Error,
+    },
+    /// The node answered with a non-success HTTP status.
+    RpcHttpStatus {
+        method: String,
+        status: reqwest::StatusCode,
+    },
+    /// The node answered with a JSON-RPC error object.
+    RpcError {
+        method: String,
+        code: i64,
+        message: String,
+    },
+    /// The node answered with a well-formed but unusable JSON-RPC response.
+    RpcProtocol { method: String, reason: String },
+    /// Every retry of a transient failure was used up; `source` is the last failure.
+    RpcRetriesExhausted {
+        method: String,
+        attempts: u32,
+        source: Box<Error>,
+    },
+    /// A UFVK from the config could not be decoded; `key` is shortened for display.
+    KeyDecode {
+        key: String,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// Deriving the transparent payout address for `height` failed.
+    AddressDerivation {
+        height: u64,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// The metrics HTTP server could not bind its address.
+    MetricsBind {
+        addr: String,
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// Two reports passed to `merge` cover overlapping height ranges.
+    ReportsOverlap { first: [u64; 2], second: [u64; 2] },
+    /// `merge` was called without any reports.
+    NoReports,
+    /// Summing zat amounts for `what` left the `i64` range.
+    ValueOverflow { what: String },
+    /// The interrupt flag was set while fetching blocks; the blocks fetched so far are kept.
+    Interrupted,
+    /// The `--tui` report viewer could not set up, draw on or read from the terminal.
+    Terminal(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io {
+                operation, path, ..
+            } => write!(f, "{operation} {}", path.display()),
+            Self::ConfigParse { path, .. } => write!(f, "parsing config file {}", path.display()),
+            Self::ConfigInvalid { field, reason } => write!(f, "invalid {field}: {reason}"),
+            Self::AddressMapParse { path, .. } => {
+                write!(f, "parsing address map {}", path.display())
+            }
+            Self::CacheCorrupt { path, .. } | Self::CacheCorruptMsgpack { path, .. } => {
+                write!(f, "parsing cache {}", path.display())
+            }
+            Self::CacheEncode { path, .. } => write!(f, "serializing cache {}", path.display()),
+            Self::CacheShardMismatch { path, height } => write!(
+                f,
+                "the shards written from cache {} do not hold its block {height}; the cache \
+                 was left as is",
+                path.display()
+            ),
+            Self::CacheVersionUnsupported {
+                path,
+                found,
+                supported,
+            } => write!(
+                f,
+                "cache {} has schema version {found}, but this build reads at most version \
+                 {supported}; upgrade uview-miner-stats",
+                path.display()
+            ),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite { path, .. } => write!(f, "accessing SQLite cache {}", path.display()),
+            Self::ReportCorrupt { path, .. } => write!(f, "parsing report {}", path.display()),
+            Self::Serialize { what, .. } => write!(f, "serializing {what}"),
+            Self::TlsCertificate { path, .. } => {
+                write!(f, "parsing RPC CA certificate {}", path.display())
+            }
+            Self::HttpClient(_) => f.write_str("constructing RPC client"),
+            Self::RpcTransport { method, .. } => write!(f, "calling RPC method {method}"),
+            Self::RpcHttpStatus { method, status } => {
+                write!(f, "RPC {method} failed: HTTP {status}")
+            }
+            Self::RpcError {
+                method,
+                code,
+                message,
+            } => write!(f, "RPC {method} error {code}: {message}"),
+            Self::RpcProtocol { method, reason } => write!(f, "RPC {method} {reason}"),
+            Self::RpcRetriesExhausted {
+                method, attempts, ..
+            } => write!(f, "RPC {method} failed after {attempts} attempts"),
+            Self::KeyDecode { key, .. } => write!(f, "decoding UFVK {key}"),
+            Self::AddressDerivation { height, .. } => {
+                write!(f, "deriving address for height {height}")
+            }
+            Self::MetricsBind { addr, .. } => write!(f, "binding metrics server on {addr}"),
+            Self::ReportsOverlap { first, second } => write!(
+                f,
+                "report ranges overlap: {}-{} and {}-{}",
+                first[0], first[1], second[0], second[1]
+            ),
+            Self::NoReports => f.write_str("no reports to merge"),
+            Self::ValueOverflow { what } => write!(f, "{what} overflows the i64 zat range"),
+            Self::Interrupted => f.write_str("interrupted while fetching blocks"),
+            Self::Terminal(_) => f.write_str("running the terminal report viewer"),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Io { source, .. } | Self::Terminal(source) => Some(source),
+            Self::ConfigParse { source, .. } => Some(source),
+            Self::CacheCorruptMsgpack { source, .. } => Some(source),
+            Self::CacheEncode { source, .. } => Some(source),
+            Self::CacheCorrupt { source, .. }
+            | Self::AddressMapParse { source, .. }
+            | Self::ReportCorrupt { source, .. }
+            | Self::Serialize { source, .. } => Some(source),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite { source, .. } => Some(source),
+            Self::TlsCertificate { source, .. }
+            | Self::HttpClient(source)
+            | Self::RpcTransport { source, .. } => Some(source),
+            Self::RpcRetriesExhausted { source, .. } => Some(source.as_ref()),
+            Self::KeyDecode { source, .. } | Self::AddressDerivation { source, .. } => {
+                Some(source.as_ref())
+            }
+            Self::MetricsBind { source, .. } => Some(source.as_ref()),
+            Self::ConfigInvalid { .. }
+            | Self::CacheShardMismatch { .. }
+            | Self::CacheVersionUnsupported { .. }
+            | Self::RpcHttpStatus { .. }
+            | Self::RpcError { .. }
+            | Self::RpcProtocol { .. }
+            | Self::ReportsOverlap { .. }
+            | Self::NoReports
+            | Self::ValueOverflow { .. }
+            | Self::Interrupted => None,
+        }
+    }
+}
+
+impl Error {
+    /// `map_err` adapter for [`Error::Io`].
+    pub(crate) fn io(operation: &'static str, path: &Path) -> impl FnOnce(io::Error) -> Self {
+        let path = path.to_path_buf();
+        move |source| Self::Io {
+            operation,
+            path,
+            source,
+        }
+    }
+
+    pub(crate) fn config(field: &str, reason: impl Into<String>) -> Self {
+        Self::ConfigInvalid {
+            field: field.to_string(),
+            reason: reason.into(),
+        }
+    }
+
+    /// Renders the error and its sources on one line, for log messages.
+    pub(crate) fn chain(&self) -> String {
+        let mut out = self.to_string();
+        let mut source = self.source();
+        while let Some(err) = source {
+            out.push_str(": ");
+            out.push_str(&err.to_string());
+            source = err.source();
+        }
+        out
+    }
+}
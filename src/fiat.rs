@@ -0,0 +1,54 @@
+//! `fiat_currency`: the WEC exchange rate fetched once at startup from `price_api_url`, so
+//! the report can show miner totals in e.g. USD next to WEC.
+
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Default `price_api_url`: CoinGecko's simple price endpoint. `{currency}` is replaced with
+/// the lowercased `fiat_currency`.
+pub const DEFAULT_PRICE_API_URL: &str =
+    "https://api.coingecko.com/api/v3/simple/price?ids=w-cash&vs_currencies={currency}";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Price of one WEC in `currency`, or `None` with a warning if it could not be fetched: a
+/// price service outage only costs the fiat figures, never the run.
+pub(crate) fn fetch_rate(url_template: &str, currency: &str) -> Option<f64> {
+    let url = url_template.replace("{currency}", &currency.to_lowercase());
+    match request_rate(&url, currency) {
+        Ok(rate) => {
+            debug!(currency, rate, "fetched exchange rate");
+            Some(rate)
+        }
+        Err(reason) => {
+            warn!("fetching the WEC/{currency} rate from {url} failed: {reason}");
+            None
+        }
+    }
+}
+
+/// Expects CoinGecko's response shape, `{"<coin id>": {"<currency>": <price>}}`.
+fn request_rate(url: &str, currency: &str) -> Result<f64, String> {
+    let client = reqwest::blocking::Client::builder()
+        .timeout(TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let body: Value = client
+        .get(url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json())
+        .map_err(|e| e.to_string())?;
+    let currency = currency.to_lowercase();
+    body.as_object()
+        .into_iter()
+        .flat_map(|coins| coins.values())
+        .find_map(|prices| prices.get(&currency)?.as_f64())
+        .ok_or_else(|| format!("no {currency} price in the response"))
+}
+
+/// `zats` in the fiat currency at `rate`, rounded to cents.
+pub(crate) fn value(zats: i64, rate: f64) -> f64 {
+    (zats as f64 / 1e8 * rate * 100.0).round() / 100.0
+}
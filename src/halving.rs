@@ -0,0 +1,98 @@
+//! Block subsidy halving schedule (ZIP 208), used to split reports into halving epochs and
+//! to tell a block's subsidy from its fees.
+
+use zcash_primitives::consensus::{NetworkType, NetworkUpgrade, Parameters};
+use zingolib::config::ChainType;
+
+/// Subsidy in zats of a pre-Blossom block before the first halving.
+const MAX_BLOCK_SUBSIDY: i64 = 1_250_000_000;
+
+/// Coinbase subsidy in zats at `height` on `chain`: the slow start, then halvings, with
+/// post-Blossom blocks paying half as much. Includes the funding stream share and excludes
+/// fees.
+pub fn block_subsidy(height: u64, chain: &ChainType) -> i64 {
+    HalvingSchedule::for_chain(chain).subsidy(height)
+}
+
+/// Halving parameters for one network. Heights before the first halving are epoch 0.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HalvingSchedule {
+    slow_start_shift: u64,
+    blossom_height: u64,
+    pre_blossom_interval: u64,
+    post_blossom_interval: u64,
+}
+
+impl HalvingSchedule {
+    pub(crate) fn for_chain(chain: &ChainType) -> Self {
+        let (slow_start_shift, pre_blossom_interval, post_blossom_interval) =
+            match chain.network_type() {
+                NetworkType::Main | NetworkType::Test => (10_000, 840_000, 1_680_000),
+                NetworkType::Regtest => (0, 144, 288),
+            };
+        let blossom_height = chain
+            .activation_height(NetworkUpgrade::Blossom)
+            .map(u64::from)
+            .unwrap_or(u64::MAX);
+        Self {
+            slow_start_shift,
+            blossom_height,
+            pre_blossom_interval,
+            post_blossom_interval,
+        }
+    }
+
+    /// Number of halvings that have happened at `height`.
+    pub(crate) fn epoch(&self, height: u64) -> u32 {
+        let epoch = if height < self.slow_start_shift {
+            0
+        } else if height < self.blossom_height {
+            (height - self.slow_start_shift) / self.pre_blossom_interval
+        } else {
+            (self.pre_blossom_span() + (height - self.blossom_height)) / self.post_blossom_interval
+        };
+        epoch as u32
+    }
+
+    /// First height of `epoch`.
+    pub(crate) fn epoch_start(&self, epoch: u32) -> u64 {
+        if epoch == 0 {
+            return 0;
+        }
+        let pre_blossom = self.slow_start_shift + u64::from(epoch) * self.pre_blossom_interval;
+        if pre_blossom < self.blossom_height {
+            return pre_blossom;
+        }
+        self.blossom_height + u64::from(epoch) * self.post_blossom_interval
+            - self.pre_blossom_span()
+    }
+
+    fn subsidy(&self, height: u64) -> i64 {
+        // The slow start ramps the subsidy up linearly over twice `slow_start_shift` blocks.
+        let slow_start_interval = 2 * self.slow_start_shift;
+        if height < slow_start_interval {
+            let rate = MAX_BLOCK_SUBSIDY / slow_start_interval as i64;
+            let steps = if height < self.slow_start_shift {
+                height
+            } else {
+                height + 1
+            };
+            return rate * steps as i64;
+        }
+        let halvings = self.epoch(height);
+        if halvings >= 64 {
+            return 0;
+        }
+        if height < self.blossom_height {
+            MAX_BLOCK_SUBSIDY >> halvings
+        } else {
+            (MAX_BLOCK_SUBSIDY / 2) >> halvings
+        }
+    }
+
+    /// Pre-Blossom part of the halving formula expressed in post-Blossom blocks.
+    fn pre_blossom_span(&self) -> u64 {
+        (self.blossom_height - self.slow_start_shift) * self.post_blossom_interval
+            / self.pre_blossom_interval
+    }
+}
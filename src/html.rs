@@ -0,0 +1,146 @@
+//! Self-contained HTML rendering of a [`MinerStatsReport`](crate::MinerStatsReport).
+//!
+//! The page uses inline CSS only and native `<details>` elements for the per-miner block
+//! lists, so it renders without JavaScript or network access.
+
+use crate::MinerStatsReport;
+use std::fmt::Write;
+
+const STYLE: &str = "\
+body { font-family: system-ui, sans-serif; margin: 2rem; color: #222; }
+table { border-collapse: collapse; margin-bottom: 1.5rem; }
+th, td { border: 1px solid #ccc; padding: 0.3rem 0.6rem; }
+th { background: #f0f0f0; text-align: left; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+tr.others td { font-style: italic; }
+details { margin-bottom: 1rem; }
+summary h2 { display: inline; font-size: 1.1rem; }
+code { font-size: 0.85rem; }
+";
+
+pub(crate) fn render(report: &MinerStatsReport) -> String {
+    let mut out = String::new();
+    let title = format!(
+        "Miner stats for heights {}-{}",
+        report.start_height, report.end_height
+    );
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{title}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n\
+         <h1>{title}</h1>\n<p>Total {:.2} WEC across {} matched blocks.</p>\n",
+        report.total_value_wec, report.total_mined_blocks
+    );
+
+    out.push_str(
+        "<table>\n<thead><tr><th>Label</th><th>Blocks</th><th>ZAT</th><th>WEC</th>\
+         <th>% Share</th></tr></thead>\n<tbody>\n",
+    );
+    for miner in &report.miners {
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+             <td class=\"num\">{:.2}</td><td class=\"num\">{:.2}%</td></tr>",
+            escape(&miner.label),
+            miner.matched_blocks,
+            miner.total_value_zat,
+            miner.total_value_wec,
+            miner.share_percent
+        );
+    }
+    let _ = writeln!(
+        out,
+        "<tr class=\"others\"><td>Others</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+         <td class=\"num\">{:.2}</td><td class=\"num\">{:.2}%</td></tr>",
+        report.unmatched.blocks,
+        report.unmatched.total_value_zat,
+        report.unmatched.total_value_wec,
+        report.unmatched.share_percent
+    );
+    out.push_str("</tbody>\n</table>\n");
+
+    if !report.top_blocks.is_empty() {
+        out.push_str(
+            "<h2>Top blocks by value</h2>\n<table>\n<thead><tr><th>Height</th><th>Hash</th>\
+             <th>Coinbase ZAT</th><th>Miner</th></tr></thead>\n<tbody>\n",
+        );
+        for block in &report.top_blocks {
+            let _ = writeln!(
+                out,
+                "<tr><td class=\"num\">{}</td><td><code>{}</code></td>\
+                 <td class=\"num\">{}</td><td>{}</td></tr>",
+                block.height,
+                escape(&block.hash),
+                block.total_coinbase_zat,
+                escape(block.matched_miner.as_deref().unwrap_or("Others"))
+            );
+        }
+        out.push_str("</tbody>\n</table>\n");
+    }
+
+    // Only reports of a config with `[annotations]` matching a block get the column.
+    let annotated = report
+        .detailed_miners
+        .iter()
+        .flat_map(|miner| &miner.detailed_blocks)
+        .any(|detail| detail.annotation.is_some());
+    for miner in &report.detailed_miners {
+        let _ = writeln!(
+            out,
+            "<details>\n<summary><h2>{}</h2> ({} blocks)</summary>",
+            escape(&miner.label),
+            miner.matched_blocks
+        );
+        if miner.detailed_blocks.is_empty() {
+            out.push_str("<p>No matched blocks.</p>\n</details>\n");
+            continue;
+        }
+        out.push_str(
+            "<table>\n<thead><tr><th>Height</th><th>Hash</th><th>Time (Unix)</th>\
+             <th>Payout address</th>",
+        );
+        if annotated {
+            out.push_str("<th>Annotation</th>");
+        }
+        out.push_str("</tr></thead>\n<tbody>\n");
+        for detail in &miner.detailed_blocks {
+            let hash = format!("<code>{}</code>", escape(&detail.block_hash));
+            let hash = match &detail.explorer_url {
+                Some(url) => format!("<a href=\"{}\">{hash}</a>", escape(url)),
+                None => hash,
+            };
+            let _ = write!(
+                out,
+                "<tr><td class=\"num\">{}</td><td>{hash}</td><td class=\"num\">{}</td>\
+                 <td><code>{}</code></td>",
+                detail.block_height,
+                detail.block_time,
+                escape(&detail.payout_address)
+            );
+            if annotated {
+                let annotation = detail.annotation.as_deref().unwrap_or_default();
+                let _ = write!(out, "<td>{}</td>", escape(annotation));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</tbody>\n</table>\n</details>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
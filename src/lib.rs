@@ -0,0 +1,5226 @@
+//! Core of `uview-miner-stats`: configuration, the block cache, the node RPC client and the
+//! statistics that attribute coinbase outputs to miners by unified full viewing key.
+//!
+//! The entry points are [`MinerStatsConfig::from_file`] (or [`MinerStatsConfig::builder`]),
+//! [`BlockCache`], [`NodeRpcClient`], [`compute_statistics`] and [`MinerStatsReport`];
+//! [`run`] ties them together exactly like the `uview-miner-stats` binary does.
+//!
+//! # Stability
+//!
+//! The library follows semantic versioning. Within a `0.x` series, breaking changes only
+//! happen on a minor version bump (`0.1` -> `0.2`); patch releases only add items, fields
+//! marked `#[serde(default)]`, or fixes. The JSON layout of [`MinerStatsReport`] and of the
+//! block cache is part of that guarantee: fields are added but never renamed or removed
+//! without a minor bump, and newer versions keep reading files written by older ones.
+//! Items not reachable from the crate root, or hidden from the docs, are internal and may
+//! change at any time.
+
+#![forbid(unsafe_code)]
+
+mod address_cache;
+pub mod cache_export;
+pub mod cache_repair;
+pub mod cache_stats;
+pub mod diff;
+pub mod error;
+mod fiat;
+mod halving;
+mod html;
+pub mod list_miners;
+pub mod merge;
+pub mod metrics;
+pub mod sharded_cache;
+mod shielded;
+#[cfg(feature = "sqlite")]
+mod sqlite_cache;
+mod systemd;
+pub mod telemetry;
+#[cfg(feature = "tui")]
+mod tui;
+pub mod validate_config;
+mod webhook;
+
+pub use address_cache::{ADDRESS_CACHE_VERSION, DerivedAddressCache};
+use bloomfilter::Bloom;
+use chrono::Datelike;
+pub use chrono_tz::Tz;
+use clap::ValueEnum;
+pub use error::{Error, Result};
+pub use fiat::DEFAULT_PRICE_API_URL;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use halving::HalvingSchedule;
+pub use halving::block_subsidy;
+use indicatif::{ProgressBar, ProgressStyle};
+use metrics::Metrics;
+use owo_colors::{OwoColorize, Style};
+use pepper_sync::keys::transparent::{self, TransparentScope};
+use rayon::prelude::*;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{self, Unexpected, Visitor},
+};
+pub use sharded_cache::ShardedFileCache;
+#[cfg(feature = "sqlite")]
+pub use sqlite_cache::SqliteCache;
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    io::{IsTerminal, Read, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Condvar, Mutex, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tracing::{Level, Span, debug, info, info_span, instrument, warn};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use webhook::Webhook;
+use zcash_primitives::legacy::keys::NonHardenedChildIndex;
+pub use zingolib::config::ChainType;
+use zingolib::config::chain_from_str;
+use zingolib::wallet::keys::unified::UnifiedKeyStore;
+
+/// File format of the main report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+    Html,
+}
+
+/// Order of the miners in the report and the console table. The `Others` row always comes
+/// last.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum MinerSortOrder {
+    /// Order of the `ufvks` entries in the config file.
+    #[default]
+    Config,
+    BlocksDesc,
+    BlocksAsc,
+    ValueDesc,
+    ValueAsc,
+    ShareDesc,
+    ShareAsc,
+    LabelAsc,
+}
+
+impl MinerSortOrder {
+    /// Compares `(matched_blocks, total_value_zat, share_percent, label)` tuples; ties (and
+    /// `Config`) keep the existing order because callers sort stably.
+    fn compare(self, a: (u32, i64, f64, &str), b: (u32, i64, f64, &str)) -> std::cmp::Ordering {
+        match self {
+            Self::Config => std::cmp::Ordering::Equal,
+            Self::BlocksDesc => b.0.cmp(&a.0),
+            Self::BlocksAsc => a.0.cmp(&b.0),
+            Self::ValueDesc => b.1.cmp(&a.1),
+            Self::ValueAsc => a.1.cmp(&b.1),
+            Self::ShareDesc => b.2.total_cmp(&a.2),
+            Self::ShareAsc => a.2.total_cmp(&b.2),
+            Self::LabelAsc => a.3.cmp(b.3),
+        }
+    }
+}
+
+/// Outputs requested on top of the config file, normally taken from the command line.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    pub output_format: OutputFormat,
+    /// Also write one CSV row per matched block here.
+    pub detail_output: Option<PathBuf>,
+    /// Also write one JSON Lines record per matched block here.
+    pub stream_output: Option<PathBuf>,
+    /// Also append the miner totals and matched blocks here as InfluxDB line protocol.
+    pub influx_output: Option<PathBuf>,
+    /// Also write the matched blocks here as an Elasticsearch bulk request.
+    pub es_output: Option<PathBuf>,
+    /// Index the `es_output` actions target.
+    pub es_index: String,
+    /// Gzip the report; `compress_output` in the config enables it as well.
+    pub compress_output: bool,
+    /// Write the report uncompressed even if the config sets `compress_output`.
+    pub decompress_output: bool,
+    /// Leave `detailed_miners` out of a JSON report; `summary_only` in the config enables it
+    /// as well.
+    pub summary_only: bool,
+    /// Neither read nor write the block cache, whatever `cache_backend` says.
+    pub no_cache: bool,
+    /// Discard every cached block and fetch the whole range again, overwriting the cache.
+    pub force_refetch: bool,
+    /// Compute and print the statistics but write no files: neither the cache nor any
+    /// report.
+    pub dry_run: bool,
+    /// Highlight the console table with ANSI colors.
+    pub color: bool,
+    /// Order of `miners` and `detailed_miners` in the report and of the table rows.
+    pub sort_by: MinerSortOrder,
+    /// Drop cached blocks below `start_height`; `prune_cache` in the config enables it as
+    /// well.
+    pub prune_cache: bool,
+    /// Display width of the table's label column; longer labels are truncated with `…`.
+    pub label_width: usize,
+    /// Add a column with each miner's share of the total value to the table.
+    pub show_value_share: bool,
+    /// Leave `outlier_blocks` out of the report.
+    pub no_outliers: bool,
+    /// Add each miner's first and last matched height and `inactive_since` to the table.
+    pub show_activity: bool,
+    /// Add each miner's mature and immature value to the table.
+    pub show_maturity: bool,
+    /// Print the table without its `+---+` border and separator lines, for `awk` or `cut`.
+    pub table_no_border: bool,
+    /// Show amounts in the table and the JSON report through [`format_value`] with a unit,
+    /// instead of as WEC with two decimals.
+    pub human_values: bool,
+    /// Timezone of the `*_datetime` fields of the report.
+    pub timezone: Tz,
+    /// Browse the report in an interactive terminal viewer instead of printing the table,
+    /// when stdout is a terminal (`tui` feature).
+    pub tui: bool,
+    /// After the first report, poll the node this often and update the cache, the reports
+    /// and the table whenever new blocks arrive, until `interrupt` is set.
+    pub watch: Option<Duration>,
+    /// Set (e.g. by a Ctrl-C handler) to stop fetching early: [`run`] then saves the blocks
+    /// fetched so far and returns [`Error::Interrupted`].
+    pub interrupt: Arc<AtomicBool>,
+}
+
+/// Default [`RunOptions::label_width`].
+pub const DEFAULT_LABEL_WIDTH: usize = 20;
+
+/// Default [`RunOptions::es_index`].
+pub const DEFAULT_ES_INDEX: &str = "miner-stats";
+
+/// Loads the config at `config_path`, brings the block cache up to the node's tip, and
+/// writes every configured report.
+pub fn run(config_path: &Path, options: &RunOptions) -> Result<()> {
+    let cfg = MinerStatsConfig::from_file(config_path)?;
+    let metrics = Arc::new(match &cfg.statsd_addr {
+        Some(addr) => Metrics::with_statsd(addr, &cfg.statsd_prefix)?,
+        None => Metrics::default(),
+    });
+    if let Some(addr) = &cfg.metrics_addr {
+        metrics::serve(addr, metrics.clone())?;
+    }
+    let client = node_client(&cfg, Some(metrics.clone()))?;
+
+    let cache = cfg.cache_backend(options.no_cache)?;
+    run_with_client(&cfg, &client, cache.as_ref(), &metrics, options)
+}
+
+/// The [`NodeRpcClient`] described by the RPC settings of `cfg`.
+fn node_client(cfg: &MinerStatsConfig, metrics: Option<Arc<Metrics>>) -> Result<NodeRpcClient> {
+    NodeRpcClient::new(
+        cfg.rpc_urls.clone(),
+        RpcClientOptions {
+            auth: cfg.rpc_auth.clone(),
+            tls: cfg.rpc_tls.clone(),
+            retry: cfg.rpc_retry,
+            timeout: cfg.rpc_timeout,
+            fetch_timeout: cfg.rpc_fetch_timeout,
+            max_concurrent: cfg.rpc_max_concurrent,
+            metrics,
+            chain: cfg.chain.clone(),
+            node_type: cfg.node_type,
+        },
+    )
+}
+
+/// [`run`] with an already loaded config, any [`RpcClient`] in place of the node configured
+/// in `cfg`, and any [`CacheBackend`].
+pub fn run_with_client<R: RpcClient>(
+    cfg: &MinerStatsConfig,
+    client: &R,
+    backend: &dyn CacheBackend,
+    metrics: &Metrics,
+    options: &RunOptions,
+) -> Result<()> {
+    let (mut cache, mut migrated) = backend.load()?;
+    let pruning = options.prune_cache || cfg.prune_cache;
+    if pruning && prune_cache(&mut cache, cfg.start_height) > 0 && !options.dry_run {
+        backend.save(&cache)?;
+    }
+    if options.force_refetch && cfg.cache_readonly {
+        return Err(Error::config(
+            "cache_readonly",
+            "--force-refetch rewrites the cache; unset cache_readonly to use it",
+        ));
+    }
+    if options.force_refetch && !cache.blocks.is_empty() {
+        info!(
+            "Discarding {} cached blocks to fetch them again",
+            cache.blocks.len()
+        );
+        cache.blocks.clear();
+        cache.last_tip = None;
+    }
+    metrics.set_cache_blocks(cache.blocks.len());
+    if let Some(last_tip) = cache.last_tip.filter(|_| !cfg.cache_readonly) {
+        let gaps = cache.coverage_gaps(cfg.start_height, last_tip);
+        if !gaps.is_empty() {
+            warn!(
+                gaps = %format_ranges(&gaps),
+                "cache is missing blocks below its last synced tip; fetching them again"
+            );
+        }
+    }
+    let cache: SharedCache = Arc::new(RwLock::new(cache));
+    let mut addresses = match &cfg.derived_address_cache {
+        Some(path) => DerivedAddressCache::load(path)?,
+        None => DerivedAddressCache::default(),
+    };
+    if cfg.systemd {
+        systemd::enable();
+    }
+    let mut webhook = match (&cfg.webhook_url, options.watch) {
+        (Some(url), Some(_)) => Some(Webhook::new(
+            url.clone(),
+            cfg.webhook_secret.clone(),
+            cfg.webhook_on_threshold,
+        )?),
+        _ => None,
+    };
+    if let Some(currency) = &cfg.fiat_currency
+        && let Some(rate) = fiat::fetch_rate(&cfg.price_api_url, currency)
+    {
+        cfg.fiat_rate.set(rate).ok();
+    }
+    let mut ready = false;
+    let mut inactive_miners = HashSet::new();
+    loop {
+        let report = update_report(
+            cfg,
+            client,
+            backend,
+            metrics,
+            options,
+            &cache,
+            &mut addresses,
+            migrated,
+        )?;
+        // The first sync saved the migrated cache, unless nothing may be written.
+        migrated = false;
+        if let Some(webhook) = &mut webhook {
+            webhook.notify(&report);
+        }
+        if options.watch.is_some() {
+            warn_inactive_miners(cfg, &report, &mut inactive_miners);
+        }
+        let status = format!(
+            "STATUS=Up to date at height {}",
+            read_cache(&cache).last_tip.unwrap_or_default()
+        );
+        if ready {
+            systemd::notify(&status);
+        } else {
+            systemd::notify(&format!("READY=1\n{status}"));
+            ready = true;
+        }
+        let Some(interval) = options.watch else {
+            break;
+        };
+        debug!(?interval, "waiting for new blocks");
+        if !sleep_unless_interrupted(interval, &options.interrupt) {
+            info!(
+                "Stopped watching at height {:?}",
+                read_cache(&cache).last_tip
+            );
+            return Err(Error::Interrupted);
+        }
+        if cfg.cache_readonly {
+            // Whoever writes the cache may have added blocks since.
+            let (reloaded, _) = backend.load()?;
+            *write_cache(&cache) = reloaded;
+        }
+    }
+    if options.dry_run {
+        let report_path = if compress_report(cfg, options) {
+            gz_path(&cfg.output_file)
+        } else {
+            cfg.output_file.clone()
+        };
+        info!(
+            "Dry run: no cache or report files were written; the report would go to {}.",
+            report_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// One pass of [`run_with_client`]: syncs `cache` to the node's tip, then computes, writes
+/// and prints the report, which it returns. `migrated` is whether `cache` was loaded with an
+/// older schema and still needs saving back.
+#[instrument(name = "update report", skip_all, fields(start_height = cfg.start_height))]
+fn update_report<R: RpcClient>(
+    cfg: &MinerStatsConfig,
+    client: &R,
+    backend: &dyn CacheBackend,
+    metrics: &Metrics,
+    options: &RunOptions,
+    cache: &RwLock<BlockCache>,
+    addresses: &mut DerivedAddressCache,
+    migrated: bool,
+) -> Result<MinerStatsReport> {
+    let tip_height = if cfg.cache_readonly {
+        let tip_height = client.block_count()?;
+        let gaps = read_cache(cache).coverage_gaps(cfg.start_height, tip_height);
+        if !gaps.is_empty() {
+            warn!(
+                missing = %format_ranges(&gaps),
+                "cache_readonly is set; computing statistics over the cached heights only"
+            );
+        }
+        tip_height
+    } else {
+        sync_and_save(cfg, client, backend, options, cache, migrated)?
+    };
+    metrics.set_last_tip(tip_height);
+
+    // Held for the whole computation so the statistics see one consistent set of blocks.
+    let mut report = {
+        let cache = read_cache(cache);
+        metrics.set_cache_blocks(cache.blocks.len());
+        compute_statistics_with_addresses(cfg, &cache, tip_height, addresses)?
+    };
+    if options.no_outliers {
+        report.outlier_blocks.clear();
+    }
+    if let Some(path) = &cfg.derived_address_cache
+        && !options.dry_run
+    {
+        addresses.save(path)?;
+    }
+    report.sort_miners(options.sort_by);
+    report.set_timezone(options.timezone);
+    for miner in &report.miners {
+        metrics.set_miner(&miner.label, miner.matched_blocks, miner.total_value_zat);
+    }
+    if !options.dry_run {
+        write_reports(cfg, &report, options)?;
+    }
+    info!(
+        "Processed heights {}-{}; matched {} blocks across {} miners.",
+        cfg.start_height,
+        tip_height,
+        report.total_mined_blocks,
+        report.miners.len()
+    );
+    #[cfg(feature = "tui")]
+    if options.tui && std::io::stdout().is_terminal() {
+        tui::show(&mut report, options.sort_by, options.human_values)?;
+        return Ok(report);
+    }
+    if tracing::enabled!(Level::INFO) {
+        print_table(&report, options);
+    }
+    Ok(report)
+}
+
+/// Syncs `cache` to the node's tip for [`update_report`], saving it at checkpoints and when
+/// it changed or was `migrated` from an older schema, and returns the tip.
+fn sync_and_save<R: RpcClient>(
+    cfg: &MinerStatsConfig,
+    client: &R,
+    backend: &dyn CacheBackend,
+    options: &RunOptions,
+    cache: &RwLock<BlockCache>,
+    migrated: bool,
+) -> Result<u64> {
+    let previous_tip = read_cache(cache).last_tip;
+    let sync = sync_shared_cache(
+        client,
+        cache,
+        cfg.start_height,
+        cfg.rpc_batch_size,
+        cfg.cache_save_interval as usize,
+        &options.interrupt,
+        |cache| {
+            if options.dry_run {
+                return Ok(());
+            }
+            debug!(blocks = cache.blocks.len(), "checkpoint: saving cache");
+            backend.save(cache)
+        },
+    );
+    let sync = match sync {
+        Err(Error::Interrupted) if !options.dry_run => {
+            let cache = read_cache(cache);
+            backend.save(&cache)?;
+            warn!(
+                "Interrupted; partial cache saved with {} blocks",
+                cache.blocks.len()
+            );
+            return Err(Error::Interrupted);
+        }
+        result => result?,
+    };
+    let tip_height = sync.tip_height;
+    if let Some(previous) = previous_tip
+        && tip_height < previous
+    {
+        warn!(
+            previous,
+            tip_height, "node tip moved back (re-org or resync); reporting up to the new tip"
+        );
+    }
+    let changed = migrated || sync.fetched_blocks > 0 || previous_tip != Some(tip_height);
+    if !options.dry_run && !cfg.cache_readonly && changed {
+        backend.save(&read_cache(cache))?;
+    }
+    Ok(tip_height)
+}
+
+/// Warns once per dry spell about each miner with no block at the tip and none for more
+/// than `inactivity_threshold_blocks` heights before it. `warned` holds the labels already
+/// warned about; a miner leaves it with its next block.
+fn warn_inactive_miners(
+    cfg: &MinerStatsConfig,
+    report: &MinerStatsReport,
+    warned: &mut HashSet<String>,
+) {
+    if cfg.inactivity_threshold_blocks == 0 {
+        return;
+    }
+    for miner in &report.miners {
+        let gap = miner.inactive_since.filter(|gap| {
+            miner.current_streak == 0 && *gap > u64::from(cfg.inactivity_threshold_blocks)
+        });
+        let Some(gap) = gap else {
+            warned.remove(&miner.label);
+            continue;
+        };
+        if warned.insert(miner.label.clone()) {
+            warn!(
+                miner = %miner.label,
+                last_block = ?miner.last_block,
+                "no block for {gap} heights, more than inactivity_threshold_blocks ({})",
+                cfg.inactivity_threshold_blocks
+            );
+        }
+    }
+}
+
+/// Sleeps for `duration` in short steps; returns `false` as soon as `interrupt` is set.
+fn sleep_unless_interrupted(duration: Duration, interrupt: &AtomicBool) -> bool {
+    const STEP: Duration = Duration::from_millis(200);
+    let deadline = Instant::now() + duration;
+    loop {
+        if interrupt.load(Ordering::Relaxed) {
+            return false;
+        }
+        systemd::watchdog();
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        std::thread::sleep(STEP.min(deadline - now));
+    }
+}
+
+/// `100-120, 205` style list of height ranges for log lines.
+fn format_ranges(ranges: &[RangeInclusive<u64>]) -> String {
+    ranges
+        .iter()
+        .map(|range| {
+            if range.start() == range.end() {
+                range.start().to_string()
+            } else {
+                format!("{}-{}", range.start(), range.end())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Whether the report is gzipped: `--compress-output` or `compress_output`, unless
+/// `--decompress-output` overrides them.
+fn compress_report(cfg: &MinerStatsConfig, options: &RunOptions) -> bool {
+    !options.decompress_output && (options.compress_output || cfg.compress_output)
+}
+
+#[instrument(name = "write report", skip_all, fields(miner_count = report.miners.len()))]
+fn write_reports(
+    cfg: &MinerStatsConfig,
+    report: &MinerStatsReport,
+    options: &RunOptions,
+) -> Result<()> {
+    let compress = compress_report(cfg, options);
+    if (options.summary_only || cfg.summary_only) && options.output_format == OutputFormat::Json {
+        report.write_summary(&cfg.output_file, compress, options.human_values)?;
+    } else {
+        report.write(
+            &cfg.output_file,
+            options.output_format,
+            compress,
+            options.human_values,
+        )?;
+    }
+    if let Some(dir) = &cfg.per_miner_output_dir {
+        report.write_per_miner_files(dir)?;
+    }
+    if let Some(path) = &options.detail_output {
+        report.write_detail_csv(path)?;
+    }
+    if let Some(path) = &options.stream_output {
+        report.write_stream(path)?;
+    }
+    if let Some(path) = &options.influx_output {
+        report.write_influx(path)?;
+    }
+    if let Some(path) = &options.es_output {
+        report.write_es_bulk(path, &options.es_index)?;
+    }
+    Ok(())
+}
+
+/// Removes the blocks below `start_height` and returns how many were removed. Refuses (with
+/// a warning) when that would empty the cache, since that usually means `start_height` is
+/// set wrong rather than that the old range is no longer wanted.
+fn prune_cache(cache: &mut BlockCache, start_height: u64) -> usize {
+    let below = cache.blocks.range(..start_height).count();
+    if below == 0 {
+        return 0;
+    }
+    if below == cache.blocks.len() {
+        warn!(
+            start_height,
+            blocks = below,
+            "every cached block is below start_height; not pruning, all cached data would be lost"
+        );
+        return 0;
+    }
+    cache.blocks = cache.blocks.split_off(&start_height);
+    info!("Pruned {below} cached blocks below height {start_height}.");
+    below
+}
+
+/// What [`sync_cache`] changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SyncSummary {
+    /// Node tip the cache now extends to.
+    pub tip_height: u64,
+    /// Blocks fetched because they were missing, incomplete or re-orged.
+    pub fetched_blocks: usize,
+    /// Cached blocks evicted because the node no longer has them on its chain.
+    pub reorged_blocks: usize,
+}
+
+/// Brings `cache` up to the client's tip: re-checks the most recent cached hashes for
+/// re-orgs, then fetches every missing or incomplete height from `start_height` on, in
+/// parallel batches of `batch_size`. On error the cache keeps every block it had before,
+/// minus re-orged ones.
+pub fn sync_cache<R: RpcClient>(
+    client: &R,
+    cache: &mut BlockCache,
+    start_height: u64,
+    batch_size: usize,
+) -> Result<SyncSummary> {
+    sync_cache_with_checkpoints(
+        client,
+        cache,
+        start_height,
+        batch_size,
+        usize::MAX,
+        &AtomicBool::new(false),
+        |_| Ok(()),
+    )
+}
+
+/// [`sync_cache`] that fetches in segments of about `checkpoint_interval` blocks (rounded
+/// up to whole batches) and hands the cache to `checkpoint` after every segment but the
+/// last, so a crash late in a long sync only loses the current segment. On error the
+/// cache keeps the blocks of every completed segment.
+///
+/// Once `interrupt` is set, no further batches are started; the batches already in flight
+/// are added to the cache and [`Error::Interrupted`] is returned without updating
+/// `last_tip`.
+pub fn sync_cache_with_checkpoints<R: RpcClient>(
+    client: &R,
+    cache: &mut BlockCache,
+    start_height: u64,
+    batch_size: usize,
+    checkpoint_interval: usize,
+    interrupt: &AtomicBool,
+    checkpoint: impl FnMut(&BlockCache) -> Result<()>,
+) -> Result<SyncSummary> {
+    with_shared(cache, |cache| {
+        sync_shared_cache(
+            client,
+            cache,
+            start_height,
+            batch_size,
+            checkpoint_interval,
+            interrupt,
+            checkpoint,
+        )
+    })
+}
+
+/// [`sync_cache_with_checkpoints`] for a cache other threads read concurrently, such as a
+/// [`SharedCache`]. Blocks are fetched without holding the lock; the write lock is only
+/// taken to insert each segment's blocks or evict re-orged ones, and `checkpoint` runs
+/// under a read lock, so readers are only ever held up for those short updates.
+pub fn sync_shared_cache<R: RpcClient>(
+    client: &R,
+    cache: &RwLock<BlockCache>,
+    start_height: u64,
+    batch_size: usize,
+    checkpoint_interval: usize,
+    interrupt: &AtomicBool,
+    mut checkpoint: impl FnMut(&BlockCache) -> Result<()>,
+) -> Result<SyncSummary> {
+    let tip_height = client.block_count()?;
+    if tip_height < start_height {
+        return Err(Error::config(
+            "start_height",
+            format!("{start_height} is above the node's tip height {tip_height}"),
+        ));
+    }
+
+    let recent: Vec<u64> = read_cache(cache)
+        .blocks
+        .range(start_height..=tip_height)
+        .rev()
+        .take(REORG_CHECK_DEPTH)
+        .map(|(height, _)| *height)
+        .collect();
+    let mut reorged_blocks = 0;
+    if !recent.is_empty() {
+        let reorged = client.verify_block_hashes(&recent, &read_cache(cache))?;
+        if let Some(lowest) = reorged.iter().min() {
+            warn!(
+                blocks = reorged.len(),
+                depth = tip_height - lowest + 1,
+                "cached blocks no longer match the node (re-org); re-fetching"
+            );
+            let mut cache = write_cache(cache);
+            for height in &reorged {
+                cache.blocks.remove(height);
+            }
+            reorged_blocks = reorged.len();
+        }
+    }
+
+    let missing: Vec<u64> = {
+        let cache = read_cache(cache);
+        (start_height..=tip_height)
+            .filter(|h| !cache.blocks.get(h).is_some_and(CachedBlock::is_complete))
+            .collect()
+    };
+    fetch_into(
+        client,
+        cache,
+        &missing,
+        batch_size,
+        checkpoint_interval,
+        interrupt,
+        &mut checkpoint,
+    )?;
+    write_cache(cache).last_tip = Some(tip_height);
+    Ok(SyncSummary {
+        tip_height,
+        fetched_blocks: missing.len(),
+        reorged_blocks,
+    })
+}
+
+/// Fetches `heights` in parallel batches of `batch_size` and stores them in `cache`, one
+/// segment of about `checkpoint_interval` heights at a time; `checkpoint` runs between
+/// segments. A segment's blocks are only stored once every batch of it succeeded.
+fn fetch_into<R: RpcClient>(
+    client: &R,
+    cache: &RwLock<BlockCache>,
+    heights: &[u64],
+    batch_size: usize,
+    checkpoint_interval: usize,
+    interrupt: &AtomicBool,
+    checkpoint: &mut dyn FnMut(&BlockCache) -> Result<()>,
+) -> Result<()> {
+    if heights.is_empty() {
+        return Ok(());
+    }
+    info!(blocks = heights.len(), "fetching blocks from RPC");
+    systemd::notify(&format!("STATUS=Fetching {} blocks", heights.len()));
+    let batch_size = batch_size.max(1);
+    let segment = checkpoint_interval
+        .clamp(1, heights.len())
+        .div_ceil(batch_size)
+        * batch_size;
+    let progress = blocks_progress_bar(heights.len());
+    let slots = Semaphore::new(client.max_concurrent());
+    let parent = Span::current();
+    let mut segments = heights.chunks(segment).peekable();
+    let result = (|| -> Result<()> {
+        while let Some(segment) = segments.next() {
+            let fetched: Vec<Vec<CachedBlock>> = segment
+                .par_chunks(batch_size)
+                .map(|chunk| {
+                    if interrupt.load(Ordering::Relaxed) {
+                        return Ok(Vec::new());
+                    }
+                    // Rayon workers do not inherit the current span; attach it explicitly.
+                    let _span =
+                        info_span!(parent: &parent, "fetch batch", height_count = chunk.len())
+                            .entered();
+                    let blocks = slots.run(|| client.fetch_blocks(chunk));
+                    progress.inc(chunk.len() as u64);
+                    blocks
+                })
+                .collect::<Result<_>>()?;
+            {
+                let mut cache = write_cache(cache);
+                for block in fetched.into_iter().flatten() {
+                    cache.blocks.insert(block.height, block);
+                }
+            }
+            systemd::watchdog();
+            if interrupt.load(Ordering::Relaxed) {
+                return Err(Error::Interrupted);
+            }
+            if segments.peek().is_some() {
+                checkpoint(&read_cache(cache))?;
+            }
+        }
+        Ok(())
+    })();
+    progress.finish_and_clear();
+    result
+}
+
+/// Counting semaphore capping the RPC calls made from rayon workers at once. Waiting
+/// workers block, as they would on the node anyway.
+pub(crate) struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Semaphore {
+    pub(crate) fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits.max(1)),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Runs `f` while holding one permit.
+    pub(crate) fn run<T>(&self, f: impl FnOnce() -> T) -> T {
+        {
+            let mut available = self
+                .available
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            while *available == 0 {
+                available = self
+                    .released
+                    .wait(available)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            *available -= 1;
+        }
+        let result = f();
+        *self
+            .available
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) += 1;
+        self.released.notify_one();
+        result
+    }
+}
+
+/// Progress bar over `total` blocks, for fetching or exporting them; hidden when stderr is
+/// not a terminal so redirected output stays clean, and when `info` logs are filtered out
+/// (`--quiet`).
+pub(crate) fn blocks_progress_bar(total: usize) -> ProgressBar {
+    if !std::io::stderr().is_terminal() || !tracing::enabled!(Level::INFO) {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(total as u64);
+    if let Ok(style) = ProgressStyle::with_template(
+        "{spinner} [{elapsed_precise}] [{bar:40}] {pos}/{len} blocks (ETA {eta})",
+    ) {
+        bar.set_style(style.progress_chars("=> "));
+    }
+    bar.enable_steady_tick(Duration::from_millis(120));
+    bar
+}
+
+/// Number of most recent cached blocks whose hashes are re-checked against the node on
+/// every run to detect re-orgs.
+const REORG_CHECK_DEPTH: usize = 100;
+
+/// Rejects a UFVK listed under more than one entry, whose blocks would be counted for each
+/// of them, and warns about different keys that share key material (the same address at
+/// height 0). Keys that fail to decode are left for the scan to report.
+fn check_duplicate_keys(chain: &ChainType, miners: &[MinerEntry]) -> Result<()> {
+    let mut labels_by_key: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for miner in miners {
+        for key in miner.keys.iter().collect::<BTreeSet<_>>() {
+            labels_by_key.entry(key).or_default().push(&miner.label);
+        }
+    }
+    if let Some((key, labels)) = labels_by_key.iter().find(|(_, labels)| labels.len() > 1) {
+        return Err(Error::config(
+            "ufvks",
+            format!(
+                "UFVK {} is listed under more than one entry: {}",
+                shorten_key(key),
+                quoted_list(labels)
+            ),
+        ));
+    }
+
+    let mut labels_by_address: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for miner in miners {
+        let addresses: BTreeSet<String> = miner
+            .keys
+            .iter()
+            .filter_map(|key| validate_config::derive_first_address(chain, key).ok())
+            .collect();
+        for address in addresses {
+            labels_by_address
+                .entry(address)
+                .or_default()
+                .push(&miner.label);
+        }
+    }
+    for (address, labels) in labels_by_address.iter().filter(|(_, l)| l.len() > 1) {
+        warn!(
+            address,
+            entries = %quoted_list(labels),
+            "different UFVKs derive the same transparent address at height 0; \
+             their blocks will be counted for each entry"
+        );
+    }
+    Ok(())
+}
+
+/// Entries sharing a label make the report ambiguous. Returns a warning per shared label,
+/// or an error for the first one under `strict_labels`.
+fn check_duplicate_labels(miners: &[MinerEntry], strict: bool) -> Result<Vec<String>> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for miner in miners {
+        *counts.entry(&miner.label).or_default() += 1;
+    }
+    let mut warnings = Vec::new();
+    for (label, count) in counts.into_iter().filter(|(_, count)| *count > 1) {
+        let message = format!("label '{label}' is used by {count} ufvks entries");
+        if strict {
+            return Err(Error::config("ufvks", format!("{message} (strict_labels)")));
+        }
+        warn!("{message}; the report cannot tell them apart");
+        warnings.push(message);
+    }
+    Ok(warnings)
+}
+
+/// Reads `address_map_file`, rejecting empty labels.
+fn load_address_map(path: &Path) -> Result<HashMap<String, String>> {
+    let raw = fs::read(path).map_err(Error::io("reading address map", path))?;
+    let map: HashMap<String, String> =
+        serde_json::from_slice(&raw).map_err(|source| Error::AddressMapParse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    if let Some(address) = map.keys().find(|address| map[*address].is_empty()) {
+        return Err(Error::config(
+            "address_map_file",
+            format!("address {address} has an empty label"),
+        ));
+    }
+    Ok(map)
+}
+
+fn quoted_list(labels: &[&str]) -> String {
+    labels
+        .iter()
+        .map(|label| format!("'{label}'"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Raw TOML layout of the configuration file, before validation. Public only so the fuzz
+/// targets can parse it; use [`MinerStatsConfig::from_file`] instead.
+#[doc(hidden)]
+#[derive(Debug, Deserialize)]
+pub struct ConfigFile {
+    start_height: u64,
+    chain: String,
+    /// Single endpoint; convenience alias for a one-element `rpc_urls`.
+    #[serde(default)]
+    rpc_url: Option<String>,
+    /// Endpoints tried in order; later entries are fallbacks.
+    #[serde(default)]
+    rpc_urls: Vec<String>,
+    #[serde(default)]
+    rpc_user: Option<String>,
+    #[serde(default)]
+    rpc_password: Option<String>,
+    /// Extra PEM root certificate trusted for HTTPS endpoints (e.g. a self-signed proxy).
+    #[serde(default)]
+    rpc_ca_cert_pem: Option<PathBuf>,
+    /// Accept any TLS certificate. Only for isolated test setups.
+    #[serde(default)]
+    rpc_tls_insecure: bool,
+    #[serde(default)]
+    ufvks: Vec<MinerConfigEntry>,
+    cache_file: PathBuf,
+    output_file: PathBuf,
+    /// Storage format of `cache_file`.
+    #[serde(default)]
+    cache_backend: CacheBackendKind,
+    #[serde(default)]
+    compressed: bool,
+    #[serde(default)]
+    rpc_retry: RetryPolicy,
+    #[serde(default = "default_rpc_batch_size")]
+    rpc_batch_size: usize,
+    /// Timeout for cheap calls such as `getblockcount`.
+    #[serde(default = "default_rpc_timeout_secs")]
+    rpc_timeout_secs: u64,
+    /// Timeout for block fetches (`getblockhash`/`getblock`, single or batched).
+    #[serde(default = "default_rpc_fetch_timeout_secs")]
+    rpc_fetch_timeout_secs: u64,
+    /// Also report shares over only the last `window_blocks` heights of the range.
+    #[serde(default)]
+    window_blocks: Option<u32>,
+    /// Gzip the report file (same as `--compress-output`).
+    #[serde(default)]
+    compress_output: bool,
+    /// Directory receiving one `{label}.json` file per miner.
+    #[serde(default)]
+    per_miner_output_dir: Option<PathBuf>,
+    /// Address (`host:port`) to serve Prometheus metrics on.
+    #[serde(default)]
+    metrics_addr: Option<String>,
+    /// Heights left out of every statistic (known anomalies, test blocks, orphans).
+    #[serde(default)]
+    exclude_heights: Vec<u64>,
+    /// Save the cache after every this many newly fetched blocks.
+    #[serde(default = "default_cache_save_interval")]
+    cache_save_interval: u32,
+    /// Drop cached blocks below `start_height` on load (same as `--prune-cache`).
+    #[serde(default)]
+    prune_cache: bool,
+    /// Most RPC requests in flight at once across all fetch workers.
+    #[serde(default = "default_rpc_max_concurrent")]
+    rpc_max_concurrent: usize,
+    /// Reject entries sharing a label instead of only warning about them.
+    #[serde(default)]
+    strict_labels: bool,
+    /// JSON file caching the transparent addresses derived per key and height.
+    #[serde(default)]
+    derived_address_cache: Option<PathBuf>,
+    /// StatsD daemon (`host:port`) that receives metrics over UDP while running.
+    #[serde(default)]
+    statsd_addr: Option<String>,
+    /// Prefix of every StatsD metric name.
+    #[serde(default = "default_statsd_prefix")]
+    statsd_prefix: String,
+    /// Send `Type=notify` readiness, watchdog and status messages to systemd.
+    #[serde(default)]
+    systemd: bool,
+    /// With `--watch`, URL to POST every block newly credited to a miner to.
+    #[serde(default)]
+    webhook_url: Option<String>,
+    /// New blocks a miner must gain before they are posted (1 = every block).
+    #[serde(default = "default_webhook_on_threshold")]
+    webhook_on_threshold: u32,
+    /// Key for the HMAC-SHA256 signature of webhook bodies (`X-Signature-256`).
+    #[serde(default)]
+    webhook_secret: Option<String>,
+    /// Block explorer link for every matched block; `{height}` and `{hash}` are filled in.
+    #[serde(default)]
+    explorer_url_template: Option<String>,
+    /// Node software behind `rpc_urls`; detected with `getinfo` when unset.
+    #[serde(default)]
+    node_type: Option<NodeType>,
+    /// Leave `detailed_miners` out of the JSON report; same as `--summary-only`.
+    #[serde(default)]
+    summary_only: bool,
+    /// Add `moving_avg_share` windows to every miner of the report.
+    #[serde(default)]
+    include_moving_avg: bool,
+    /// Heights per `moving_avg_share` window.
+    #[serde(default = "default_rolling_window_blocks")]
+    rolling_window_blocks: u32,
+    /// Heights between the ends of consecutive `moving_avg_share` windows.
+    #[serde(default = "default_rolling_window_step")]
+    rolling_window_step: u32,
+    /// Most `moving_avg_share` windows per miner; the most recent are kept.
+    #[serde(default = "default_max_windows")]
+    max_windows: usize,
+    /// List runs of more than this many heights without a block per miner; 0 turns it off.
+    #[serde(default)]
+    inactivity_threshold_blocks: u32,
+    /// Add a `value_histogram` of coinbase values to the report and every miner.
+    #[serde(default)]
+    include_histogram: bool,
+    /// Confirmations before a coinbase output counts as mature; 100 on every Zcash network.
+    #[serde(default = "default_maturity_depth")]
+    maturity_depth: u32,
+    /// Currency (e.g. `"USD"`) to value miner totals in, at the rate fetched from
+    /// `price_api_url` at startup.
+    #[serde(default)]
+    fiat_currency: Option<String>,
+    /// Price endpoint for `fiat_currency`; `{currency}` is replaced with the lowercased currency.
+    #[serde(default = "default_price_api_url")]
+    price_api_url: String,
+    /// Encoding of a `json` backend cache file: `json` or `msgpack`.
+    #[serde(default)]
+    cache_format: CacheFormat,
+    /// Never write the cache (e.g. one shared by several processes): missing blocks are not
+    /// fetched, and statistics cover only the cached heights.
+    #[serde(default)]
+    cache_readonly: bool,
+    /// Splits a `json` backend cache into one file per this many heights, next to
+    /// `cache_file`; see [`ShardedFileCache`].
+    #[serde(default)]
+    cache_shard_size: Option<u32>,
+    /// JSON object of transparent address to miner label, for miners whose keys are managed
+    /// elsewhere; matched alongside the UFVKs.
+    #[serde(default)]
+    address_map_file: Option<PathBuf>,
+    /// Notes on known special blocks (upgrade activations, pool launches), by height, from the
+    /// `[annotations]` table; copied onto the matching `MinerBlockDetail`s.
+    #[serde(default, rename = "annotations")]
+    block_annotations: BTreeMap<String, String>,
+    /// Blocks with the highest coinbase value listed under `top_blocks`; 0 leaves it out.
+    #[serde(default = "default_top_n_blocks")]
+    top_n_blocks: usize,
+    /// Add `day_of_week_stats` of matched blocks per weekday to every miner of the report.
+    #[serde(default)]
+    include_day_stats: bool,
+}
+
+fn default_cache_save_interval() -> u32 {
+    500
+}
+
+fn default_rpc_max_concurrent() -> usize {
+    8
+}
+
+fn default_rpc_batch_size() -> usize {
+    50
+}
+
+fn default_rpc_timeout_secs() -> u64 {
+    10
+}
+
+fn default_statsd_prefix() -> String {
+    "miner_stats".to_string()
+}
+
+fn default_webhook_on_threshold() -> u32 {
+    1
+}
+
+/// One day of blocks at the 75-second target spacing.
+fn default_rolling_window_blocks() -> u32 {
+    1152
+}
+
+fn default_rolling_window_step() -> u32 {
+    288
+}
+
+fn default_max_windows() -> usize {
+    100
+}
+
+fn default_transparent_scopes() -> Vec<AddressScope> {
+    vec![AddressScope::External]
+}
+
+fn default_maturity_depth() -> u32 {
+    COINBASE_MATURITY
+}
+
+fn default_price_api_url() -> String {
+    DEFAULT_PRICE_API_URL.to_string()
+}
+
+fn default_rpc_fetch_timeout_secs() -> u64 {
+    60
+}
+
+fn default_top_n_blocks() -> usize {
+    10
+}
+
+#[derive(Debug, Deserialize)]
+struct MinerConfigEntry {
+    #[serde(default)]
+    key: Option<String>,
+    /// Several UFVKs (e.g. rotated keys) whose matches are combined under `label`.
+    #[serde(default)]
+    keys: Vec<String>,
+    label: String,
+    /// First height this miner is credited for (inclusive); defaults to the global
+    /// `start_height`. Blocks outside the miner's range are never attributed to it and
+    /// count as `Others`, but its `share_percent` is still taken over every block of the
+    /// global range so it reflects the miner's true network share.
+    #[serde(default)]
+    start_height: Option<u64>,
+    /// Last height this miner is credited for (inclusive); defaults to the node tip.
+    #[serde(default)]
+    end_height: Option<u64>,
+    /// Transparent address chains checked at each height, in order.
+    #[serde(default = "default_transparent_scopes")]
+    transparent_scopes: Vec<AddressScope>,
+}
+
+/// Validated configuration; see `config.example.toml` for the file format.
+#[derive(Debug)]
+pub struct MinerStatsConfig {
+    start_height: u64,
+    chain: ChainType,
+    rpc_urls: Vec<String>,
+    rpc_auth: Option<RpcAuth>,
+    rpc_tls: RpcTls,
+    miners: Vec<MinerEntry>,
+    cache_file: PathBuf,
+    output_file: PathBuf,
+    cache_backend: CacheBackendKind,
+    compressed: bool,
+    rpc_retry: RetryPolicy,
+    rpc_batch_size: usize,
+    rpc_timeout: Duration,
+    rpc_fetch_timeout: Duration,
+    window_blocks: Option<u32>,
+    compress_output: bool,
+    per_miner_output_dir: Option<PathBuf>,
+    metrics_addr: Option<String>,
+    exclude_heights: BTreeSet<u64>,
+    cache_save_interval: u32,
+    prune_cache: bool,
+    rpc_max_concurrent: usize,
+    /// Problems found while validating that did not stop the run; copied into the report.
+    warnings: Vec<String>,
+    derived_address_cache: Option<PathBuf>,
+    statsd_addr: Option<String>,
+    statsd_prefix: String,
+    systemd: bool,
+    webhook_url: Option<String>,
+    webhook_on_threshold: u32,
+    webhook_secret: Option<String>,
+    explorer_url_template: Option<String>,
+    node_type: Option<NodeType>,
+    summary_only: bool,
+    include_moving_avg: bool,
+    rolling_window_blocks: u32,
+    rolling_window_step: u32,
+    max_windows: usize,
+    inactivity_threshold_blocks: u32,
+    include_histogram: bool,
+    maturity_depth: u32,
+    fiat_currency: Option<String>,
+    price_api_url: String,
+    /// Rate of `fiat_currency` fetched by [`run_with_client`]; unset if the fetch failed or
+    /// never ran.
+    fiat_rate: OnceLock<f64>,
+    cache_format: CacheFormat,
+    cache_readonly: bool,
+    cache_shard_size: Option<u64>,
+    /// Contents of `address_map_file`: miner label by address.
+    address_map: HashMap<String, String>,
+    block_annotations: HashMap<u64, String>,
+    top_n_blocks: usize,
+    include_day_stats: bool,
+}
+
+#[derive(Debug, Clone)]
+struct MinerEntry {
+    keys: Vec<String>,
+    label: String,
+    start_height: Option<u64>,
+    end_height: Option<u64>,
+    transparent_scopes: Vec<AddressScope>,
+}
+
+impl MinerStatsConfig {
+    pub fn builder() -> MinerStatsConfigBuilder {
+        MinerStatsConfigBuilder::default()
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path).map_err(Error::io("reading config file", path))?;
+        let cfg: ConfigFile = toml::from_str(&raw).map_err(|source| Error::ConfigParse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let chain = chain_from_str(&cfg.chain)
+            .map_err(|e| Error::config("chain", format!("'{}': {e}", cfg.chain)))?;
+        MinerStatsConfigBuilder {
+            start_height: cfg.start_height,
+            chain: Some(chain),
+            rpc_url: cfg.rpc_url,
+            rpc_urls: cfg.rpc_urls,
+            rpc_user: cfg.rpc_user,
+            rpc_password: cfg.rpc_password,
+            rpc_ca_cert_pem: cfg.rpc_ca_cert_pem,
+            rpc_tls_insecure: cfg.rpc_tls_insecure,
+            miners: cfg
+                .ufvks
+                .into_iter()
+                .map(|entry| match (entry.key, entry.keys) {
+                    (Some(key), keys) if keys.is_empty() => Ok(MinerEntry {
+                        keys: vec![key],
+                        label: entry.label,
+                        start_height: entry.start_height,
+                        end_height: entry.end_height,
+                        transparent_scopes: entry.transparent_scopes,
+                    }),
+                    (None, keys) if !keys.is_empty() => Ok(MinerEntry {
+                        keys,
+                        label: entry.label,
+                        start_height: entry.start_height,
+                        end_height: entry.end_height,
+                        transparent_scopes: entry.transparent_scopes,
+                    }),
+                    _ => Err(Error::config(
+                        "ufvks",
+                        format!(
+                            "entry '{}' must set exactly one of key or keys",
+                            entry.label
+                        ),
+                    )),
+                })
+                .collect::<Result<_>>()?,
+            cache_file: Some(cfg.cache_file),
+            output_file: Some(cfg.output_file),
+            cache_backend: cfg.cache_backend,
+            compressed: cfg.compressed,
+            rpc_retry: cfg.rpc_retry,
+            rpc_batch_size: cfg.rpc_batch_size,
+            rpc_timeout_secs: cfg.rpc_timeout_secs,
+            rpc_fetch_timeout_secs: cfg.rpc_fetch_timeout_secs,
+            window_blocks: cfg.window_blocks,
+            compress_output: cfg.compress_output,
+            per_miner_output_dir: cfg.per_miner_output_dir,
+            metrics_addr: cfg.metrics_addr,
+            exclude_heights: cfg.exclude_heights,
+            cache_save_interval: cfg.cache_save_interval,
+            prune_cache: cfg.prune_cache,
+            rpc_max_concurrent: cfg.rpc_max_concurrent,
+            strict_labels: cfg.strict_labels,
+            derived_address_cache: cfg.derived_address_cache,
+            statsd_addr: cfg.statsd_addr,
+            statsd_prefix: cfg.statsd_prefix,
+            systemd: cfg.systemd,
+            webhook_url: cfg.webhook_url,
+            webhook_on_threshold: cfg.webhook_on_threshold,
+            webhook_secret: cfg.webhook_secret,
+            explorer_url_template: cfg.explorer_url_template,
+            node_type: cfg.node_type,
+            summary_only: cfg.summary_only,
+            include_moving_avg: cfg.include_moving_avg,
+            rolling_window_blocks: cfg.rolling_window_blocks,
+            rolling_window_step: cfg.rolling_window_step,
+            max_windows: cfg.max_windows,
+            inactivity_threshold_blocks: cfg.inactivity_threshold_blocks,
+            include_histogram: cfg.include_histogram,
+            maturity_depth: cfg.maturity_depth,
+            fiat_currency: cfg.fiat_currency,
+            price_api_url: cfg.price_api_url,
+            cache_format: cfg.cache_format,
+            cache_readonly: cfg.cache_readonly,
+            cache_shard_size: cfg.cache_shard_size,
+            address_map_file: cfg.address_map_file,
+            block_annotations: cfg
+                .block_annotations
+                .into_iter()
+                .map(|(height, note)| match height.parse() {
+                    Ok(height) => Ok((height, note)),
+                    Err(_) => Err(Error::config(
+                        "annotations",
+                        format!("key '{height}' is not a block height"),
+                    )),
+                })
+                .collect::<Result<_>>()?,
+            top_n_blocks: cfg.top_n_blocks,
+            include_day_stats: cfg.include_day_stats,
+        }
+        .build()
+    }
+
+    /// Opens the configured cache backend, or [`NoOpCache`] when `disabled`.
+    pub fn cache_backend(&self, disabled: bool) -> Result<Box<dyn CacheBackend>> {
+        if disabled {
+            return Ok(Box::new(NoOpCache));
+        }
+        if let (CacheBackendKind::Json, Some(shard_size)) =
+            (self.cache_backend, self.cache_shard_size)
+        {
+            return Ok(Box::new(ShardedFileCache::new(
+                self.cache_file.clone(),
+                shard_size,
+                self.start_height,
+                self.compressed,
+                self.cache_format,
+                self.cache_readonly,
+            )));
+        }
+        match self.cache_backend {
+            CacheBackendKind::Json => Ok(Box::new(JsonFileCache {
+                path: self.cache_file.clone(),
+                compressed: self.compressed,
+                format: self.cache_format,
+                readonly: self.cache_readonly,
+            })),
+            #[cfg(feature = "sqlite")]
+            CacheBackendKind::Sqlite if self.cache_readonly => {
+                Ok(Box::new(SqliteCache::read_only(self.cache_file.clone())))
+            }
+            #[cfg(feature = "sqlite")]
+            CacheBackendKind::Sqlite => Ok(Box::new(SqliteCache::new(self.cache_file.clone()))),
+            #[cfg(not(feature = "sqlite"))]
+            CacheBackendKind::Sqlite => Err(Error::config(
+                "cache_backend",
+                "\"sqlite\" requires building with the `sqlite` feature",
+            )),
+            CacheBackendKind::Disabled => Ok(Box::new(NoOpCache)),
+        }
+    }
+}
+
+/// Assembles a [`MinerStatsConfig`] in code, applying the same defaults and validation as
+/// [`MinerStatsConfig::from_file`].
+#[derive(Debug)]
+pub struct MinerStatsConfigBuilder {
+    start_height: u64,
+    chain: Option<ChainType>,
+    rpc_url: Option<String>,
+    rpc_urls: Vec<String>,
+    rpc_user: Option<String>,
+    rpc_password: Option<String>,
+    rpc_ca_cert_pem: Option<PathBuf>,
+    rpc_tls_insecure: bool,
+    miners: Vec<MinerEntry>,
+    cache_file: Option<PathBuf>,
+    output_file: Option<PathBuf>,
+    cache_backend: CacheBackendKind,
+    compressed: bool,
+    rpc_retry: RetryPolicy,
+    rpc_batch_size: usize,
+    rpc_timeout_secs: u64,
+    rpc_fetch_timeout_secs: u64,
+    window_blocks: Option<u32>,
+    compress_output: bool,
+    per_miner_output_dir: Option<PathBuf>,
+    metrics_addr: Option<String>,
+    exclude_heights: Vec<u64>,
+    cache_save_interval: u32,
+    prune_cache: bool,
+    rpc_max_concurrent: usize,
+    strict_labels: bool,
+    derived_address_cache: Option<PathBuf>,
+    statsd_addr: Option<String>,
+    statsd_prefix: String,
+    systemd: bool,
+    webhook_url: Option<String>,
+    webhook_on_threshold: u32,
+    webhook_secret: Option<String>,
+    explorer_url_template: Option<String>,
+    node_type: Option<NodeType>,
+    summary_only: bool,
+    include_moving_avg: bool,
+    rolling_window_blocks: u32,
+    rolling_window_step: u32,
+    max_windows: usize,
+    inactivity_threshold_blocks: u32,
+    include_histogram: bool,
+    maturity_depth: u32,
+    fiat_currency: Option<String>,
+    price_api_url: String,
+    cache_format: CacheFormat,
+    cache_readonly: bool,
+    cache_shard_size: Option<u32>,
+    address_map_file: Option<PathBuf>,
+    block_annotations: HashMap<u64, String>,
+    top_n_blocks: usize,
+    include_day_stats: bool,
+}
+
+impl Default for MinerStatsConfigBuilder {
+    fn default() -> Self {
+        Self {
+            start_height: 0,
+            chain: None,
+            rpc_url: None,
+            rpc_urls: Vec::new(),
+            rpc_user: None,
+            rpc_password: None,
+            rpc_ca_cert_pem: None,
+            rpc_tls_insecure: false,
+            miners: Vec::new(),
+            cache_file: None,
+            output_file: None,
+            cache_backend: CacheBackendKind::default(),
+            compressed: false,
+            rpc_retry: RetryPolicy::default(),
+            rpc_batch_size: default_rpc_batch_size(),
+            rpc_timeout_secs: default_rpc_timeout_secs(),
+            rpc_fetch_timeout_secs: default_rpc_fetch_timeout_secs(),
+            window_blocks: None,
+            compress_output: false,
+            per_miner_output_dir: None,
+            metrics_addr: None,
+            exclude_heights: Vec::new(),
+            cache_save_interval: default_cache_save_interval(),
+            prune_cache: false,
+            rpc_max_concurrent: default_rpc_max_concurrent(),
+            strict_labels: Default::default(),
+            derived_address_cache: None,
+            statsd_addr: None,
+            statsd_prefix: default_statsd_prefix(),
+            systemd: Default::default(),
+            webhook_url: Default::default(),
+            webhook_on_threshold: default_webhook_on_threshold(),
+            webhook_secret: Default::default(),
+            explorer_url_template: Default::default(),
+            node_type: Default::default(),
+            summary_only: Default::default(),
+            include_moving_avg: Default::default(),
+            rolling_window_blocks: default_rolling_window_blocks(),
+            rolling_window_step: default_rolling_window_step(),
+            max_windows: default_max_windows(),
+            inactivity_threshold_blocks: Default::default(),
+            include_histogram: Default::default(),
+            maturity_depth: default_maturity_depth(),
+            fiat_currency: Default::default(),
+            price_api_url: default_price_api_url(),
+            cache_format: Default::default(),
+            cache_readonly: Default::default(),
+            cache_shard_size: Default::default(),
+            address_map_file: Default::default(),
+            block_annotations: Default::default(),
+            top_n_blocks: default_top_n_blocks(),
+            include_day_stats: Default::default(),
+        }
+    }
+}
+
+impl MinerStatsConfigBuilder {
+    pub fn start_height(mut self, height: u64) -> Self {
+        self.start_height = height;
+        self
+    }
+
+    pub fn chain(mut self, chain: ChainType) -> Self {
+        self.chain = Some(chain);
+        self
+    }
+
+    /// Adds an endpoint; endpoints are tried in the order they were added.
+    pub fn rpc_url(mut self, url: impl Into<String>) -> Self {
+        self.rpc_urls.push(url.into());
+        self
+    }
+
+    pub fn add_miner(mut self, label: impl Into<String>, key: impl Into<String>) -> Self {
+        self.miners.push(MinerEntry {
+            keys: vec![key.into()],
+            label: label.into(),
+            start_height: None,
+            end_height: None,
+            transparent_scopes: default_transparent_scopes(),
+        });
+        self
+    }
+
+    /// Adds a miner whose blocks may pay any of `keys`; matches are combined under `label`.
+    pub fn add_miner_keys<K: Into<String>>(
+        mut self,
+        label: impl Into<String>,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Self {
+        self.miners.push(MinerEntry {
+            keys: keys.into_iter().map(Into::into).collect(),
+            label: label.into(),
+            start_height: None,
+            end_height: None,
+            transparent_scopes: default_transparent_scopes(),
+        });
+        self
+    }
+
+    /// Sets the transparent scopes checked for the miner added last (`[External]` by
+    /// default).
+    pub fn transparent_scopes(mut self, scopes: impl IntoIterator<Item = AddressScope>) -> Self {
+        if let Some(miner) = self.miners.last_mut() {
+            miner.transparent_scopes = scopes.into_iter().collect();
+        }
+        self
+    }
+
+    pub fn cache_file(mut self, path: PathBuf) -> Self {
+        self.cache_file = Some(path);
+        self
+    }
+
+    pub fn output_file(mut self, path: PathBuf) -> Self {
+        self.output_file = Some(path);
+        self
+    }
+
+    pub fn derived_address_cache(mut self, path: PathBuf) -> Self {
+        self.derived_address_cache = Some(path);
+        self
+    }
+
+    /// POSTs blocks newly credited to a miner to `url` while watching (`webhook_url`),
+    /// signed with `secret` if given (`webhook_secret`).
+    pub fn webhook(mut self, url: impl Into<String>, secret: Option<String>) -> Self {
+        self.webhook_url = Some(url.into());
+        self.webhook_secret = secret;
+        self
+    }
+
+    /// Adds `moving_avg_share` windows of `window_blocks` heights, `step` heights apart, to
+    /// every miner (`include_moving_avg`).
+    pub fn moving_avg(mut self, window_blocks: u32, step: u32) -> Self {
+        self.include_moving_avg = true;
+        self.rolling_window_blocks = window_blocks;
+        self.rolling_window_step = step;
+        self
+    }
+
+    /// Keeps only the most recent `max` `moving_avg_share` windows (`max_windows`).
+    pub fn max_windows(mut self, max: usize) -> Self {
+        self.max_windows = max;
+        self
+    }
+
+    /// Lists each miner's runs of more than `blocks` heights without a block
+    /// (`inactivity_threshold_blocks`).
+    pub fn inactivity_threshold_blocks(mut self, blocks: u32) -> Self {
+        self.inactivity_threshold_blocks = blocks;
+        self
+    }
+
+    /// Counts a block's value as mature once `depth` blocks were mined on top of it
+    /// (`maturity_depth`).
+    pub fn maturity_depth(mut self, depth: u32) -> Self {
+        self.maturity_depth = depth;
+        self
+    }
+
+    /// Values miner totals in `currency` as well (`fiat_currency`).
+    pub fn fiat_currency(mut self, currency: impl Into<String>) -> Self {
+        self.fiat_currency = Some(currency.into());
+        self
+    }
+
+    /// Fetches the `fiat_currency` rate from `url` (`price_api_url`).
+    pub fn price_api_url(mut self, url: impl Into<String>) -> Self {
+        self.price_api_url = url.into();
+        self
+    }
+
+    /// Encoding of the `json` backend's cache file (`cache_format`).
+    pub fn cache_format(mut self, format: CacheFormat) -> Self {
+        self.cache_format = format;
+        self
+    }
+
+    /// Never writes the cache, and computes statistics over the cached heights only instead of
+    /// fetching missing blocks (`cache_readonly`).
+    pub fn cache_readonly(mut self, enabled: bool) -> Self {
+        self.cache_readonly = enabled;
+        self
+    }
+
+    /// Splits the cache into one file per `heights` heights (`cache_shard_size`).
+    pub fn cache_shard_size(mut self, heights: u32) -> Self {
+        self.cache_shard_size = Some(heights);
+        self
+    }
+
+    /// Also credits the addresses of a JSON `{"address": "label"}` file to those labels.
+    pub fn address_map_file(mut self, path: PathBuf) -> Self {
+        self.address_map_file = Some(path);
+        self
+    }
+
+    /// Annotates the report's matched block at `height` with `note`.
+    pub fn annotate_block(mut self, height: u64, note: impl Into<String>) -> Self {
+        self.block_annotations.insert(height, note.into());
+        self
+    }
+
+    /// Lists this many blocks with the highest coinbase value under `top_blocks` (10 by
+    /// default; 0 leaves them out).
+    pub fn top_n_blocks(mut self, count: usize) -> Self {
+        self.top_n_blocks = count;
+        self
+    }
+
+    /// Adds each miner's blocks and value per weekday to the report (`day_of_week_stats`).
+    pub fn include_day_stats(mut self, include: bool) -> Self {
+        self.include_day_stats = include;
+        self
+    }
+
+    /// Adds coinbase value histograms to the report (`include_histogram`).
+    pub fn include_histogram(mut self, enabled: bool) -> Self {
+        self.include_histogram = enabled;
+        self
+    }
+
+    /// Writes only the aggregate figures of the JSON report (`summary_only`).
+    pub fn summary_only(mut self, enabled: bool) -> Self {
+        self.summary_only = enabled;
+        self
+    }
+
+    /// Decodes blocks the way `node_type` answers `getblock` instead of asking the node.
+    pub fn node_type(mut self, node_type: NodeType) -> Self {
+        self.node_type = Some(node_type);
+        self
+    }
+
+    /// Links every matched block to a block explorer (`explorer_url_template`).
+    pub fn explorer_url_template(mut self, template: impl Into<String>) -> Self {
+        self.explorer_url_template = Some(template.into());
+        self
+    }
+
+    /// Makes entries sharing a label an error rather than a warning.
+    pub fn strict_labels(mut self, strict: bool) -> Self {
+        self.strict_labels = strict;
+        self
+    }
+
+    /// Leaves `heights` out of every statistic.
+    pub fn exclude_heights(mut self, heights: impl IntoIterator<Item = u64>) -> Self {
+        self.exclude_heights.extend(heights);
+        self
+    }
+
+    /// Validates the settings and creates the parent directory of the output file. The cache
+    /// file's directory is only created once the cache is written, so runs that never touch
+    /// the cache (`--no-cache`, `--dry-run`) leave it alone.
+    pub fn build(self) -> Result<MinerStatsConfig> {
+        let chain = self
+            .chain
+            .ok_or_else(|| Error::config("chain", "must be set"))?;
+        let cache_file = self
+            .cache_file
+            .ok_or_else(|| Error::config("cache_file", "must be set"))?;
+        let output_file = self
+            .output_file
+            .ok_or_else(|| Error::config("output_file", "must be set"))?;
+        if self.miners.is_empty() && self.address_map_file.is_none() {
+            return Err(Error::config(
+                "ufvks",
+                "config must contain at least one UFVK entry or set address_map_file",
+            ));
+        }
+        if let Some(miner) = self.miners.iter().find(|m| m.keys.is_empty()) {
+            return Err(Error::config(
+                "ufvks",
+                format!("entry '{}' has no keys", miner.label),
+            ));
+        }
+        if let Some(miner) = self.miners.iter().find(|m| m.transparent_scopes.is_empty()) {
+            return Err(Error::config(
+                "ufvks",
+                format!("entry '{}' has empty transparent_scopes", miner.label),
+            ));
+        }
+        if let Some(miner) = self.miners.iter().find(|m| {
+            m.start_height
+                .zip(m.end_height)
+                .is_some_and(|(start, end)| start > end)
+        }) {
+            return Err(Error::config(
+                "ufvks",
+                format!("entry '{}' has start_height above end_height", miner.label),
+            ));
+        }
+        check_duplicate_keys(&chain, &self.miners)?;
+        let warnings = check_duplicate_labels(&self.miners, self.strict_labels)?;
+        if self.rpc_batch_size == 0 {
+            return Err(Error::config("rpc_batch_size", "must be at least 1"));
+        }
+        if self.rpc_timeout_secs == 0 {
+            return Err(Error::config("rpc_timeout_secs", "must be at least 1"));
+        }
+        if self.rpc_fetch_timeout_secs == 0 {
+            return Err(Error::config(
+                "rpc_fetch_timeout_secs",
+                "must be at least 1",
+            ));
+        }
+        if self.cache_save_interval == 0 {
+            return Err(Error::config("cache_save_interval", "must be at least 1"));
+        }
+        if let Some(template) = &self.explorer_url_template {
+            check_explorer_url_template(template)?;
+        }
+        if self.webhook_on_threshold == 0 {
+            return Err(Error::config("webhook_on_threshold", "must be at least 1"));
+        }
+        if self.rpc_max_concurrent == 0 {
+            return Err(Error::config("rpc_max_concurrent", "must be at least 1"));
+        }
+        if self.window_blocks == Some(0) {
+            return Err(Error::config("window_blocks", "must be at least 1"));
+        }
+        if self.rolling_window_blocks == 0 {
+            return Err(Error::config("rolling_window_blocks", "must be at least 1"));
+        }
+        if self.rolling_window_step == 0 {
+            return Err(Error::config("rolling_window_step", "must be at least 1"));
+        }
+        if self.max_windows == 0 {
+            return Err(Error::config("max_windows", "must be at least 1"));
+        }
+        if self.cache_shard_size == Some(0) {
+            return Err(Error::config("cache_shard_size", "must be at least 1"));
+        }
+        if self.cache_shard_size.is_some() && !matches!(self.cache_backend, CacheBackendKind::Json)
+        {
+            return Err(Error::config(
+                "cache_shard_size",
+                "only applies to cache_backend = \"json\"",
+            ));
+        }
+        let address_map = match &self.address_map_file {
+            Some(path) => load_address_map(path)?,
+            None => HashMap::new(),
+        };
+        create_parent_dir(&output_file, "creating output file directory")?;
+        let rpc_urls = match (self.rpc_url, self.rpc_urls) {
+            (Some(_), urls) if !urls.is_empty() => {
+                return Err(Error::config(
+                    "rpc_url",
+                    "config must set either rpc_url or rpc_urls, not both",
+                ));
+            }
+            (Some(url), _) => vec![url],
+            (None, urls) if !urls.is_empty() => urls,
+            (None, _) => {
+                return Err(Error::config(
+                    "rpc_urls",
+                    "config must set rpc_url or rpc_urls",
+                ));
+            }
+        };
+        let rpc_auth = match (self.rpc_user, self.rpc_password) {
+            (Some(user), password) => Some(RpcAuth { user, password }),
+            (None, Some(_)) => {
+                return Err(Error::config(
+                    "rpc_password",
+                    "rpc_password is set without rpc_user",
+                ));
+            }
+            (None, None) => None,
+        };
+        let exclude_heights: BTreeSet<u64> = self.exclude_heights.into_iter().collect();
+        for &height in exclude_heights.range(..self.start_height) {
+            warn!(
+                height,
+                start_height = self.start_height,
+                "exclude_heights entry is below start_height; ignoring it"
+            );
+        }
+        let rpc_tls = match (self.rpc_ca_cert_pem, self.rpc_tls_insecure) {
+            (Some(_), true) => {
+                return Err(Error::config(
+                    "rpc_tls_insecure",
+                    "rpc_ca_cert_pem and rpc_tls_insecure are mutually exclusive; set only one",
+                ));
+            }
+            (Some(path), false) => RpcTls::CustomCa(path),
+            (None, true) => RpcTls::Insecure,
+            (None, false) => RpcTls::Default,
+        };
+        Ok(MinerStatsConfig {
+            start_height: self.start_height,
+            chain,
+            rpc_urls,
+            rpc_auth,
+            rpc_tls,
+            miners: self.miners,
+            cache_file,
+            output_file,
+            cache_backend: self.cache_backend,
+            compressed: self.compressed,
+            rpc_retry: self.rpc_retry,
+            rpc_batch_size: self.rpc_batch_size,
+            rpc_timeout: Duration::from_secs(self.rpc_timeout_secs),
+            rpc_fetch_timeout: Duration::from_secs(self.rpc_fetch_timeout_secs),
+            window_blocks: self.window_blocks,
+            compress_output: self.compress_output,
+            per_miner_output_dir: self.per_miner_output_dir,
+            metrics_addr: self.metrics_addr,
+            exclude_heights,
+            cache_save_interval: self.cache_save_interval,
+            prune_cache: self.prune_cache,
+            rpc_max_concurrent: self.rpc_max_concurrent,
+            warnings,
+            derived_address_cache: self.derived_address_cache,
+            statsd_addr: self.statsd_addr,
+            statsd_prefix: self.statsd_prefix,
+            systemd: self.systemd,
+            webhook_url: self.webhook_url,
+            webhook_on_threshold: self.webhook_on_threshold,
+            webhook_secret: self.webhook_secret,
+            explorer_url_template: self.explorer_url_template,
+            node_type: self.node_type,
+            summary_only: self.summary_only,
+            include_moving_avg: self.include_moving_avg,
+            rolling_window_blocks: self.rolling_window_blocks,
+            rolling_window_step: self.rolling_window_step,
+            max_windows: self.max_windows,
+            inactivity_threshold_blocks: self.inactivity_threshold_blocks,
+            include_histogram: self.include_histogram,
+            maturity_depth: self.maturity_depth,
+            fiat_currency: self.fiat_currency,
+            price_api_url: self.price_api_url,
+            fiat_rate: OnceLock::new(),
+            cache_format: self.cache_format,
+            cache_readonly: self.cache_readonly,
+            cache_shard_size: self.cache_shard_size.map(u64::from),
+            address_map,
+            block_annotations: self.block_annotations,
+            top_n_blocks: self.top_n_blocks,
+            include_day_stats: self.include_day_stats,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CacheBackendKind {
+    /// [`JsonFileCache`], optionally zstd-compressed.
+    #[default]
+    Json,
+    /// [`SqliteCache`], one row per block.
+    Sqlite,
+    /// [`NoOpCache`].
+    #[serde(rename = "none")]
+    Disabled,
+}
+
+/// Where the [`BlockCache`] is persisted between runs.
+pub trait CacheBackend: Send + Sync {
+    /// The stored cache, migrated in memory only, and whether it had an older schema; see
+    /// [`BlockCache::load`].
+    fn load(&self) -> Result<(BlockCache, bool)>;
+
+    fn save(&self, cache: &BlockCache) -> Result<()>;
+}
+
+/// The whole cache as one JSON or MessagePack document, optionally zstd-compressed.
+#[derive(Debug, Clone)]
+pub struct JsonFileCache {
+    pub path: PathBuf,
+    pub compressed: bool,
+    /// Encoding written on save; loading accepts either.
+    pub format: CacheFormat,
+    /// `cache_readonly`: never write the file.
+    pub readonly: bool,
+}
+
+impl CacheBackend for JsonFileCache {
+    fn load(&self) -> Result<(BlockCache, bool)> {
+        BlockCache::load(&self.path)
+    }
+
+    fn save(&self, cache: &BlockCache) -> Result<()> {
+        if self.readonly {
+            warn_readonly_save(&self.path);
+            return Ok(());
+        }
+        cache.save_as(&self.path, self.format, self.compressed)
+    }
+}
+
+/// What a `cache_readonly` backend logs instead of saving.
+pub(crate) fn warn_readonly_save(path: &Path) {
+    warn!(
+        "cache_readonly is set; not saving the cache to {}",
+        path.display()
+    );
+}
+
+/// Encoding of a [`JsonFileCache`] file (`cache_format`). MessagePack caches are smaller and
+/// faster to read and write; JSON ones can be inspected with a text editor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheFormat {
+    #[default]
+    Json,
+    Msgpack,
+}
+
+impl CacheFormat {
+    /// A cache is a map: JSON files start with `{`, MessagePack ones with a map marker
+    /// (`0x80`-`0x8f`, `0xde` or `0xdf`), which is never valid leading JSON.
+    fn detect(bytes: &[u8]) -> Self {
+        match bytes.first() {
+            Some(0x80..=0x8f | 0xde | 0xdf) => CacheFormat::Msgpack,
+            _ => CacheFormat::Json,
+        }
+    }
+}
+
+/// Keeps nothing: every run starts from an empty cache and fetches the full range.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoOpCache;
+
+impl CacheBackend for NoOpCache {
+    fn load(&self) -> Result<(BlockCache, bool)> {
+        Ok((BlockCache::default(), false))
+    }
+
+    fn save(&self, _cache: &BlockCache) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Schema version written to new caches; see [`BlockCache::migrate`].
+pub const CACHE_VERSION: u32 = 1;
+
+/// Coinbase data of already fetched blocks, keyed by height.
+#[derive(Serialize, Deserialize)]
+pub struct BlockCache {
+    /// Schema version; `0` for caches written before the field existed.
+    #[serde(default)]
+    pub version: u32,
+    /// Node tip at the end of the last sync.
+    #[serde(default, deserialize_with = "deserialize_opt_height")]
+    pub last_tip: Option<u64>,
+    pub blocks: BTreeMap<u64, CachedBlock>,
+}
+
+/// A [`BlockCache`] shared between the thread syncing it and threads reading it, e.g. to
+/// compute statistics while `--watch` fetches new blocks; see [`sync_shared_cache`].
+pub type SharedCache = Arc<RwLock<BlockCache>>;
+
+/// Read access to a shared cache. A panic while the lock was held could at worst have left
+/// a segment of fetched blocks half inserted, which the next sync completes, so poisoning is
+/// ignored.
+pub(crate) fn read_cache(cache: &RwLock<BlockCache>) -> RwLockReadGuard<'_, BlockCache> {
+    cache.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Write access to a shared cache; see [`read_cache`].
+pub(crate) fn write_cache(cache: &RwLock<BlockCache>) -> RwLockWriteGuard<'_, BlockCache> {
+    cache.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs `f` on `cache` moved into a lock of its own, for the `&mut BlockCache` wrappers of
+/// functions working on shared caches.
+pub(crate) fn with_shared<T>(
+    cache: &mut BlockCache,
+    f: impl FnOnce(&RwLock<BlockCache>) -> T,
+) -> T {
+    let shared = RwLock::new(std::mem::take(cache));
+    let result = f(&shared);
+    *cache = shared.into_inner().unwrap_or_else(PoisonError::into_inner);
+    result
+}
+
+/// Header prepended to zstd-compressed cache files so `load` can tell them apart from
+/// plain JSON caches written by older versions.
+///
+/// The `cache_round_trip/10k_blocks` benchmark compares save and load times of plain and
+/// compressed caches and prints both file sizes.
+const COMPRESSED_CACHE_MAGIC: &[u8; 4] = b"UMSZ";
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self {
+            version: CACHE_VERSION,
+            last_tip: None,
+            blocks: BTreeMap::new(),
+        }
+    }
+}
+
+impl BlockCache {
+    /// Reads a plain or zstd-compressed cache in either [`CacheFormat`]; a missing file
+    /// yields an empty cache. A cache with an older schema is migrated in memory only; the
+    /// returned flag tells whether it was, so the caller can save it back unless it must
+    /// leave the file alone (`--dry-run`, `cache_readonly`, inspecting subcommands).
+    pub fn load(path: &Path) -> Result<(Self, bool)> {
+        if !path.exists() {
+            return Ok((Self::default(), false));
+        }
+        let raw = fs::read(path).map_err(Error::io("reading cache", path))?;
+        let compressed = raw.starts_with(COMPRESSED_CACHE_MAGIC);
+        let bytes = if compressed {
+            zstd::decode_all(&raw[COMPRESSED_CACHE_MAGIC.len()..])
+                .map_err(Error::io("decompressing cache", path))?
+        } else {
+            raw
+        };
+        let format = CacheFormat::detect(&bytes);
+        let mut cache: Self = match format {
+            CacheFormat::Json => {
+                serde_json::from_slice(&bytes).map_err(|source| Error::CacheCorrupt {
+                    path: path.to_path_buf(),
+                    source,
+                })?
+            }
+            CacheFormat::Msgpack => {
+                rmp_serde::from_slice(&bytes).map_err(|source| Error::CacheCorruptMsgpack {
+                    path: path.to_path_buf(),
+                    source,
+                })?
+            }
+        };
+        let migrated = cache.migrate(path)?;
+        Ok((cache, migrated))
+    }
+
+    /// Brings a cache read from `path` up to [`CACHE_VERSION`] and returns whether anything
+    /// changed. Fields added by later versions are filled in by their serde defaults while
+    /// parsing (e.g. `time: 0` for "unknown", re-fetched once by [`sync_cache`]); each step
+    /// here only has to handle what serde cannot.
+    pub fn migrate(&mut self, path: &Path) -> Result<bool> {
+        if self.version > CACHE_VERSION {
+            return Err(Error::CacheVersionUnsupported {
+                path: path.to_path_buf(),
+                found: self.version,
+                supported: CACHE_VERSION,
+            });
+        }
+        if self.version == CACHE_VERSION {
+            return Ok(false);
+        }
+        // 0 -> 1: only the `version` field itself was added.
+        info!(
+            from = self.version,
+            to = CACHE_VERSION,
+            "migrating cache {}",
+            path.display()
+        );
+        self.version = CACHE_VERSION;
+        Ok(true)
+    }
+
+    /// Maximal runs of heights in `start..=end` without a cached block, lowest first.
+    pub fn coverage_gaps(&self, start: u64, end: u64) -> Vec<RangeInclusive<u64>> {
+        let mut gaps = Vec::new();
+        if start > end {
+            return gaps;
+        }
+        let mut next = start;
+        for &height in self.blocks.range(start..=end).map(|(height, _)| height) {
+            if height > next {
+                gaps.push(next..=height - 1);
+            }
+            match height.checked_add(1) {
+                Some(after) => next = after,
+                None => return gaps,
+            }
+        }
+        if next <= end {
+            gaps.push(next..=end);
+        }
+        gaps
+    }
+
+    /// Writes the cache as JSON; see [`save_as`](Self::save_as).
+    pub fn save(&self, path: &Path, compressed: bool) -> Result<()> {
+        self.save_as(path, CacheFormat::Json, compressed)
+    }
+
+    pub fn save_as(&self, path: &Path, format: CacheFormat, compressed: bool) -> Result<()> {
+        write_cache_file(self, path, format, compressed)
+    }
+}
+
+/// Writes `cache` (a [`BlockCache`] or a view with the same fields) to `path` in `format`,
+/// behind [`COMPRESSED_CACHE_MAGIC`] when `compressed`.
+pub(crate) fn write_cache_file<T: Serialize>(
+    cache: &T,
+    path: &Path,
+    format: CacheFormat,
+    compressed: bool,
+) -> Result<()> {
+    let encoded = match format {
+        CacheFormat::Json => {
+            serde_json::to_vec_pretty(cache).map_err(|source| Error::Serialize {
+                what: format!("cache {}", path.display()),
+                source,
+            })?
+        }
+        // Field names are kept so `#[serde(default)]` fields can be added and skipped
+        // as in JSON; the positional encoding of `to_vec` cannot leave fields out.
+        CacheFormat::Msgpack => {
+            rmp_serde::to_vec_named(cache).map_err(|source| Error::CacheEncode {
+                path: path.to_path_buf(),
+                source,
+            })?
+        }
+    };
+    let bytes = if compressed {
+        let mut bytes = COMPRESSED_CACHE_MAGIC.to_vec();
+        bytes.extend(
+            zstd::encode_all(encoded.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL)
+                .map_err(Error::io("compressing cache", path))?,
+        );
+        bytes
+    } else {
+        encoded
+    };
+    create_parent_dir(path, "creating cache file directory")?;
+    write_atomic(path, &bytes)
+}
+
+/// Creates the directory `path` will be written to, if it has one.
+pub(crate) fn create_parent_dir(path: &Path, what: &'static str) -> Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            fs::create_dir_all(parent).map_err(Error::io(what, parent))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Writes `contents` to a sibling `<path>.tmp` file and renames it over `path`, so an
+/// interrupted write never leaves a truncated file behind. The temp file is synced before
+/// the rename: otherwise a crash right after it can still leave `path` empty on filesystems
+/// that reorder data and metadata writes (ext4, xfs). Falls back to copy-then-delete when
+/// the rename crosses a mount point.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+
+    let written = fs::File::create(&tmp_path).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(err) = written {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(Error::io("writing temp file", &tmp_path)(err));
+    }
+    match fs::rename(&tmp_path, path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+            let copied = fs::copy(&tmp_path, path);
+            let _ = fs::remove_file(&tmp_path);
+            copied
+                .map(|_| ())
+                .map_err(Error::io("copying temp file over", path))
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&tmp_path);
+            Err(Error::io("renaming temp file over", path)(err))
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CachedBlock {
+    #[serde(deserialize_with = "deserialize_height")]
+    pub height: u64,
+    pub hash: String,
+    /// Block header timestamp (Unix seconds); `0` for blocks cached by older versions.
+    #[serde(default)]
+    pub time: u32,
+    /// Outputs of the coinbase transaction.
+    pub outputs: Vec<CoinbaseOutput>,
+    /// Block subsidy in zats, recorded when fetched; the rest of the coinbase value is fees.
+    /// `None` for blocks cached by older versions, which fall back to [`block_subsidy`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subsidy_zat: Option<i64>,
+}
+
+impl CachedBlock {
+    /// Whether the entry carries every field the current version records. Incomplete
+    /// entries are re-fetched once on the next run.
+    pub fn is_complete(&self) -> bool {
+        self.time != 0
+    }
+}
+
+/// Accepts heights serialized as any non-negative integer, so caches written while heights
+/// were `u32` keep loading now that they are `u64`.
+struct HeightVisitor;
+
+impl<'de> Visitor<'de> for HeightVisitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a non-negative block height")
+    }
+
+    fn visit_u32<E: de::Error>(self, value: u32) -> Result<u64, E> {
+        Ok(u64::from(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<u64, E> {
+        Ok(value)
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<u64, E> {
+        u64::try_from(value).map_err(|_| E::invalid_value(Unexpected::Signed(value), &self))
+    }
+}
+
+/// `Option` wrapper around [`HeightVisitor`] for `BlockCache::last_tip`.
+struct OptHeightVisitor;
+
+impl<'de> Visitor<'de> for OptHeightVisitor {
+    type Value = Option<u64>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a non-negative block height or null")
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Option<u64>, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Option<u64>, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Option<u64>, D::Error> {
+        deserialize_height(deserializer).map(Some)
+    }
+}
+
+fn deserialize_height<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    deserializer.deserialize_u64(HeightVisitor)
+}
+
+fn deserialize_opt_height<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    deserializer.deserialize_option(OptHeightVisitor)
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CoinbaseOutput {
+    pub value_zat: i64,
+    pub addresses: Vec<String>,
+    /// Raw `scriptPubKey` of an output the node listed no addresses for (e.g. some P2SH or
+    /// bare multisig scripts). [`NodeRpcClient`] fills `addresses` from `decodescript` when
+    /// it fetches the block.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hex: Option<String>,
+}
+
+/// Retry behaviour for transient RPC failures (connection errors, HTTP 5xx and
+/// JSON-RPC "still loading" responses).
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total attempts per call, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubled on every subsequent attempt.
+    pub base_delay_ms: u64,
+    /// Upper bound of the random delay added to each backoff.
+    pub jitter_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay_ms: 500,
+            jitter_ms: 250,
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay_ms
+            .saturating_mul(1u64 << (attempt - 1).min(16));
+        let jitter = if self.jitter_ms == 0 {
+            0
+        } else {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.subsec_nanos() as u64)
+                .unwrap_or_default();
+            nanos % (self.jitter_ms + 1)
+        };
+        Duration::from_millis(exponential.saturating_add(jitter))
+    }
+}
+
+/// JSON-RPC error codes that indicate the node is temporarily unable to serve requests.
+/// `-28` is returned by zcashd while it is still loading the block index.
+const RETRYABLE_RPC_CODES: &[i64] = &[-28];
+
+/// Outcome of a single failed RPC attempt, classified by whether retrying can help.
+enum RpcAttemptError {
+    /// Connection failures, HTTP 5xx without a JSON-RPC error body and retryable JSON-RPC
+    /// codes: try the next endpoint and retry with backoff.
+    Transient(Error),
+    /// Other HTTP errors: try the next endpoint, but retrying the same set will not help.
+    Endpoint(Error),
+    /// The node answered with an error that no other node or retry would change.
+    Fatal(Error),
+}
+
+impl RpcAttemptError {
+    fn error(&self) -> &Error {
+        match self {
+            Self::Transient(err) | Self::Endpoint(err) | Self::Fatal(err) => err,
+        }
+    }
+}
+
+/// HTTP basic-auth credentials sent with every RPC request.
+#[derive(Clone)]
+pub struct RpcAuth {
+    pub user: String,
+    pub password: Option<String>,
+}
+
+impl std::fmt::Debug for RpcAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RpcAuth")
+            .field("user", &self.user)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+/// How HTTPS certificates of the RPC endpoints are verified.
+#[derive(Debug, Clone, Default)]
+pub enum RpcTls {
+    /// System trust roots only.
+    #[default]
+    Default,
+    /// System trust roots plus the PEM certificate at this path.
+    CustomCa(PathBuf),
+    /// No certificate verification at all.
+    Insecure,
+}
+
+#[derive(Debug, Clone)]
+pub struct RpcClientOptions {
+    pub auth: Option<RpcAuth>,
+    pub tls: RpcTls,
+    pub retry: RetryPolicy,
+    /// Per-request timeout for lightweight calls.
+    pub timeout: Duration,
+    /// Per-request timeout for block fetches.
+    pub fetch_timeout: Duration,
+    /// Most requests in flight at once; see [`RpcClient::max_concurrent`].
+    pub max_concurrent: usize,
+    /// Receives a count of every failed RPC attempt.
+    pub metrics: Option<Arc<Metrics>>,
+    /// Network of the node, whose subsidy schedule is recorded with each fetched block.
+    pub chain: ChainType,
+    /// How to decode `getblock`; detected with `getinfo` on first use when `None`.
+    pub node_type: Option<NodeType>,
+}
+
+/// Node software serving the JSON-RPC endpoints, which decides how `getblock` replies are
+/// decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeType {
+    Zcashd,
+    Zebrad,
+}
+
+impl NodeType {
+    /// Decodes a verbosity-2 `getblock` result as this node formats it and returns the
+    /// outputs of the block's coinbase transaction.
+    pub fn coinbase_outputs(self, getblock_result: &str) -> Result<Vec<CoinbaseOutput>> {
+        let protocol = |e: serde_json::Error| Error::RpcProtocol {
+            method: "getblock".to_string(),
+            reason: e.to_string(),
+        };
+        let block = match self {
+            NodeType::Zcashd => serde_json::from_str::<BlockResult>(getblock_result),
+            NodeType::Zebrad => {
+                serde_json::from_str::<ZebradBlockResult>(getblock_result).map(BlockResult::from)
+            }
+        }
+        .map_err(protocol)?;
+        Ok(block.coinbase_outputs())
+    }
+}
+
+/// Source of chain data for [`sync_cache`]. Only `block_count` and `fetch_block` are
+/// required; clients that can batch requests override the other methods.
+pub trait RpcClient: Sync {
+    fn block_count(&self) -> Result<u64>;
+
+    fn fetch_block(&self, height: u64) -> Result<CachedBlock>;
+
+    /// Most calls [`sync_cache`] and [`cache_repair::repair_cache`] issue at once. Each
+    /// parallel fetch worker holds one slot per call, so a shared node never sees more
+    /// simultaneous requests than this. Unlimited by default.
+    fn max_concurrent(&self) -> usize {
+        usize::MAX
+    }
+
+    fn fetch_blocks(&self, heights: &[u64]) -> Result<Vec<CachedBlock>> {
+        heights
+            .iter()
+            .map(|height| self.fetch_block(*height))
+            .collect()
+    }
+
+    /// Returns the heights among `heights` whose cached block hash no longer matches the
+    /// client's chain.
+    fn verify_block_hashes(&self, heights: &[u64], cache: &BlockCache) -> Result<Vec<u64>> {
+        let mut reorged = Vec::new();
+        for &height in heights {
+            let Some(cached) = cache.blocks.get(&height) else {
+                continue;
+            };
+            if self.fetch_block(height)?.hash != cached.hash {
+                reorged.push(height);
+            }
+        }
+        Ok(reorged)
+    }
+}
+
+/// Blocking JSON-RPC client for zcashd/zebrad with endpoint failover and retries. Clones
+/// share failover and batch-support state.
+#[derive(Clone)]
+pub struct NodeRpcClient {
+    client: reqwest::blocking::Client,
+    urls: Vec<String>,
+    auth: Option<RpcAuth>,
+    /// Index into `urls` of the endpoint that answered last; shared between clones so every
+    /// worker sticks to the same node until it fails.
+    primary: Arc<AtomicUsize>,
+    /// Cleared the first time a node rejects a batch request.
+    batch_supported: Arc<AtomicBool>,
+    retry: RetryPolicy,
+    timeout: Duration,
+    fetch_timeout: Duration,
+    max_concurrent: usize,
+    metrics: Option<Arc<Metrics>>,
+    chain: ChainType,
+    /// Set from [`RpcClientOptions::node_type`], or by the first `getblock` after asking
+    /// the node; shared between clones.
+    node_type: Arc<OnceLock<NodeType>>,
+}
+
+impl NodeRpcClient {
+    pub fn new(urls: Vec<String>, options: RpcClientOptions) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(Error::config(
+                "rpc_urls",
+                "at least one RPC endpoint is required",
+            ));
+        }
+        for url in &urls {
+            let parsed = reqwest::Url::parse(url)
+                .map_err(|e| Error::config("rpc_urls", format!("{url}: {e}")))?;
+            if !parsed.username().is_empty() || parsed.password().is_some() {
+                warn!(
+                    "credentials embedded in rpc_url are deprecated and visible in process \
+                     listings; use rpc_user/rpc_password instead"
+                );
+            }
+        }
+        let mut builder = reqwest::blocking::Client::builder();
+        match &options.tls {
+            RpcTls::Default => {}
+            RpcTls::CustomCa(path) => {
+                let pem = fs::read(path).map_err(Error::io("reading RPC CA certificate", path))?;
+                let cert = reqwest::Certificate::from_pem(&pem).map_err(|source| {
+                    Error::TlsCertificate {
+                        path: path.clone(),
+                        source,
+                    }
+                })?;
+                builder = builder.add_root_certificate(cert);
+            }
+            RpcTls::Insecure => {
+                warn!("TLS certificate verification is disabled (rpc_tls_insecure)");
+                builder = builder.danger_accept_invalid_certs(true);
+            }
+        }
+        let client = builder.build().map_err(Error::HttpClient)?;
+        Ok(Self {
+            client,
+            urls,
+            auth: options.auth,
+            primary: Arc::new(AtomicUsize::new(0)),
+            batch_supported: Arc::new(AtomicBool::new(true)),
+            retry: options.retry,
+            timeout: options.timeout,
+            fetch_timeout: options.fetch_timeout,
+            max_concurrent: options.max_concurrent.max(1),
+            metrics: options.metrics,
+            chain: options.chain,
+            node_type: Arc::new(options.node_type.map(OnceLock::from).unwrap_or_default()),
+        })
+    }
+
+    fn node_type(&self) -> NodeType {
+        *self.node_type.get_or_init(|| self.detect_node_type())
+    }
+
+    /// Zebra reports itself as `/Zebra:<version>/` in the `subversion` field of `getinfo`.
+    /// Anything else, including a failed `getinfo`, is treated as zcashd.
+    fn detect_node_type(&self) -> NodeType {
+        let info = match self.call_method::<serde_json::Value>(
+            "getinfo",
+            serde_json::json!([]),
+            self.timeout,
+        ) {
+            Ok(info) => info,
+            Err(err) => {
+                warn!("getinfo failed ({err}); assuming the node is zcashd");
+                return NodeType::Zcashd;
+            }
+        };
+        let node_type = if info["subversion"]
+            .as_str()
+            .is_some_and(|version| version.contains("Zebra"))
+        {
+            NodeType::Zebrad
+        } else {
+            NodeType::Zcashd
+        };
+        info!(?node_type, "detected RPC node type");
+        node_type
+    }
+
+    /// `getblock` at verbosity 2, decoded for the node type.
+    fn get_block(&self, hash: &str) -> Result<BlockResult> {
+        let params = serde_json::json!([hash, 2]);
+        match self.node_type() {
+            NodeType::Zcashd => self.call_method("getblock", params, self.fetch_timeout),
+            NodeType::Zebrad => self
+                .call_method::<ZebradBlockResult>("getblock", params, self.fetch_timeout)
+                .map(BlockResult::from),
+        }
+    }
+
+    /// Addresses of the raw script `hex`, as the node's `decodescript` reports them.
+    pub fn decode_script(&self, hex: &str) -> Result<Vec<String>> {
+        self.call_method::<DecodedScript>("decodescript", serde_json::json!([hex]), self.timeout)
+            .map(|script| script.addresses)
+    }
+
+    /// `block` as cached, with the addresses of outputs that came without any decoded from
+    /// their script. A script the node cannot decode leaves the output unmatched, with a
+    /// warning, rather than failing the fetch.
+    fn cached_block(&self, block: BlockResult, height: u64, hash: String) -> CachedBlock {
+        let mut cached = block.into_cached(height, hash, &self.chain);
+        for output in &mut cached.outputs {
+            let Some(hex) = output
+                .hex
+                .as_deref()
+                .filter(|_| output.addresses.is_empty())
+            else {
+                continue;
+            };
+            match self.decode_script(hex) {
+                Ok(addresses) => output.addresses = addresses,
+                Err(err) => warn!(height, "decoding coinbase script {hex}: {}", err.chain()),
+            }
+        }
+        cached
+    }
+
+    /// Batched [`get_block`](Self::get_block); `None` when the node rejects batches.
+    fn get_blocks(&self, hashes: &[String]) -> Result<Option<Vec<BlockResult>>> {
+        let params = hashes.iter().map(|h| serde_json::json!([h, 2])).collect();
+        Ok(match self.node_type() {
+            NodeType::Zcashd => self.call_batch("getblock", params, self.fetch_timeout)?,
+            NodeType::Zebrad => self
+                .call_batch::<ZebradBlockResult>("getblock", params, self.fetch_timeout)?
+                .map(|blocks| blocks.into_iter().map(BlockResult::from).collect()),
+        })
+    }
+
+    fn call_method<T: for<'a> Deserialize<'a>>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+        timeout: Duration,
+    ) -> Result<T> {
+        let request = RpcRequest {
+            jsonrpc: "2.0",
+            id: serde_json::Value::from(RPC_REQUEST_ID),
+            method,
+            params,
+        };
+        self.with_retry(method, |url| {
+            let response = self.send(url, method, &request, timeout)?;
+            let rpc_response: RpcResponse<T> = Self::decode(method, response)?;
+            rpc_response.into_result(method)
+        })
+    }
+
+    /// Sends one JSON-RPC batch with a request per entry of `params` and returns the results
+    /// in the same order, or `None` if the node answered HTTP 400 (no batch support).
+    fn call_batch<T: for<'a> Deserialize<'a>>(
+        &self,
+        method: &str,
+        params: Vec<serde_json::Value>,
+        timeout: Duration,
+    ) -> Result<Option<Vec<T>>> {
+        let requests: Vec<RpcRequest> = params
+            .into_iter()
+            .enumerate()
+            .map(|(index, params)| RpcRequest {
+                jsonrpc: "2.0",
+                id: serde_json::Value::from(index),
+                method,
+                params,
+            })
+            .collect();
+        self.with_retry(method, |url| {
+            let response = self.send(url, method, &requests, timeout)?;
+            if response.status() == reqwest::StatusCode::BAD_REQUEST {
+                return Ok(None);
+            }
+            let responses: Vec<RpcResponse<T>> = Self::decode(method, response)?;
+            let mut results: Vec<Option<T>> = (0..requests.len()).map(|_| None).collect();
+            for response in responses {
+                let slot = response
+                    .id
+                    .as_u64()
+                    .and_then(|id| results.get_mut(id as usize))
+                    .ok_or_else(|| {
+                        RpcAttemptError::Fatal(Error::RpcProtocol {
+                            method: method.to_string(),
+                            reason: format!("batch returned unexpected id {}", response.id),
+                        })
+                    })?;
+                *slot = Some(response.into_result(method)?);
+            }
+            results
+                .into_iter()
+                .enumerate()
+                .map(|(index, result)| {
+                    result.ok_or_else(|| {
+                        RpcAttemptError::Fatal(Error::RpcProtocol {
+                            method: method.to_string(),
+                            reason: format!("batch is missing the response for request {index}"),
+                        })
+                    })
+                })
+                .collect::<std::result::Result<Vec<T>, _>>()
+                .map(Some)
+        })
+    }
+
+    /// Runs `attempt` against the endpoints, retrying transient failures with backoff.
+    fn with_retry<T>(
+        &self,
+        method: &str,
+        attempt: impl Fn(&str) -> std::result::Result<T, RpcAttemptError>,
+    ) -> Result<T> {
+        let max_attempts = self.retry.max_attempts.max(1);
+        let mut attempt_number = 1;
+        loop {
+            let err = match self.call_any_endpoint(method, &attempt) {
+                Ok(result) => return Ok(result),
+                Err(RpcAttemptError::Transient(err)) => err,
+                Err(RpcAttemptError::Endpoint(err) | RpcAttemptError::Fatal(err)) => {
+                    return Err(err);
+                }
+            };
+            if attempt_number >= max_attempts {
+                return Err(Error::RpcRetriesExhausted {
+                    method: method.to_string(),
+                    attempts: max_attempts,
+                    source: Box::new(err),
+                });
+            }
+            let delay = self.retry.backoff(attempt_number);
+            warn!(
+                rpc_method = method,
+                attempt = attempt_number,
+                max_attempts,
+                delay_ms = delay.as_millis() as u64,
+                "RPC call failed, retrying: {}",
+                err.chain()
+            );
+            std::thread::sleep(delay);
+            attempt_number += 1;
+        }
+    }
+
+    /// Tries every endpoint once, starting from the current primary, and promotes the first
+    /// one that answers.
+    fn call_any_endpoint<T>(
+        &self,
+        method: &str,
+        attempt: &impl Fn(&str) -> std::result::Result<T, RpcAttemptError>,
+    ) -> std::result::Result<T, RpcAttemptError> {
+        let primary = self.primary.load(Ordering::Relaxed);
+        let mut failure = None;
+        for offset in 0..self.urls.len() {
+            let index = (primary + offset) % self.urls.len();
+            let url = &self.urls[index];
+            match attempt(url) {
+                Ok(result) => {
+                    if index != primary {
+                        info!(rpc_method = method, url = %url, "switching primary RPC endpoint");
+                        self.primary.store(index, Ordering::Relaxed);
+                    }
+                    return Ok(result);
+                }
+                Err(err @ RpcAttemptError::Fatal(_)) => {
+                    self.record_error();
+                    return Err(err);
+                }
+                Err(err) => {
+                    self.record_error();
+                    if self.urls.len() > 1 {
+                        warn!(
+                            rpc_method = method,
+                            url = %url,
+                            "RPC endpoint failed: {}",
+                            err.error().chain()
+                        );
+                    }
+                    // Remember a transient failure over an endpoint one so the call is retried.
+                    failure = match (failure, err) {
+                        (Some(transient @ RpcAttemptError::Transient(_)), _) => Some(transient),
+                        (_, err) => Some(err),
+                    };
+                }
+            }
+        }
+        Err(failure.unwrap_or_else(|| {
+            RpcAttemptError::Fatal(Error::config("rpc_urls", "no RPC endpoints configured"))
+        }))
+    }
+
+    /// [`RpcClient::fetch_blocks`] without the metrics.
+    fn fetch_batch(&self, heights: &[u64]) -> Result<Vec<CachedBlock>> {
+        if self.batch_supported.load(Ordering::Relaxed) {
+            let hash_params = heights.iter().map(|h| serde_json::json!([h])).collect();
+            if let Some(hashes) =
+                self.call_batch::<String>("getblockhash", hash_params, self.fetch_timeout)?
+            {
+                if let Some(blocks) = self.get_blocks(&hashes)? {
+                    return Ok(heights
+                        .iter()
+                        .zip(hashes)
+                        .zip(blocks)
+                        .map(|((height, hash), block)| self.cached_block(block, *height, hash))
+                        .collect());
+                }
+            }
+            if self.batch_supported.swap(false, Ordering::Relaxed) {
+                warn!("RPC node rejected batch requests; falling back to individual calls");
+            }
+        }
+        heights
+            .iter()
+            .map(|height| self.fetch_block(*height))
+            .collect()
+    }
+
+    fn record_error(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.inc_rpc_errors();
+        }
+    }
+
+    #[instrument(
+        name = "rpc request",
+        level = "debug",
+        skip_all,
+        fields(rpc_method = method, rpc_url = url)
+    )]
+    fn send<B: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        method: &str,
+        body: &B,
+        timeout: Duration,
+    ) -> std::result::Result<reqwest::blocking::Response, RpcAttemptError> {
+        let mut request = self.client.post(url).timeout(timeout).json(body);
+        if let Some(auth) = &self.auth {
+            request = request.basic_auth(&auth.user, auth.password.as_ref());
+        }
+        let started = Instant::now();
+        let response = request.send();
+        if let Some(metrics) = &self.metrics {
+            metrics.record_rpc_duration(started.elapsed());
+        }
+        response.map_err(|source| {
+            RpcAttemptError::Transient(Error::RpcTransport {
+                method: method.to_string(),
+                source,
+            })
+        })
+    }
+
+    fn decode<R: for<'a> Deserialize<'a>>(
+        method: &str,
+        response: reqwest::blocking::Response,
+    ) -> std::result::Result<R, RpcAttemptError> {
+        let status = response.status();
+        if !status.is_success() {
+            // zcashd answers every JSON-RPC error with HTTP 500; the error's code, not the
+            // status, tells whether retrying can help.
+            if let Ok(body) = response.bytes()
+                && carries_rpc_error(&body)
+                && let Ok(decoded) = serde_json::from_slice(&body)
+            {
+                return Ok(decoded);
+            }
+            let error = Error::RpcHttpStatus {
+                method: method.to_string(),
+                status,
+            };
+            return Err(if status.is_server_error() {
+                RpcAttemptError::Transient(error)
+            } else {
+                RpcAttemptError::Endpoint(error)
+            });
+        }
+        response.json().map_err(|source| {
+            RpcAttemptError::Fatal(Error::RpcTransport {
+                method: method.to_string(),
+                source,
+            })
+        })
+    }
+}
+
+impl RpcClient for NodeRpcClient {
+    fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    fn block_count(&self) -> Result<u64> {
+        self.call_method::<u64>("getblockcount", serde_json::json!([]), self.timeout)
+    }
+
+    fn fetch_block(&self, height: u64) -> Result<CachedBlock> {
+        let hash: String = self.call_method(
+            "getblockhash",
+            serde_json::json!([height]),
+            self.fetch_timeout,
+        )?;
+        let block = self.get_block(&hash)?;
+        Ok(self.cached_block(block, height, hash))
+    }
+
+    /// Fetches `heights` with one batched `getblockhash` request followed by one batched
+    /// `getblock` request. Nodes that reject batches with HTTP 400 are remembered and served
+    /// with individual calls from then on.
+    fn fetch_blocks(&self, heights: &[u64]) -> Result<Vec<CachedBlock>> {
+        let blocks = self.fetch_batch(heights)?;
+        if let Some(metrics) = &self.metrics {
+            metrics.add_blocks_fetched(blocks.len());
+        }
+        Ok(blocks)
+    }
+
+    /// Re-queries the node's hash for each of `heights` and returns the heights whose cached
+    /// block hash no longer matches the canonical chain.
+    fn verify_block_hashes(&self, heights: &[u64], cache: &BlockCache) -> Result<Vec<u64>> {
+        let mut node_hashes = None;
+        if self.batch_supported.load(Ordering::Relaxed) {
+            let params = heights.iter().map(|h| serde_json::json!([h])).collect();
+            node_hashes = self.call_batch::<String>("getblockhash", params, self.fetch_timeout)?;
+            if node_hashes.is_none() && self.batch_supported.swap(false, Ordering::Relaxed) {
+                warn!("RPC node rejected batch requests; falling back to individual calls");
+            }
+        }
+        let node_hashes = match node_hashes {
+            Some(hashes) => hashes,
+            None => heights
+                .iter()
+                .map(|h| {
+                    self.call_method::<String>(
+                        "getblockhash",
+                        serde_json::json!([h]),
+                        self.fetch_timeout,
+                    )
+                })
+                .collect::<Result<_>>()?,
+        };
+        Ok(heights
+            .iter()
+            .zip(node_hashes)
+            .filter(|(height, hash)| {
+                cache
+                    .blocks
+                    .get(*height)
+                    .is_some_and(|block| &block.hash != hash)
+            })
+            .map(|(height, _)| *height)
+            .collect())
+    }
+}
+
+/// Whether `body` is a JSON-RPC response, or batch of them, with an `error` object.
+fn carries_rpc_error(body: &[u8]) -> bool {
+    let has_error =
+        |response: &serde_json::Value| response.get("error").is_some_and(|e| e.is_object());
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(serde_json::Value::Array(responses)) => responses.iter().any(has_error),
+        Ok(response) => has_error(&response),
+        Err(_) => false,
+    }
+}
+
+/// Identifier sent with single (non-batch) JSON-RPC requests.
+const RPC_REQUEST_ID: &str = "zingo-miner-stats";
+
+#[derive(Serialize)]
+struct RpcRequest<'a> {
+    jsonrpc: &'a str,
+    id: serde_json::Value,
+    method: &'a str,
+    params: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcError>,
+    id: serde_json::Value,
+}
+
+impl<T> RpcResponse<T> {
+    fn into_result(self, method: &str) -> std::result::Result<T, RpcAttemptError> {
+        if let Some(err) = self.error {
+            let retryable = RETRYABLE_RPC_CODES.contains(&err.code);
+            let error = Error::RpcError {
+                method: method.to_string(),
+                code: err.code,
+                message: err.message,
+            };
+            return Err(if retryable {
+                RpcAttemptError::Transient(error)
+            } else {
+                RpcAttemptError::Fatal(error)
+            });
+        }
+        self.result.ok_or_else(|| {
+            RpcAttemptError::Fatal(Error::RpcProtocol {
+                method: method.to_string(),
+                reason: "returned no result".to_string(),
+            })
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct BlockResult {
+    hash: String,
+    height: u64,
+    time: u32,
+    tx: Vec<BlockTx>,
+}
+
+impl BlockResult {
+    fn into_cached(self, height: u64, hash: String, chain: &ChainType) -> CachedBlock {
+        CachedBlock {
+            height,
+            hash,
+            time: self.time,
+            outputs: self.coinbase_outputs(),
+            subsidy_zat: Some(block_subsidy(height, chain)),
+        }
+    }
+
+    fn coinbase_outputs(&self) -> Vec<CoinbaseOutput> {
+        self.tx
+            .first()
+            .map(|tx| {
+                tx.vout
+                    .iter()
+                    .map(|vout| {
+                        let addresses = vout.script_pub_key.addresses.clone().unwrap_or_default();
+                        CoinbaseOutput {
+                            value_zat: vout.value_zat,
+                            // Only kept where it is needed to find the addresses.
+                            hex: vout
+                                .script_pub_key
+                                .hex
+                                .clone()
+                                .filter(|_| addresses.is_empty()),
+                            addresses,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize)]
+struct BlockTx {
+    vout: Vec<BlockVout>,
+}
+
+#[derive(Deserialize)]
+struct BlockVout {
+    #[serde(rename = "valueZat")]
+    value_zat: i64,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: ScriptPubKey,
+}
+
+#[derive(Deserialize)]
+struct ScriptPubKey {
+    addresses: Option<Vec<String>>,
+    #[serde(default)]
+    hex: Option<String>,
+}
+
+/// The part of a `decodescript` result the scanner uses.
+#[derive(Deserialize)]
+struct DecodedScript {
+    #[serde(default)]
+    addresses: Vec<String>,
+}
+
+/// `getblock` as zebrad returns it: the block time may be missing, and outputs may give
+/// their value in ZEC only and a single `address` instead of `addresses`.
+#[derive(Deserialize)]
+struct ZebradBlockResult {
+    hash: String,
+    height: u64,
+    #[serde(default)]
+    time: u32,
+    tx: Vec<ZebradBlockTx>,
+}
+
+#[derive(Deserialize)]
+struct ZebradBlockTx {
+    vout: Vec<ZebradBlockVout>,
+}
+
+#[derive(Deserialize)]
+struct ZebradBlockVout {
+    #[serde(rename = "valueZat", alias = "valueSat")]
+    value_zat: Option<i64>,
+    /// In ZEC; only read when `valueZat` is absent.
+    value: Option<f64>,
+    #[serde(rename = "scriptPubKey")]
+    script_pub_key: ZebradScriptPubKey,
+}
+
+#[derive(Deserialize)]
+struct ZebradScriptPubKey {
+    #[serde(default)]
+    addresses: Vec<String>,
+    address: Option<String>,
+    #[serde(default)]
+    hex: Option<String>,
+}
+
+impl From<ZebradBlockResult> for BlockResult {
+    fn from(block: ZebradBlockResult) -> Self {
+        let tx = block
+            .tx
+            .into_iter()
+            .map(|tx| BlockTx {
+                vout: tx
+                    .vout
+                    .into_iter()
+                    .map(|vout| {
+                        let mut addresses = vout.script_pub_key.addresses;
+                        addresses.extend(vout.script_pub_key.address);
+                        BlockVout {
+                            value_zat: vout.value_zat.unwrap_or_else(|| {
+                                (vout.value.unwrap_or_default() * ZATS_PER_WEC as f64).round()
+                                    as i64
+                            }),
+                            script_pub_key: ScriptPubKey {
+                                addresses: Some(addresses),
+                                hex: vout.script_pub_key.hex,
+                            },
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        Self {
+            hash: block.hash,
+            height: block.height,
+            time: block.time,
+            tx,
+        }
+    }
+}
+
+/// Makes [`compute_statistics`] scan every cached block instead of only those passing the
+/// bloom filter pre-check, so the benchmarks can time it against a baseline. Only built with
+/// the `bench` feature.
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub static SKIP_BLOOM_PRECHECK: AtomicBool = AtomicBool::new(false);
+
+fn bloom_precheck_enabled() -> bool {
+    #[cfg(feature = "bench")]
+    if SKIP_BLOOM_PRECHECK.load(Ordering::Relaxed) {
+        return false;
+    }
+    true
+}
+
+/// Attributes the cached blocks from `cfg.start_height` to `tip_height` to the configured
+/// miners. Heights missing from `cache` are counted in the range but never matched.
+pub fn compute_statistics(
+    cfg: &MinerStatsConfig,
+    cache: &BlockCache,
+    tip_height: u64,
+) -> Result<MinerStatsReport> {
+    compute_statistics_with_addresses(cfg, cache, tip_height, &mut DerivedAddressCache::default())
+}
+
+/// Like [`compute_statistics`], but reuses the transparent addresses already in `addresses`
+/// instead of deriving them again, and adds the ones it had to derive.
+pub fn compute_statistics_with_addresses(
+    cfg: &MinerStatsConfig,
+    cache: &BlockCache,
+    tip_height: u64,
+    addresses: &mut DerivedAddressCache,
+) -> Result<MinerStatsReport> {
+    for &height in cfg.exclude_heights.iter().filter(|h| **h > tip_height) {
+        warn!(
+            height,
+            tip_height, "exclude_heights entry is above the node tip; ignoring it"
+        );
+    }
+    let excluded_heights: Vec<u64> = cfg
+        .exclude_heights
+        .range(cfg.start_height..=tip_height)
+        .copied()
+        .collect();
+    // A read-only cache is never synced, so its missing heights are left out rather than
+    // counted as blocks of unknown miners.
+    let heights: Vec<u64> = (cfg.start_height..=tip_height)
+        .filter(|h| !cfg.exclude_heights.contains(h))
+        .filter(|h| !cfg.cache_readonly || cache.blocks.contains_key(h))
+        .collect();
+    let total_blocks = heights.len() as u32;
+    let coinbase_totals: HashMap<u64, i64> = heights
+        .iter()
+        .filter_map(|h| cache.blocks.get(h).map(|block| (*h, block)))
+        .map(|(height, block)| {
+            let values = block.outputs.iter().map(|o| o.value_zat);
+            let total = checked_zat_sum(values, || format!("coinbase of block {height}"))?;
+            Ok::<_, Error>((height, total))
+        })
+        .collect::<Result<_>>()?;
+
+    let skipped_blocks: Vec<u64> = heights
+        .iter()
+        .filter(|h| cache.blocks.contains_key(h) && derivation_index(**h).is_none())
+        .copied()
+        .collect();
+    if let (Some(first), Some(last)) = (skipped_blocks.first(), skipped_blocks.last()) {
+        warn!(
+            blocks = skipped_blocks.len(),
+            "heights {first}-{last} have no non-hardened child index; their blocks cannot be \
+             matched and are listed under skipped_blocks"
+        );
+    }
+
+    // Pass 1: derive every address a miner may be paid at, in parallel across miners and
+    // heights. The scan below then only looks addresses up, and the bloom filter needs them
+    // all up front anyway. Keyed by miner index rather than label, since labels need not be
+    // unique.
+    let known_addresses = &*addresses;
+    let derived_per_miner = info_span!(
+        "derive addresses",
+        miner_count = cfg.miners.len(),
+        height_count = heights.len()
+    )
+    .in_scope(|| {
+        cfg.miners
+            .par_iter()
+            .map(|miner| {
+                let key_stores = miner
+                    .keys
+                    .iter()
+                    .map(|key| {
+                        UnifiedKeyStore::new_from_ufvk(&cfg.chain, key.clone())
+                            .map(|store| (key, store))
+                            .map_err(|e| Error::KeyDecode {
+                                key: shorten_key(key),
+                                source: e.into(),
+                            })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let mut shielded_receivers = Vec::new();
+                for key in &miner.keys {
+                    for (receiver_type, address) in shielded::receivers(&cfg.chain, key)? {
+                        shielded_receivers.push((
+                            key,
+                            address,
+                            receiver_type,
+                            AddressScope::External,
+                        ));
+                    }
+                }
+                let miner_range = miner.start_height.unwrap_or(cfg.start_height)
+                    ..=miner.end_height.unwrap_or(tip_height);
+                heights
+                    .par_iter()
+                    .filter(|h| miner_range.contains(h) && cache.blocks.contains_key(h))
+                    .filter_map(|&height| Some((height, derivation_index(height)?)))
+                    .map(|(height, index)| {
+                        let mut derived = key_stores
+                            .iter()
+                            .flat_map(|(key, key_store)| {
+                                miner.transparent_scopes.iter().map(move |&scope| {
+                                    // The address cache only holds external addresses.
+                                    let known = match scope {
+                                        AddressScope::External => known_addresses.get(key, height),
+                                        AddressScope::Internal => None,
+                                    };
+                                    if let Some(known) = known {
+                                        return Ok((
+                                            *key,
+                                            known.to_string(),
+                                            ReceiverType::Transparent,
+                                            scope,
+                                        ));
+                                    }
+                                    let address = key_store
+                                        .generate_transparent_address(index, scope.transparent())
+                                        .map_err(|e| Error::AddressDerivation {
+                                            height,
+                                            source: e.into(),
+                                        })?;
+                                    let encoded = transparent::encode_address(&cfg.chain, address);
+                                    Ok::<_, Error>((
+                                        *key,
+                                        encoded,
+                                        ReceiverType::Transparent,
+                                        scope,
+                                    ))
+                                })
+                            })
+                            .collect::<Result<Vec<_>>>()?;
+                        derived.extend(shielded_receivers.iter().cloned());
+                        Ok::<_, Error>((height, derived))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+    // (key, encoded address, receiver type, scope)
+    type Derived<'k> = (&'k String, String, ReceiverType, AddressScope);
+    let mut derived: HashMap<u64, HashMap<usize, Vec<Derived<'_>>>> = HashMap::new();
+    for (miner_index, by_height) in derived_per_miner.into_iter().enumerate() {
+        for (height, by_key) in by_height {
+            for (key, encoded, receiver_type, scope) in &by_key {
+                if *receiver_type == ReceiverType::Transparent && *scope == AddressScope::External {
+                    addresses.insert(key, height, encoded.clone());
+                }
+            }
+            derived
+                .entry(height)
+                .or_default()
+                .insert(miner_index, by_key);
+        }
+    }
+
+    for (height, by_miner) in &derived {
+        for (miner_index, by_key) in by_miner {
+            let mapped = by_key
+                .iter()
+                .find_map(|(_, encoded, _, _)| Some((encoded, cfg.address_map.get(encoded)?)));
+            if let Some((address, label)) = mapped {
+                return Err(Error::config(
+                    "address_map_file",
+                    format!(
+                        "address {address} is mapped to '{label}' but also derived from the \
+                         keys of '{}' at height {height}",
+                        cfg.miners[*miner_index].label
+                    ),
+                ));
+            }
+        }
+    }
+
+    // Most blocks pay none of the configured miners. A bloom filter over every derived
+    // address rules those out with one probe per output address instead of a comparison
+    // against every miner's addresses.
+    let address_count: usize = derived.values().flatten().map(|(_, a)| a.len()).sum();
+    let mut bloom: Bloom<str> = Bloom::new_for_fp_rate(address_count.max(1), 0.01);
+    for (_, addresses) in derived.values().flatten() {
+        for (_, encoded, _, _) in addresses {
+            bloom.set(encoded.as_str());
+        }
+    }
+    let precheck = bloom_precheck_enabled();
+    let candidate_heights: HashSet<u64> = heights
+        .iter()
+        .filter(|h| {
+            cache.blocks.get(h).is_some_and(|block| {
+                !precheck
+                    || block
+                        .outputs
+                        .iter()
+                        .flat_map(|output| &output.addresses)
+                        .any(|addr| bloom.check(addr.as_str()))
+            })
+        })
+        .copied()
+        .collect();
+
+    // Pass 2: scan the candidate blocks against the precomputed addresses, one miner per
+    // task. Each task returns its summary and the `(height, matched value)` of each block it
+    // matched; those are merged below in config order, so the report does not depend on
+    // scheduling.
+    let scanned = cfg
+        .miners
+        .par_iter()
+        .enumerate()
+        .map(|(miner_index, miner)| {
+            let _span = info_span!(
+                "scan blocks for miner",
+                miner = %miner.label,
+                height_count = candidate_heights.len()
+            )
+            .entered();
+            let mut blocks = 0u32;
+            let mut total_value = 0i64;
+            let mut details = Vec::new();
+            let mut matched = Vec::new();
+            for &height in &heights {
+                if !candidate_heights.contains(&height) {
+                    continue;
+                }
+                let Some(addresses) = derived
+                    .get(&height)
+                    .and_then(|by_miner| by_miner.get(&miner_index))
+                else {
+                    continue;
+                };
+                let block = &cache.blocks[&height];
+
+                // Each output counts once, even if several of the miner's keys or scopes derive its
+                // address.
+                let mut matched_value = 0i64;
+                let mut first_match = None;
+                for output in &block.outputs {
+                    let hit = addresses.iter().find(|(_, encoded, _, _)| {
+                        output.addresses.iter().any(|addr| addr == encoded)
+                    });
+                    if let Some(hit) = hit {
+                        matched_value = checked_zat_sum([matched_value, output.value_zat], || {
+                            format!("matched value of block {height}")
+                        })?;
+                        first_match.get_or_insert(hit);
+                    }
+                }
+                if let Some((key, encoded, receiver_type, scope)) =
+                    first_match.filter(|_| matched_value > 0)
+                {
+                    debug!(
+                        "[height {height}] matched miner {:?} address {encoded} value {matched_value} zat",
+                        miner.label
+                    );
+                    blocks += 1;
+                    total_value = checked_zat_sum([total_value, matched_value], || {
+                        format!("total value of miner {:?}", miner.label)
+                    })?;
+                    matched.push((height, matched_value));
+                    details.push(MinerBlockDetail {
+                        block_height: height,
+                        block_hash: block.hash.clone(),
+                        block_time: block.time,
+                        block_datetime: format_block_time(block.time, Tz::UTC),
+                        payout_address: encoded.clone(),
+                        derivation_index: match receiver_type {
+                            ReceiverType::Transparent => height as u32,
+                            _ => 0,
+                        },
+                        value_zat: matched_value,
+                        matched_key_hint: shorten_key(key),
+                        receiver_type: *receiver_type,
+                        scope: *scope,
+                        fees_zat: block_fees(block, coinbase_totals[&height], &cfg.chain),
+                        explorer_url: cfg
+                            .explorer_url_template
+                            .as_deref()
+                            .map(|template| explorer_url(template, height, &block.hash)),
+                        annotation: cfg.block_annotations.get(&height).cloned(),
+                    });
+                }
+            }
+            let summary = MinerSummary {
+                label: miner.label.clone(),
+                matched_blocks: blocks,
+                total_value_zat: total_value,
+                total_value_wec: zats_to_wec(total_value),
+                share_percent: 0.0,
+                detailed_blocks: details,
+                mature_value_zat: 0,
+                immature_value_zat: 0,
+                moving_avg_share: Vec::new(),
+                inactivity_periods: Vec::new(),
+                value_histogram: None,
+                day_of_week_stats: None,
+            };
+            Ok::<_, Error>((summary, matched))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mapped = scan_address_map(cfg, cache, &heights, &coinbase_totals)?;
+
+    let mut per_miner = Vec::with_capacity(scanned.len() + mapped.len());
+    let mut matched_blocks = BTreeSet::new();
+    let mut block_totals: HashMap<u64, i64> = HashMap::new();
+    for (summary, matched) in scanned.into_iter().chain(mapped) {
+        for (height, matched_value) in matched {
+            matched_blocks.insert(height);
+            block_totals
+                .entry(height)
+                .and_modify(|v| *v = (*v).max(matched_value))
+                .or_insert(matched_value);
+        }
+        per_miner.push(summary);
+    }
+
+    let window_ends = rolling_window_ends(cfg, tip_height);
+    let histogram_edges = if cfg.include_histogram {
+        histogram_edges(coinbase_totals.values().copied())
+    } else {
+        None
+    };
+    for miner in &mut per_miner {
+        miner.share_percent = percent_share_blocks(miner.matched_blocks, total_blocks);
+        (miner.mature_value_zat, miner.immature_value_zat) =
+            maturity_split(&miner.detailed_blocks, tip_height, cfg.maturity_depth);
+        miner.moving_avg_share = moving_avg_shares(
+            &heights,
+            &miner.detailed_blocks,
+            &window_ends,
+            cfg.rolling_window_blocks,
+        );
+        miner.inactivity_periods = inactivity_periods(
+            &miner.detailed_blocks,
+            cfg.inactivity_threshold_blocks,
+            tip_height,
+        );
+        miner.value_histogram = histogram_edges.as_ref().map(|edges| {
+            histogram(
+                edges,
+                miner
+                    .detailed_blocks
+                    .iter()
+                    .map(|b| coinbase_totals[&b.block_height]),
+            )
+        });
+        if cfg.include_day_stats {
+            miner.day_of_week_stats = Some(day_of_week_stats(&miner.detailed_blocks, Tz::UTC));
+        }
+    }
+
+    let matched_value_zat =
+        checked_zat_sum(block_totals.values().copied(), || "matched value".into())?;
+    let unmatched_blocks = total_blocks.saturating_sub(matched_blocks.len() as u32);
+    let unmatched_value_zat = checked_zat_sum(
+        coinbase_totals
+            .iter()
+            .filter(|(height, _)| !matched_blocks.contains(height))
+            .map(|(_, value)| *value),
+        || "value of unmatched blocks".into(),
+    )?;
+    let unmatched_value_wec = zats_to_wec(unmatched_value_zat);
+    let unmatched_share = percent_share_blocks(unmatched_blocks, total_blocks);
+
+    let total_value_zat = checked_zat_sum([matched_value_zat, unmatched_value_zat], || {
+        "total value".into()
+    })?;
+    let total_value_wec = zats_to_wec(total_value_zat);
+
+    let mut range_blocks = cache
+        .blocks
+        .range(cfg.start_height..=tip_height)
+        .map(|(_, b)| b);
+    let first_block = range_blocks.next();
+    let last_block = range_blocks.next_back().or(first_block);
+    let time_range = first_block
+        .zip(last_block)
+        .map(|(first, last)| TimeRange::new(first.time, last.time));
+    let elapsed_days = elapsed_days(time_range.as_ref(), cfg.start_height, tip_height);
+
+    let window = cfg.window_blocks.map(|size| {
+        let window_start = tip_height
+            .saturating_sub(u64::from(size) - 1)
+            .max(cfg.start_height);
+        let window_total = heights.iter().filter(|h| **h >= window_start).count() as u32;
+        WindowStats {
+            start_height: window_start,
+            end_height: tip_height,
+            total_blocks: window_total,
+            miners: per_miner
+                .iter()
+                .map(|m| {
+                    let matched_blocks = m
+                        .detailed_blocks
+                        .iter()
+                        .filter(|d| d.block_height >= window_start)
+                        .count() as u32;
+                    WindowMinerStats {
+                        label: m.label.clone(),
+                        matched_blocks,
+                        share_percent: percent_share_blocks(matched_blocks, window_total),
+                    }
+                })
+                .collect(),
+        }
+    });
+
+    let fiat_rate = cfg.fiat_rate.get().copied();
+    let schedule = HalvingSchedule::for_chain(&cfg.chain);
+    let halvings = (schedule.epoch(cfg.start_height)..=schedule.epoch(tip_height))
+        .map(|epoch| {
+            let start_height = schedule.epoch_start(epoch).max(cfg.start_height);
+            let end_height = schedule
+                .epoch_start(epoch + 1)
+                .saturating_sub(1)
+                .min(tip_height);
+            EpochStats {
+                epoch,
+                start_height,
+                end_height,
+                total_blocks: heights
+                    .iter()
+                    .filter(|h| (start_height..=end_height).contains(h))
+                    .count() as u32,
+                miners: per_miner
+                    .iter()
+                    .map(|m| EpochMinerStats {
+                        label: m.label.clone(),
+                        matched_blocks: m
+                            .detailed_blocks
+                            .iter()
+                            .filter(|d| (start_height..=end_height).contains(&d.block_height))
+                            .count() as u32,
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    // First miner in config order credited with each height, for `top_blocks`.
+    let mut credited: HashMap<u64, &str> = HashMap::new();
+    for miner in &per_miner {
+        for detail in &miner.detailed_blocks {
+            credited.entry(detail.block_height).or_insert(&miner.label);
+        }
+    }
+    let top_blocks = top_blocks(cfg, cache, &coinbase_totals, &credited);
+
+    Ok(MinerStatsReport {
+        start_height: cfg.start_height,
+        end_height: tip_height,
+        total_mined_blocks: matched_blocks.len() as u32,
+        total_value_zat,
+        total_value_wec,
+        miners: per_miner
+            .iter()
+            .map(|m| {
+                let (longest_streak, current_streak) =
+                    block_streaks(&m.detailed_blocks, tip_height);
+                let (first_block, last_block, inactive_since) =
+                    block_activity(&m.detailed_blocks, tip_height);
+                let total_fees_zat = total_fees(m)?;
+                Ok::<_, Error>(MinerAggregate {
+                    label: m.label.clone(),
+                    matched_blocks: m.matched_blocks,
+                    total_value_zat: m.total_value_zat,
+                    total_value_wec: m.total_value_wec,
+                    share_percent: m.share_percent,
+                    blocks_per_day: blocks_per_day(m.matched_blocks, elapsed_days),
+                    longest_streak,
+                    current_streak,
+                    total_fees_zat,
+                    total_fees_wec: zats_to_wec(total_fees_zat),
+                    value_share_percent: percent_share_value(m.total_value_zat, total_value_zat),
+                    first_block,
+                    last_block,
+                    inactive_since,
+                    mature_value_zat: m.mature_value_zat,
+                    immature_value_zat: m.immature_value_zat,
+                    total_value_fiat: fiat_rate.map(|rate| fiat::value(m.total_value_zat, rate)),
+                    fiat_currency: fiat_rate.and(cfg.fiat_currency.clone()),
+                })
+            })
+            .collect::<Result<_>>()?,
+        detailed_miners: per_miner,
+        unmatched: UnmatchedSummary {
+            blocks: unmatched_blocks,
+            blocks_per_day: blocks_per_day(unmatched_blocks, elapsed_days),
+            total_value_zat: unmatched_value_zat,
+            total_value_wec: unmatched_value_wec,
+            share_percent: unmatched_share,
+            value_share_percent: percent_share_value(unmatched_value_zat, total_value_zat),
+        },
+        window,
+        time_range,
+        halvings,
+        excluded_heights,
+        outlier_blocks: outlier_blocks(&coinbase_totals),
+        top_blocks,
+        skipped_blocks,
+        fiat_rate,
+        value_histogram: histogram_edges
+            .as_ref()
+            .map(|edges| histogram(edges, coinbase_totals.values().copied())),
+        warnings: cfg.warnings.clone(),
+        coverage_gaps: cache
+            .coverage_gaps(cfg.start_height, tip_height)
+            .into_iter()
+            .map(|gap| [*gap.start(), *gap.end()])
+            .collect(),
+    })
+}
+
+/// Pass 2 for `address_map_file`: one summary per mapped label, in label order, with the
+/// `(height, matched value)` of each block it matched. Blocks are credited like those of a
+/// UFVK miner over the whole range; their details carry derivation index 0 and
+/// `address map` as the key hint, since no key is involved.
+fn scan_address_map(
+    cfg: &MinerStatsConfig,
+    cache: &BlockCache,
+    heights: &[u64],
+    coinbase_totals: &HashMap<u64, i64>,
+) -> Result<Vec<(MinerSummary, Vec<(u64, i64)>)>> {
+    let mut by_label: BTreeMap<&str, (Vec<MinerBlockDetail>, Vec<(u64, i64)>)> = cfg
+        .address_map
+        .values()
+        .map(|label| (label.as_str(), Default::default()))
+        .collect();
+    for &height in heights {
+        let Some(block) = cache.blocks.get(&height) else {
+            continue;
+        };
+        // Matched value and first matched address per label; each output counts once.
+        let mut matches: BTreeMap<&str, (i64, &str)> = BTreeMap::new();
+        for output in &block.outputs {
+            let hit = output
+                .addresses
+                .iter()
+                .find_map(|addr| Some((cfg.address_map.get(addr)?.as_str(), addr.as_str())));
+            if let Some((label, address)) = hit {
+                let entry = matches.entry(label).or_insert((0, address));
+                entry.0 = checked_zat_sum([entry.0, output.value_zat], || {
+                    format!("matched value of block {height}")
+                })?;
+            }
+        }
+        for (label, (matched_value, address)) in matches {
+            if matched_value <= 0 {
+                continue;
+            }
+            debug!(
+                "[height {height}] matched mapped miner {label:?} address {address} value \
+                 {matched_value} zat"
+            );
+            let (details, matched) = by_label.entry(label).or_default();
+            matched.push((height, matched_value));
+            details.push(MinerBlockDetail {
+                block_height: height,
+                block_hash: block.hash.clone(),
+                block_time: block.time,
+                block_datetime: format_block_time(block.time, Tz::UTC),
+                payout_address: address.to_string(),
+                derivation_index: 0,
+                value_zat: matched_value,
+                matched_key_hint: "address map".to_string(),
+                receiver_type: ReceiverType::Transparent,
+                scope: AddressScope::External,
+                fees_zat: block_fees(block, coinbase_totals[&height], &cfg.chain),
+                explorer_url: cfg
+                    .explorer_url_template
+                    .as_deref()
+                    .map(|template| explorer_url(template, height, &block.hash)),
+                annotation: cfg.block_annotations.get(&height).cloned(),
+            });
+        }
+    }
+    by_label
+        .into_iter()
+        .map(|(label, (details, matched))| {
+            let total_value = checked_zat_sum(matched.iter().map(|(_, value)| *value), || {
+                format!("total value of miner {label:?}")
+            })?;
+            let summary = MinerSummary {
+                label: label.to_string(),
+                matched_blocks: details.len() as u32,
+                total_value_zat: total_value,
+                total_value_wec: zats_to_wec(total_value),
+                share_percent: 0.0,
+                detailed_blocks: details,
+                mature_value_zat: 0,
+                immature_value_zat: 0,
+                moving_avg_share: Vec::new(),
+                inactivity_periods: Vec::new(),
+                value_histogram: None,
+                day_of_week_stats: None,
+            };
+            Ok((summary, matched))
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MinerAggregate {
+    pub label: String,
+    pub matched_blocks: u32,
+    pub total_value_zat: i64,
+    pub total_value_wec: Decimal,
+    pub share_percent: f64,
+    #[serde(default)]
+    pub blocks_per_day: f64,
+    /// Most consecutive heights matched in a row.
+    #[serde(default)]
+    pub longest_streak: u32,
+    /// Consecutive heights matched up to and including the tip; 0 if the miner did not
+    /// match the tip block.
+    #[serde(default)]
+    pub current_streak: u32,
+    /// Fees of the matched blocks, the part of their coinbase value above the subsidy.
+    #[serde(default)]
+    pub total_fees_zat: i64,
+    #[serde(default)]
+    pub total_fees_wec: Decimal,
+    /// Share of the range's total coinbase value paid to this miner, in percent.
+    /// `share_percent` stays the share of blocks.
+    #[serde(default)]
+    pub value_share_percent: f64,
+    /// Lowest and highest matched height; `None` if the miner matched no block of the
+    /// range.
+    #[serde(default)]
+    pub first_block: Option<u64>,
+    #[serde(default)]
+    pub last_block: Option<u64>,
+    /// Heights from `last_block` to the tip; `None` if the miner matched the tip block or
+    /// no block at all.
+    #[serde(default)]
+    pub inactive_since: Option<u64>,
+    /// `total_value_zat` split as in [`MinerSummary::mature_value_zat`].
+    #[serde(default)]
+    pub mature_value_zat: i64,
+    #[serde(default)]
+    pub immature_value_zat: i64,
+    /// `total_value_zat` in `fiat_currency` at the report's `fiat_rate`; both `None` unless
+    /// `fiat_currency` is set and its rate could be fetched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_value_fiat: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fiat_currency: Option<String>,
+}
+
+impl MinerAggregate {
+    /// Whether `mature_value_zat` and `immature_value_zat` fail to add up to
+    /// `total_value_zat`, e.g. in a hand-edited or corrupted report. The table paints such
+    /// rows red.
+    pub fn value_mismatch(&self) -> bool {
+        self.mature_value_zat.checked_add(self.immature_value_zat) != Some(self.total_value_zat)
+    }
+
+    /// This miner's row of the console table, without color, with the label truncated
+    /// and padded to `label_width` columns. `show_value_share` adds the value share column
+    /// `show_activity` the first, last and inactive-since columns and `show_maturity` the
+    /// mature and immature value columns; `human_values` formats the values as
+    /// [`format_value`] does.
+    pub fn table_row(
+        &self,
+        label_width: usize,
+        show_value_share: bool,
+        human_values: bool,
+        show_activity: bool,
+        show_maturity: bool,
+    ) -> String {
+        table_row(
+            &self.label,
+            RowFigures {
+                blocks: self.matched_blocks,
+                blocks_per_day: self.blocks_per_day,
+                value_zat: self.total_value_zat,
+                share_percent: self.share_percent,
+                value_share_percent: show_value_share.then_some(self.value_share_percent),
+                activity: show_activity.then_some([
+                    self.first_block,
+                    self.last_block,
+                    self.inactive_since,
+                ]),
+                maturity: show_maturity
+                    .then_some([Some(self.mature_value_zat), Some(self.immature_value_zat)]),
+            },
+            label_width,
+            human_values,
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MinerSummary {
+    pub label: String,
+    pub matched_blocks: u32,
+    pub total_value_zat: i64,
+    pub total_value_wec: Decimal,
+    pub share_percent: f64,
+    pub detailed_blocks: Vec<MinerBlockDetail>,
+    /// Part of `total_value_zat` in blocks with at least `maturity_depth` blocks on top of
+    /// them at the tip, which is spendable; the rest is not yet.
+    #[serde(default)]
+    pub mature_value_zat: i64,
+    #[serde(default)]
+    pub immature_value_zat: i64,
+    /// Share over sliding windows of the range, oldest first (`include_moving_avg`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moving_avg_share: Vec<WindowedShare>,
+    /// Runs of more than `inactivity_threshold_blocks` heights without a block of this
+    /// miner, by height.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inactivity_periods: Vec<InactivityPeriod>,
+    /// Coinbase values of this miner's blocks, over the buckets of the report's
+    /// `value_histogram` (`include_histogram`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_histogram: Option<Vec<HistogramBucket>>,
+    /// This miner's blocks by weekday of their `block_time`, Monday first, in UTC unless
+    /// `--timezone` says otherwise (`include_day_stats`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub day_of_week_stats: Option<[DayStats; 7]>,
+}
+
+/// Blocks a miner matched on one weekday and their matched value.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct DayStats {
+    pub block_count: u32,
+    pub value_zat: i64,
+}
+
+/// Heights `from_height..=to_height` in which a miner matched no block.
+#[derive(Serialize, Deserialize)]
+pub struct InactivityPeriod {
+    pub from_height: u64,
+    pub to_height: u64,
+    pub duration_blocks: u64,
+}
+
+/// A miner's block share over the `window_size` heights up to `window_end_height`.
+#[derive(Serialize, Deserialize)]
+pub struct WindowedShare {
+    pub window_end_height: u64,
+    pub window_size: u32,
+    pub share_percent: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MinerBlockDetail {
+    pub block_height: u64,
+    pub block_hash: String,
+    #[serde(default)]
+    pub block_time: u32,
+    /// `block_time` in ISO-8601 form, in UTC unless `--timezone` says otherwise.
+    #[serde(default)]
+    pub block_datetime: String,
+    pub payout_address: String,
+    /// Child index of the transparent address that was paid, which is the block height.
+    /// Shielded receivers are the key's default address and report 0.
+    #[serde(default)]
+    pub derivation_index: u32,
+    #[serde(default)]
+    pub value_zat: i64,
+    /// Shortened form of the UFVK that derived `payout_address`; tells the keys of a
+    /// multi-key miner apart.
+    #[serde(default)]
+    pub matched_key_hint: String,
+    /// Which receiver of the key was paid; shielded types only occur with the `sapling` and
+    /// `orchard` features.
+    #[serde(default)]
+    pub receiver_type: ReceiverType,
+    /// Chain of the transparent address that was paid; shielded receivers report `external`.
+    #[serde(default)]
+    pub scope: AddressScope,
+    /// Transaction fees collected by the block: its coinbase value above the subsidy.
+    #[serde(default)]
+    pub fees_zat: i64,
+    /// The block's page on the block explorer of `explorer_url_template`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explorer_url: Option<String>,
+    /// The `[annotations]` entry for `block_height`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<String>,
+}
+
+/// Placeholders `explorer_url_template` may contain.
+const EXPLORER_URL_TOKENS: [&str; 2] = ["{height}", "{hash}"];
+
+/// Rejects templates with a `{…}` placeholder other than [`EXPLORER_URL_TOKENS`], or with an
+/// unclosed `{`, so a typo fails at startup instead of producing broken links.
+fn check_explorer_url_template(template: &str) -> Result<()> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            return Err(Error::config(
+                "explorer_url_template",
+                format!("'{template}' has an unclosed '{{'"),
+            ));
+        };
+        let token = &rest[start..=start + len];
+        if !EXPLORER_URL_TOKENS.contains(&token) {
+            return Err(Error::config(
+                "explorer_url_template",
+                format!("unknown placeholder {token}; use {{height}} or {{hash}}"),
+            ));
+        }
+        rest = &rest[start + len + 1..];
+    }
+    Ok(())
+}
+
+fn explorer_url(template: &str, height: u64, hash: &str) -> String {
+    template
+        .replace("{height}", &height.to_string())
+        .replace("{hash}", hash)
+}
+
+/// Kind of address a matched coinbase output paid.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiverType {
+    /// The transparent address derived at child index == block height.
+    #[default]
+    Transparent,
+    /// The UFVK's default Sapling payment address.
+    Sapling,
+    /// The UFVK's Orchard receiver at index 0, encoded as a unified address.
+    Orchard,
+}
+
+/// Transparent address chain of a UFVK, as listed in a miner's `transparent_scopes`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressScope {
+    /// The receiving addresses; where coinbase outputs are normally paid.
+    #[default]
+    External,
+    /// The change addresses, used by some mining software for coinbase outputs.
+    Internal,
+}
+
+impl AddressScope {
+    fn transparent(self) -> TransparentScope {
+        match self {
+            AddressScope::External => TransparentScope::External,
+            AddressScope::Internal => TransparentScope::Internal,
+        }
+    }
+}
+
+/// One line of the `--stream-output` JSON Lines file: a matched block flattened together
+/// with the miner it was credited to. Lines appear grouped by miner (config order), and
+/// within a miner by ascending `block_height`. Fields are only ever added, never renamed.
+#[derive(Serialize)]
+struct StreamRecord<'a> {
+    block_height: u64,
+    block_hash: &'a str,
+    miner_label: &'a str,
+    payout_address: &'a str,
+    value_zat: i64,
+}
+
+/// Document line of [`MinerStatsReport::write_es_bulk`]: a [`MinerBlockDetail`] with the
+/// label of its miner. `docs/elasticsearch-mapping.json` maps these fields.
+#[derive(Serialize)]
+struct EsBlockDocument<'a> {
+    miner_label: &'a str,
+    #[serde(flatten)]
+    detail: &'a MinerBlockDetail,
+}
+
+/// What [`MinerStatsReport::write_summary`] writes.
+#[derive(Serialize)]
+struct ReportSummary<'a> {
+    start_height: u64,
+    end_height: u64,
+    total_mined_blocks: u32,
+    miners: &'a [MinerAggregate],
+    unmatched: &'a UnmatchedSummary,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MinerStatsReport {
+    pub start_height: u64,
+    pub end_height: u64,
+    pub total_mined_blocks: u32,
+    pub total_value_zat: i64,
+    pub total_value_wec: Decimal,
+    pub miners: Vec<MinerAggregate>,
+    pub detailed_miners: Vec<MinerSummary>,
+    #[serde(skip_serializing, default)]
+    pub unmatched: UnmatchedSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<WindowStats>,
+    pub time_range: Option<TimeRange>,
+    #[serde(default)]
+    pub halvings: Vec<EpochStats>,
+    /// Heights of the range left out of every figure above (`exclude_heights`).
+    #[serde(default)]
+    pub excluded_heights: Vec<u64>,
+    /// Cached blocks whose coinbase value is more than [`OUTLIER_Z_SCORE`] standard
+    /// deviations from the range's mean, by height.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outlier_blocks: Vec<OutlierBlock>,
+    /// The `top_n_blocks` cached blocks of the range with the highest coinbase value,
+    /// highest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_blocks: Vec<TopBlock>,
+    /// Cached heights of the range that were not scanned: they exceed the largest
+    /// non-hardened child index (2^31 - 1), so no transparent address can be derived for them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_blocks: Vec<u64>,
+    /// Price of one WEC in `fiat_currency` when the report was made.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fiat_rate: Option<f64>,
+    /// Total coinbase values of the cached blocks over [`HISTOGRAM_BUCKETS`] equal-width
+    /// buckets from the lowest to the highest (`include_histogram`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value_histogram: Option<Vec<HistogramBucket>>,
+    /// Configuration problems that did not stop the run, such as duplicate labels.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// Inclusive height ranges of the scanned range with no cached block; the figures above
+    /// are incomplete when this is not empty.
+    #[serde(default)]
+    pub coverage_gaps: Vec<[u64; 2]>,
+}
+
+/// Blocks with a coinbase value from `min_zat` up to but excluding `max_zat`; the last
+/// bucket of a histogram includes its `max_zat`. Consecutive buckets share their bound.
+#[derive(Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub min_zat: i64,
+    pub max_zat: i64,
+    pub block_count: u32,
+}
+
+/// A block whose coinbase value stands out from the rest of the range.
+#[derive(Serialize, Deserialize)]
+pub struct OutlierBlock {
+    pub height: u64,
+    /// Total coinbase value of the block.
+    pub value_zat: i64,
+    /// Standard deviations above (positive) or below the mean, rounded to two decimals.
+    pub z_score: f64,
+}
+
+/// One of the blocks with the highest coinbase value of the range.
+#[derive(Serialize, Deserialize)]
+pub struct TopBlock {
+    pub height: u64,
+    pub hash: String,
+    pub total_coinbase_zat: i64,
+    /// Label of the miner credited with the block, if any; the first in config order when
+    /// several are.
+    pub matched_miner: Option<String>,
+}
+
+/// Blocks per miner within one halving epoch, clipped to the scanned range.
+#[derive(Serialize, Deserialize)]
+pub struct EpochStats {
+    /// Number of halvings before this epoch (0 = initial subsidy).
+    pub epoch: u32,
+    pub start_height: u64,
+    pub end_height: u64,
+    pub total_blocks: u32,
+    pub miners: Vec<EpochMinerStats>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EpochMinerStats {
+    pub label: String,
+    pub matched_blocks: u32,
+}
+
+/// Timestamps of the first and last cached blocks in the scanned range.
+#[derive(Serialize, Deserialize)]
+pub struct TimeRange {
+    pub start_unix: u32,
+    pub end_unix: u32,
+    /// `start_unix` and `end_unix` in ISO-8601 form, in UTC unless `--timezone` says
+    /// otherwise.
+    #[serde(default)]
+    pub start_datetime: String,
+    #[serde(default)]
+    pub end_datetime: String,
+}
+
+impl TimeRange {
+    /// The range from `start_unix` to `end_unix`, with the datetimes in UTC.
+    pub fn new(start_unix: u32, end_unix: u32) -> Self {
+        Self {
+            start_unix,
+            end_unix,
+            start_datetime: format_block_time(start_unix, Tz::UTC),
+            end_datetime: format_block_time(end_unix, Tz::UTC),
+        }
+    }
+}
+
+/// ISO-8601 form of the block timestamp `unix` in `timezone`, e.g. `2023-11-14T22:13:20Z`
+/// in UTC or `2023-11-14T17:13:20-05:00` in `America/New_York`.
+pub fn format_block_time(unix: u32, timezone: Tz) -> String {
+    chrono::DateTime::from_timestamp(i64::from(unix), 0)
+        .unwrap_or_default()
+        .with_timezone(&timezone)
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// `details` counted per weekday of their block time in `timezone`, Monday first. The sums
+/// cannot overflow: they are parts of a miner's checked total.
+fn day_of_week_stats(details: &[MinerBlockDetail], timezone: Tz) -> [DayStats; 7] {
+    let mut days = [DayStats::default(); 7];
+    for detail in details {
+        let weekday = chrono::DateTime::from_timestamp(i64::from(detail.block_time), 0)
+            .unwrap_or_default()
+            .with_timezone(&timezone)
+            .weekday();
+        let day = &mut days[weekday.num_days_from_monday() as usize];
+        day.block_count += 1;
+        day.value_zat += detail.value_zat;
+    }
+    days
+}
+
+/// Block shares over the trailing `window_blocks` heights of the scanned range.
+#[derive(Serialize, Deserialize)]
+pub struct WindowStats {
+    pub start_height: u64,
+    pub end_height: u64,
+    pub total_blocks: u32,
+    pub miners: Vec<WindowMinerStats>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WindowMinerStats {
+    pub label: String,
+    pub matched_blocks: u32,
+    pub share_percent: f64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct UnmatchedSummary {
+    pub blocks: u32,
+    #[serde(default)]
+    pub blocks_per_day: f64,
+    pub total_value_zat: i64,
+    pub total_value_wec: Decimal,
+    pub share_percent: f64,
+    #[serde(default)]
+    pub value_share_percent: f64,
+}
+
+impl MinerStatsReport {
+    /// Rewrites every `*_datetime` field in `timezone`; [`compute_statistics`] writes them in
+    /// UTC.
+    pub fn set_timezone(&mut self, timezone: Tz) {
+        if let Some(range) = &mut self.time_range {
+            range.start_datetime = format_block_time(range.start_unix, timezone);
+            range.end_datetime = format_block_time(range.end_unix, timezone);
+        }
+        for detail in self
+            .detailed_miners
+            .iter_mut()
+            .flat_map(|miner| &mut miner.detailed_blocks)
+        {
+            detail.block_datetime = format_block_time(detail.block_time, timezone);
+        }
+        for miner in &mut self.detailed_miners {
+            if miner.day_of_week_stats.is_some() {
+                miner.day_of_week_stats = Some(day_of_week_stats(&miner.detailed_blocks, timezone));
+            }
+        }
+    }
+
+    /// Reorders `miners` and `detailed_miners`; `MinerSortOrder::Config` leaves them as is.
+    pub fn sort_miners(&mut self, order: MinerSortOrder) {
+        self.miners.sort_by(|a, b| {
+            order.compare(
+                (
+                    a.matched_blocks,
+                    a.total_value_zat,
+                    a.share_percent,
+                    &a.label,
+                ),
+                (
+                    b.matched_blocks,
+                    b.total_value_zat,
+                    b.share_percent,
+                    &b.label,
+                ),
+            )
+        });
+        self.detailed_miners.sort_by(|a, b| {
+            order.compare(
+                (
+                    a.matched_blocks,
+                    a.total_value_zat,
+                    a.share_percent,
+                    &a.label,
+                ),
+                (
+                    b.matched_blocks,
+                    b.total_value_zat,
+                    b.share_percent,
+                    &b.label,
+                ),
+            )
+        });
+    }
+
+    /// Loads a JSON report written by `write`, transparently gunzipping `.gz` output.
+    pub fn read(path: &Path) -> Result<Self> {
+        let raw = fs::read(path).map_err(Error::io("reading report", path))?;
+        let json = if raw.starts_with(&[0x1f, 0x8b]) {
+            let mut json = Vec::new();
+            GzDecoder::new(raw.as_slice())
+                .read_to_end(&mut json)
+                .map_err(Error::io("decompressing report", path))?;
+            json
+        } else {
+            raw
+        };
+        serde_json::from_slice(&json).map_err(|source| Error::ReportCorrupt {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Writes the report in `format`; with `compress`, gzipped to `path` plus `.gz`.
+    pub fn write(
+        &self,
+        path: &Path,
+        format: OutputFormat,
+        compress: bool,
+        human_values: bool,
+    ) -> Result<()> {
+        let bytes = match format {
+            OutputFormat::Json => if human_values {
+                serde_json::to_vec_pretty(&HumanValues(self))
+            } else {
+                serde_json::to_vec_pretty(self)
+            }
+            .map_err(|source| Error::Serialize {
+                what: format!("report {}", path.display()),
+                source,
+            })?,
+            OutputFormat::Csv => self.to_csv().into_bytes(),
+            OutputFormat::Html => html::render(self).into_bytes(),
+        };
+        write_report_bytes(path, &bytes, compress)
+    }
+
+    /// [`write`](Self::write) in JSON with only the aggregate figures: no
+    /// `detailed_miners`, which grows with every matched block, but the `Others` block
+    /// counts (`unmatched`) the full report leaves out.
+    pub fn write_summary(&self, path: &Path, compress: bool, human_values: bool) -> Result<()> {
+        let summary = ReportSummary {
+            start_height: self.start_height,
+            end_height: self.end_height,
+            total_mined_blocks: self.total_mined_blocks,
+            miners: &self.miners,
+            unmatched: &self.unmatched,
+        };
+        let bytes = if human_values {
+            serde_json::to_vec_pretty(&HumanValues(&summary))
+        } else {
+            serde_json::to_vec_pretty(&summary)
+        }
+        .map_err(|source| Error::Serialize {
+            what: format!("report {}", path.display()),
+            source,
+        })?;
+        write_report_bytes(path, &bytes, compress)
+    }
+
+    /// One row per miner plus a trailing `Others` row for unmatched blocks.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::new();
+        push_csv_row(
+            &mut out,
+            &[
+                "label",
+                "matched_blocks",
+                "total_value_zat",
+                "total_value_wec",
+                "share_percent",
+            ],
+        );
+        for miner in &self.miners {
+            push_csv_row(
+                &mut out,
+                &[
+                    &miner.label,
+                    &miner.matched_blocks.to_string(),
+                    &miner.total_value_zat.to_string(),
+                    &format!("{:.2}", miner.total_value_wec),
+                    &format!("{:.2}", miner.share_percent),
+                ],
+            );
+        }
+        push_csv_row(
+            &mut out,
+            &[
+                "Others",
+                &self.unmatched.blocks.to_string(),
+                &self.unmatched.total_value_zat.to_string(),
+                &format!("{:.2}", self.unmatched.total_value_wec),
+                &format!("{:.2}", self.unmatched.share_percent),
+            ],
+        );
+        out
+    }
+
+    /// One row per `MinerBlockDetail` across all miners, with an `annotation` column when
+    /// any of them is annotated.
+    pub fn write_detail_csv(&self, path: &Path) -> Result<()> {
+        let annotated = self
+            .detailed_miners
+            .iter()
+            .flat_map(|miner| &miner.detailed_blocks)
+            .any(|detail| detail.annotation.is_some());
+        let mut out = String::new();
+        let mut header = vec![
+            "label",
+            "block_height",
+            "block_hash",
+            "block_time",
+            "payout_address",
+        ];
+        if annotated {
+            header.push("annotation");
+        }
+        push_csv_row(&mut out, &header);
+        for miner in &self.detailed_miners {
+            for detail in &miner.detailed_blocks {
+                let height = detail.block_height.to_string();
+                let time = detail.block_time.to_string();
+                let mut row = vec![
+                    miner.label.as_str(),
+                    &height,
+                    &detail.block_hash,
+                    &time,
+                    &detail.payout_address,
+                ];
+                if annotated {
+                    row.push(detail.annotation.as_deref().unwrap_or_default());
+                }
+                push_csv_row(&mut out, &row);
+            }
+        }
+        fs::write(path, out).map_err(Error::io("writing detail CSV", path))
+    }
+
+    /// Writes each `MinerSummary` to `dir/{label}.json`, with labels sanitized for the
+    /// filesystem and colliding names disambiguated as `{label}-2.json`, `{label}-3.json`, ...
+    pub fn write_per_miner_files(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir).map_err(Error::io("creating per-miner output directory", dir))?;
+        let mut used = HashSet::new();
+        for miner in &self.detailed_miners {
+            let base = sanitize_file_stem(&truncate_label(&miner.label, FILE_STEM_MAX_WIDTH));
+            let mut stem = base.clone();
+            let mut suffix = 2;
+            while !used.insert(stem.to_lowercase()) {
+                stem = format!("{base}-{suffix}");
+                suffix += 1;
+            }
+            let path = dir.join(format!("{stem}.json"));
+            let json = serde_json::to_vec_pretty(miner).map_err(|source| Error::Serialize {
+                what: format!("miner report {}", path.display()),
+                source,
+            })?;
+            fs::write(&path, json).map_err(Error::io("writing miner report", &path))?;
+        }
+        Ok(())
+    }
+
+    /// Writes one JSON object per matched block (JSON Lines), grouped by miner.
+    pub fn write_stream(&self, path: &Path) -> Result<()> {
+        let mut out = Vec::new();
+        for miner in &self.detailed_miners {
+            for detail in &miner.detailed_blocks {
+                let record = StreamRecord {
+                    block_height: detail.block_height,
+                    block_hash: &detail.block_hash,
+                    miner_label: &miner.label,
+                    payout_address: &detail.payout_address,
+                    value_zat: detail.value_zat,
+                };
+                serde_json::to_writer(&mut out, &record).map_err(|source| Error::Serialize {
+                    what: format!("stream record {}", path.display()),
+                    source,
+                })?;
+                out.push(b'\n');
+            }
+        }
+        fs::write(path, out).map_err(Error::io("writing stream output", path))
+    }
+
+    /// Appends InfluxDB line protocol to `path`: a `miner_stats` point per miner, stamped
+    /// with the time of the last block in the range, and a `miner_block` point per matched
+    /// block, stamped with its block time. Appending lets repeated runs accumulate a time
+    /// series; Influx overwrites points whose tags and timestamp repeat.
+    pub fn write_influx(&self, path: &Path) -> Result<()> {
+        let range_end = self
+            .time_range
+            .as_ref()
+            .map(|range| influx_timestamp(range.end_unix));
+        let mut out = String::new();
+        for miner in &self.miners {
+            out.push_str(&format!(
+                "miner_stats,label={} matched_blocks={}i,value_zat={}i,share_percent={}{}\n",
+                influx_tag(&miner.label),
+                miner.matched_blocks,
+                miner.total_value_zat,
+                miner.share_percent,
+                range_end.as_deref().unwrap_or_default(),
+            ));
+        }
+        for miner in &self.detailed_miners {
+            for detail in &miner.detailed_blocks {
+                out.push_str(&format!(
+                    "miner_block,label={},height={} value_zat={}i{}\n",
+                    influx_tag(&miner.label),
+                    detail.block_height,
+                    detail.value_zat,
+                    influx_timestamp(detail.block_time),
+                ));
+            }
+        }
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(out.as_bytes()))
+            .map_err(Error::io("writing InfluxDB output", path))
+    }
+
+    /// Writes an Elasticsearch bulk API body (NDJSON) indexing every matched block into
+    /// `index`. Each document is `{miner_label}:{block_height}`, so indexing the same range
+    /// again replaces documents instead of duplicating them.
+    pub fn write_es_bulk(&self, path: &Path, index: &str) -> Result<()> {
+        let mut out = Vec::new();
+        for miner in &self.detailed_miners {
+            for detail in &miner.detailed_blocks {
+                let action = serde_json::json!({
+                    "index": {
+                        "_index": index,
+                        "_id": format!("{}:{}", miner.label, detail.block_height),
+                    }
+                });
+                let document = EsBlockDocument {
+                    miner_label: &miner.label,
+                    detail,
+                };
+                serde_json::to_writer(&mut out, &action)
+                    .and_then(|()| {
+                        out.push(b'\n');
+                        serde_json::to_writer(&mut out, &document)
+                    })
+                    .map_err(|source| Error::Serialize {
+                        what: format!("Elasticsearch bulk line {}", path.display()),
+                        source,
+                    })?;
+                out.push(b'\n');
+            }
+        }
+        fs::write(path, out).map_err(Error::io("writing Elasticsearch bulk output", path))
+    }
+}
+
+/// Writes a rendered report to `path`, or gzipped to `path` plus `.gz` with `compress`.
+fn write_report_bytes(path: &Path, bytes: &[u8], compress: bool) -> Result<()> {
+    if !compress {
+        return fs::write(path, bytes).map_err(Error::io("writing report", path));
+    }
+    let path = gz_path(path);
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(bytes)
+        .and_then(|()| encoder.finish())
+        .and_then(|gz| fs::write(&path, gz))
+        .map_err(Error::io("writing compressed report", &path))
+}
+
+/// Escapes the characters line protocol gives a meaning to in tag values.
+fn influx_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// ` <nanoseconds>`: the timestamp field of a line protocol point at Unix time `unix`.
+fn influx_timestamp(unix: u32) -> String {
+    format!(" {}", u64::from(unix) * 1_000_000_000)
+}
+
+/// Longest label, in display columns, used as a per-miner file name.
+const FILE_STEM_MAX_WIDTH: usize = 64;
+
+/// Maps a miner label to a portable file stem: anything other than ASCII alphanumerics,
+/// `-` and `_` becomes `_`.
+fn sanitize_file_stem(label: &str) -> String {
+    let stem: String = label
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if stem.is_empty() {
+        "miner".to_string()
+    } else {
+        stem
+    }
+}
+
+/// Appends `.gz` to `path` unless it already ends with it.
+pub(crate) fn gz_path(path: &Path) -> PathBuf {
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        path.to_path_buf()
+    } else {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".gz");
+        PathBuf::from(name)
+    }
+}
+
+/// Appends an RFC 4180 record: fields containing commas, quotes or line breaks are quoted
+/// with embedded quotes doubled, and the record ends with CRLF.
+pub(crate) fn push_csv_row(out: &mut String, fields: &[&str]) {
+    for (index, field) in fields.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        if field.contains([',', '"', '\r', '\n']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push_str("\r\n");
+}
+
+/// The first and last eight characters of a key or hash longer than sixteen. Cuts on char
+/// boundaries, since a mistyped key in a config file need not be ASCII.
+fn shorten_key(key: &str) -> String {
+    let chars = key.chars().count();
+    if chars <= 16 {
+        return key.to_string();
+    }
+    let offset = |nth: usize| key.char_indices().nth(nth).map_or(key.len(), |(i, _)| i);
+    format!("{}…{}", &key[..offset(8)], &key[offset(chars - 8)..])
+}
+
+/// Sums zat amounts, failing with [`Error::ValueOverflow`] (naming `what`) instead of
+/// wrapping if the total leaves the `i64` range.
+pub(crate) fn checked_zat_sum(
+    values: impl IntoIterator<Item = i64>,
+    what: impl FnOnce() -> String,
+) -> Result<i64> {
+    values
+        .into_iter()
+        .try_fold(0i64, i64::checked_add)
+        .ok_or_else(|| Error::ValueOverflow { what: what() })
+}
+
+const ZATS_PER_WEC: u64 = 100_000_000;
+
+/// Display form of an amount of `zats`. Plain, it is the WEC amount with two decimals, as in
+/// the report's `*_wec` fields. `human` adds a unit and keeps small amounts readable: two
+/// decimals from 1 WEC, six from 0.001 WEC, and whole zats below that (`12.50 WEC`,
+/// `0.004200 WEC`, `9000 zat`).
+pub fn format_value(zats: i64, human: bool) -> String {
+    let magnitude = zats.unsigned_abs();
+    if !human {
+        format!("{:.2}", zats_to_wec(zats))
+    } else if magnitude >= ZATS_PER_WEC {
+        format!("{:.2} WEC", zats_to_wec(zats))
+    } else if magnitude >= ZATS_PER_WEC / 1000 {
+        let wec =
+            Decimal::new(zats, 8).round_dp_with_strategy(6, RoundingStrategy::MidpointAwayFromZero);
+        format!("{wec:.6} WEC")
+    } else {
+        format!("{zats} zat")
+    }
+}
+
+/// A report serialized with every `*_wec` amount replaced by [`format_value`] of its `*_zat`
+/// counterpart (`--human-values`). Such reports are meant for reading: `diff` and `merge`
+/// expect the plain decimal amounts.
+struct HumanValues<'a, T>(&'a T);
+
+impl<T: Serialize> Serialize for HumanValues<'_, T> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let mut value = serde_json::to_value(self.0).map_err(serde::ser::Error::custom)?;
+        humanize_values(&mut value);
+        value.serialize(serializer)
+    }
+}
+
+fn humanize_values(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Array(items) => items.iter_mut().for_each(humanize_values),
+        serde_json::Value::Object(fields) => {
+            let amounts: Vec<(String, String)> = fields
+                .iter()
+                .filter_map(|(key, zats)| {
+                    let wec_key = format!("{}_wec", key.strip_suffix("_zat")?);
+                    let text = format_value(zats.as_i64()?, true);
+                    fields.contains_key(&wec_key).then_some((wec_key, text))
+                })
+                .collect();
+            for (key, text) in amounts {
+                fields.insert(key, serde_json::Value::String(text));
+            }
+            fields.values_mut().for_each(humanize_values);
+        }
+        _ => {}
+    }
+}
+
+/// Converts zats to WEC (1 WEC = 10^8 zats), rounded half away from zero to two decimal
+/// places. Exact for every `i64` input.
+pub fn zats_to_wec(zats: i64) -> Decimal {
+    Decimal::new(zats, 8).round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
+
+/// End heights of the `moving_avg_share` windows, oldest first: the last window ends at the
+/// tip and each earlier one `rolling_window_step` heights before the next. Only windows that
+/// lie entirely within the range count, and only the `max_windows` most recent. Empty
+/// unless `include_moving_avg` is set.
+fn rolling_window_ends(cfg: &MinerStatsConfig, tip_height: u64) -> Vec<u64> {
+    let mut ends = Vec::new();
+    if !cfg.include_moving_avg {
+        return ends;
+    }
+    let size = u64::from(cfg.rolling_window_blocks);
+    let mut end = tip_height;
+    while ends.len() < cfg.max_windows && end + 1 >= cfg.start_height + size {
+        ends.push(end);
+        let Some(earlier) = end.checked_sub(u64::from(cfg.rolling_window_step)) else {
+            break;
+        };
+        end = earlier;
+    }
+    ends.reverse();
+    ends
+}
+
+/// Share of `blocks` (ascending by height) among the scanned `heights` (ascending) in the
+/// window of `size` heights ending at each of `window_ends`.
+fn moving_avg_shares(
+    heights: &[u64],
+    blocks: &[MinerBlockDetail],
+    window_ends: &[u64],
+    size: u32,
+) -> Vec<WindowedShare> {
+    window_ends
+        .iter()
+        .map(|&end| {
+            let start = end + 1 - u64::from(size);
+            let scanned =
+                heights.partition_point(|h| *h <= end) - heights.partition_point(|h| *h < start);
+            let matched = blocks.partition_point(|b| b.block_height <= end)
+                - blocks.partition_point(|b| b.block_height < start);
+            WindowedShare {
+                window_end_height: end,
+                window_size: size,
+                share_percent: percent_share_blocks(matched as u32, scanned as u32),
+            }
+        })
+        .collect()
+}
+
+/// Index of the transparent child key that pays the block at `height`; `None` above the
+/// non-hardened range.
+fn derivation_index(height: u64) -> Option<NonHardenedChildIndex> {
+    u32::try_from(height)
+        .ok()
+        .and_then(NonHardenedChildIndex::from_index)
+}
+
+/// Confirmations a coinbase output needs before it can be spent; default `maturity_depth`.
+pub const COINBASE_MATURITY: u32 = 100;
+
+/// Buckets of every `value_histogram`.
+pub const HISTOGRAM_BUCKETS: usize = 20;
+
+/// The [`HISTOGRAM_BUCKETS`] + 1 bucket bounds dividing the range from the lowest to the
+/// highest of `values` into equal widths; `None` without values.
+fn histogram_edges(values: impl Iterator<Item = i64> + Clone) -> Option<Vec<i64>> {
+    let min = values.clone().min()?;
+    let max = values.max()?;
+    let span = i128::from(max) - i128::from(min);
+    Some(
+        (0..=HISTOGRAM_BUCKETS as i128)
+            .map(|i| (i128::from(min) + span * i / HISTOGRAM_BUCKETS as i128) as i64)
+            .collect(),
+    )
+}
+
+/// Counts `values` into the buckets between consecutive `edges`.
+fn histogram(edges: &[i64], values: impl Iterator<Item = i64>) -> Vec<HistogramBucket> {
+    let mut counts = vec![0u32; edges.len() - 1];
+    let inner = &edges[1..edges.len() - 1];
+    for value in values {
+        counts[inner.partition_point(|edge| *edge <= value)] += 1;
+    }
+    edges
+        .windows(2)
+        .zip(counts)
+        .map(|(bounds, block_count)| HistogramBucket {
+            min_zat: bounds[0],
+            max_zat: bounds[1],
+            block_count,
+        })
+        .collect()
+}
+
+/// Distance from the mean, in standard deviations, beyond which a block's coinbase value
+/// makes it an [`OutlierBlock`].
+pub const OUTLIER_Z_SCORE: f64 = 2.0;
+
+/// The `top_n_blocks` blocks of `coinbase_totals` with the highest value, highest first and
+/// lowest height first among equal values. `matched_miner` is looked up in
+/// `credited`, the label of the first miner matching each height.
+fn top_blocks(
+    cfg: &MinerStatsConfig,
+    cache: &BlockCache,
+    coinbase_totals: &HashMap<u64, i64>,
+    credited: &HashMap<u64, &str>,
+) -> Vec<TopBlock> {
+    let mut totals: Vec<(u64, i64)> = coinbase_totals.iter().map(|(h, v)| (*h, *v)).collect();
+    totals.sort_by_key(|(height, value)| (Reverse(*value), *height));
+    totals
+        .into_iter()
+        .take(cfg.top_n_blocks)
+        .map(|(height, total_coinbase_zat)| TopBlock {
+            height,
+            hash: cache.blocks[&height].hash.clone(),
+            total_coinbase_zat,
+            matched_miner: credited.get(&height).map(|label| label.to_string()),
+        })
+        .collect()
+}
+
+/// The blocks of `coinbase_totals` beyond [`OUTLIER_Z_SCORE`], by height. The mean and
+/// population standard deviation come from a single pass of Welford's online algorithm,
+/// which stays accurate where summing squares of zat amounts would lose precision.
+fn outlier_blocks(coinbase_totals: &HashMap<u64, i64>) -> Vec<OutlierBlock> {
+    let (mut count, mut mean, mut squared_deviations) = (0u64, 0.0f64, 0.0f64);
+    for &value in coinbase_totals.values() {
+        count += 1;
+        let value = value as f64;
+        let delta = value - mean;
+        mean += delta / count as f64;
+        squared_deviations += delta * (value - mean);
+    }
+    let std_dev = (squared_deviations / count.max(1) as f64).sqrt();
+    if std_dev == 0.0 {
+        return Vec::new();
+    }
+    let mut outliers: Vec<OutlierBlock> = coinbase_totals
+        .iter()
+        .filter_map(|(&height, &value_zat)| {
+            let z_score = (value_zat as f64 - mean) / std_dev;
+            (z_score.abs() > OUTLIER_Z_SCORE).then(|| OutlierBlock {
+                height,
+                value_zat,
+                z_score: (z_score * 100.0).round() / 100.0,
+            })
+        })
+        .collect();
+    outliers.sort_by_key(|block| block.height);
+    outliers
+}
+
+/// Target spacing between blocks since Blossom, in seconds.
+const TARGET_BLOCK_SPACING_SECS: f64 = 75.0;
+
+/// Days between the first and last block of `start_height..=end_height`, from their
+/// timestamps in `time_range`. Legacy caches have no timestamps (`0`), so then the time is
+/// estimated from the target block spacing instead.
+pub(crate) fn elapsed_days(
+    time_range: Option<&TimeRange>,
+    start_height: u64,
+    end_height: u64,
+) -> f64 {
+    match time_range {
+        Some(range) if range.start_unix > 0 => {
+            range.end_unix.saturating_sub(range.start_unix) as f64 / 86_400.0
+        }
+        _ => end_height.saturating_sub(start_height) as f64 * TARGET_BLOCK_SPACING_SECS / 86_400.0,
+    }
+}
+
+fn blocks_per_day(blocks: u32, elapsed_days: f64) -> f64 {
+    if elapsed_days <= 0.0 {
+        0.0
+    } else {
+        ((blocks as f64 / elapsed_days) * 100.0).round() / 100.0
+    }
+}
+
+/// Part of `block`'s coinbase value `coinbase_zat` above its subsidy. Never negative, as
+/// coinbases may pay out less than they are allowed to.
+fn block_fees(block: &CachedBlock, coinbase_zat: i64, chain: &ChainType) -> i64 {
+    let subsidy = block
+        .subsidy_zat
+        .unwrap_or_else(|| block_subsidy(block.height, chain));
+    coinbase_zat.saturating_sub(subsidy).max(0)
+}
+
+fn total_fees(miner: &MinerSummary) -> Result<i64> {
+    checked_zat_sum(miner.detailed_blocks.iter().map(|b| b.fees_zat), || {
+        format!("fees of miner {:?}", miner.label)
+    })
+}
+
+/// First and last height of `blocks` and how far the last lies below `tip_height`; all
+/// `None` for no blocks, and the last `None` if the last block is the tip.
+pub(crate) fn block_activity(
+    blocks: &[MinerBlockDetail],
+    tip_height: u64,
+) -> (Option<u64>, Option<u64>, Option<u64>) {
+    let first = blocks.iter().map(|b| b.block_height).min();
+    let last = blocks.iter().map(|b| b.block_height).max();
+    let inactive_since = last
+        .filter(|last| *last < tip_height)
+        .map(|last| tip_height - last);
+    (first, last, inactive_since)
+}
+
+/// Value of `blocks` with at least `depth` blocks mined on top of them at `tip_height`, and
+/// of the rest, as (mature, immature).
+pub(crate) fn maturity_split(
+    blocks: &[MinerBlockDetail],
+    tip_height: u64,
+    depth: u32,
+) -> (i64, i64) {
+    let mature_below = tip_height.checked_sub(u64::from(depth));
+    blocks.iter().fold((0, 0), |(mature, immature), block| {
+        if mature_below.is_some_and(|limit| block.block_height <= limit) {
+            (mature + block.value_zat, immature)
+        } else {
+            (mature, immature + block.value_zat)
+        }
+    })
+}
+
+/// Runs of more than `threshold` heights without one of `blocks`: between two of them, and
+/// from the last up to `tip_height`. Heights before the first block are not counted, as the
+/// miner may simply not have started yet. Nothing when `threshold` is 0.
+fn inactivity_periods(
+    blocks: &[MinerBlockDetail],
+    threshold: u32,
+    tip_height: u64,
+) -> Vec<InactivityPeriod> {
+    if threshold == 0 {
+        return Vec::new();
+    }
+    let mut heights: Vec<u64> = blocks.iter().map(|b| b.block_height).collect();
+    heights.sort_unstable();
+    let Some(&last) = heights.last() else {
+        return Vec::new();
+    };
+    heights
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .chain((last < tip_height).then_some((last, tip_height + 1)))
+        .filter(|(before, after)| after - before - 1 > u64::from(threshold))
+        .map(|(before, after)| InactivityPeriod {
+            from_height: before + 1,
+            to_height: after - 1,
+            duration_blocks: after - before - 1,
+        })
+        .collect()
+}
+
+/// Longest and current (ending at `tip_height`) runs of consecutive heights in `blocks`.
+/// Each miner's blocks are taken on their own, so a height matched by two miners extends
+/// the streaks of both.
+fn block_streaks(blocks: &[MinerBlockDetail], tip_height: u64) -> (u32, u32) {
+    let mut heights: Vec<u64> = blocks.iter().map(|b| b.block_height).collect();
+    heights.sort_unstable();
+    heights.dedup();
+    let mut longest_streak = 0;
+    let mut run = 0;
+    let mut previous = None;
+    for &height in &heights {
+        run = if previous.is_some_and(|p: u64| p + 1 == height) {
+            run + 1
+        } else {
+            1
+        };
+        longest_streak = longest_streak.max(run);
+        previous = Some(height);
+    }
+    let current_streak = if previous == Some(tip_height) { run } else { 0 };
+    (longest_streak, current_streak)
+}
+
+/// `part` as a percentage of `total`, rounded to two decimals; 0 when `total` is 0.
+pub fn percent_share_blocks(part: u32, total: u32) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (((part as f64) / (total as f64)) * 100.0 * 100.0).round() / 100.0
+    }
+}
+
+/// Value counterpart of [`percent_share_blocks`].
+fn percent_share_value(part: i64, total: i64) -> f64 {
+    if total <= 0 {
+        0.0
+    } else {
+        (((part as f64) / (total as f64)) * 100.0 * 100.0).round() / 100.0
+    }
+}
+
+/// The figures of one table row.
+struct RowFigures {
+    blocks: u32,
+    blocks_per_day: f64,
+    value_zat: i64,
+    share_percent: f64,
+    /// The `% Value` column, when shown.
+    value_share_percent: Option<f64>,
+    /// The first, last and inactive-since columns, when shown; `-` for `None`.
+    activity: Option<[Option<u64>; 3]>,
+    /// The mature and immature value columns, when shown; `-` for `None`.
+    maturity: Option<[Option<i64>; 2]>,
+}
+
+fn table_row(label: &str, figures: RowFigures, width: usize, human_values: bool) -> String {
+    let value_width = value_column_width(human_values);
+    let mut row = format!(
+        "| {} | {:>10} | {:>10.2} | {:>value_width$} | {:>9.2}% |",
+        pad_to_width(&truncate_label(label, width), width),
+        figures.blocks,
+        figures.blocks_per_day,
+        format_value(figures.value_zat, human_values),
+        figures.share_percent,
+    );
+    if let Some(value_share) = figures.value_share_percent {
+        row.push_str(&format!(" {value_share:>9.2}% |"));
+    }
+    for height in figures.activity.into_iter().flatten() {
+        let cell = height.map_or_else(|| "-".to_string(), |height| height.to_string());
+        row.push_str(&format!(" {cell:>10} |"));
+    }
+    for value in figures.maturity.into_iter().flatten() {
+        let cell = value.map_or_else(
+            || "-".to_string(),
+            |value| format_value(value, human_values),
+        );
+        row.push_str(&format!(" {cell:>value_width$} |"));
+    }
+    row
+}
+
+/// Left-aligns `text` in `width` terminal columns. `{:<20}` pads by `char` count, which
+/// misaligns labels with double-width characters (CJK, emoji).
+fn pad_to_width(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text.width());
+    format!("{text}{}", " ".repeat(padding))
+}
+
+/// Cuts `label` at the last grapheme boundary that leaves room for a trailing `…` within
+/// `max_display_width` columns; labels that already fit are borrowed unchanged.
+pub fn truncate_label(label: &str, max_display_width: usize) -> Cow<'_, str> {
+    if label.width() <= max_display_width {
+        return Cow::Borrowed(label);
+    }
+    let budget = max_display_width.saturating_sub(1);
+    let mut used = 0;
+    let mut truncated = String::new();
+    for grapheme in label.graphemes(true) {
+        used += grapheme.width();
+        if used > budget {
+            break;
+        }
+        truncated.push_str(grapheme);
+    }
+    truncated.push('…');
+    Cow::Owned(truncated)
+}
+
+/// Width of the table's value column; amounts with a unit need more room.
+fn value_column_width(human_values: bool) -> usize {
+    if human_values { 15 } else { 10 }
+}
+
+fn print_table(report: &MinerStatsReport, options: &RunOptions) {
+    let RunOptions {
+        color,
+        label_width,
+        show_value_share,
+        human_values,
+        show_activity,
+        show_maturity,
+        ..
+    } = *options;
+    let borders = !options.table_no_border;
+    let paint = |line: String, style: Option<Style>| match style {
+        Some(style) if color => line.style(style).to_string(),
+        _ => line,
+    };
+    let leader = report
+        .miners
+        .iter()
+        .filter(|m| m.matched_blocks > 0)
+        .reduce(|best, m| {
+            if m.share_percent > best.share_percent {
+                m
+            } else {
+                best
+            }
+        })
+        .map(|m| m.label.as_str());
+    let extra_column = |cell: &'static str| if show_value_share { cell } else { "" };
+    let activity_columns = |cells: &'static str| if show_activity { cells } else { "" };
+    let value_width = value_column_width(human_values);
+    let maturity_columns = |cell: String| {
+        if show_maturity {
+            cell.repeat(2)
+        } else {
+            String::new()
+        }
+    };
+    let border_line = format!(
+        "+{}+------------+------------+{}+------------+{}{}{}",
+        "-".repeat(label_width + 2),
+        "-".repeat(value_width + 2),
+        extra_column("------------+"),
+        activity_columns("------------+------------+------------+"),
+        maturity_columns(format!("{}+", "-".repeat(value_width + 2)))
+    );
+    let border = || {
+        if borders {
+            println!("{border_line}");
+        }
+    };
+
+    println!(
+        "\nMiner stats for heights {}-{} (total {}):",
+        report.start_height,
+        report.end_height,
+        if human_values {
+            format_value(report.total_value_zat, true)
+        } else {
+            format!("{} WEC", format_value(report.total_value_zat, false))
+        }
+    );
+    border();
+    println!(
+        "{}",
+        paint(
+            format!(
+                "| {} | {:>10} | {:>10} | {:>value_width$} | {:>10} |{}{}{}",
+                pad_to_width("Label", label_width),
+                "Blocks",
+                "Blocks/day",
+                if human_values { "Value" } else { "WEC" },
+                "% Share",
+                extra_column("    % Value |"),
+                activity_columns("      First |       Last |   Inactive |"),
+                if show_maturity {
+                    format!(
+                        " {:>value_width$} | {:>value_width$} |",
+                        "Mature", "Immature"
+                    )
+                } else {
+                    String::new()
+                }
+            ),
+            Some(Style::new().bold().cyan())
+        )
+    );
+    border();
+    for miner in &report.miners {
+        let row = miner.table_row(
+            label_width,
+            show_value_share,
+            human_values,
+            show_activity,
+            show_maturity,
+        );
+        let style = if miner.value_mismatch() {
+            Some(Style::new().red())
+        } else {
+            (leader == Some(miner.label.as_str())).then(|| Style::new().bold().green())
+        };
+        println!("{}", paint(row, style));
+    }
+    if borders {
+        println!(
+            "| {} | {:_<10} | {:_<10} | {} | {:_<10} |{}{}{}",
+            "_".repeat(label_width),
+            "",
+            "",
+            "_".repeat(value_width),
+            "",
+            extra_column(" __________ |"),
+            activity_columns(" __________ | __________ | __________ |"),
+            maturity_columns(format!(" {} |", "_".repeat(value_width)))
+        );
+    }
+    let others = table_row(
+        "Others",
+        RowFigures {
+            blocks: report.unmatched.blocks,
+            blocks_per_day: report.unmatched.blocks_per_day,
+            value_zat: report.unmatched.total_value_zat,
+            share_percent: report.unmatched.share_percent,
+            value_share_percent: show_value_share.then_some(report.unmatched.value_share_percent),
+            activity: show_activity.then_some([None; 3]),
+            maturity: show_maturity.then_some([None; 2]),
+        },
+        label_width,
+        human_values,
+    );
+    let style = (report.unmatched.share_percent > 50.0).then(|| Style::new().yellow());
+    println!("{}", paint(others, style));
+    border();
+}
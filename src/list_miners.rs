@@ -0,0 +1,59 @@
+//! `--list-miners`: prints the configured miners and the address each key pays at height 0,
+//! then the `address_map_file` entries, without touching the cache or the node.
+
+use crate::{
+    MinerStatsConfig, error::Result, pad_to_width, shorten_key, truncate_label,
+    validate_config::derive_first_address,
+};
+use std::path::Path;
+
+const LABEL_WIDTH: usize = 20;
+const KEY_WIDTH: usize = 17;
+const ADDRESS_WIDTH: usize = 35;
+
+/// Loads the config and prints one table row per configured key and mapped address.
+/// Deriving the height-0 address doubles as a check that the key decodes; returns whether
+/// every key did, with the reasons for those that did not on stderr.
+pub fn run(config_path: &Path) -> Result<bool> {
+    let cfg = MinerStatsConfig::from_file(config_path)?;
+    let border = format!(
+        "+-{}-+-{}-+-{}-+",
+        "-".repeat(LABEL_WIDTH),
+        "-".repeat(KEY_WIDTH),
+        "-".repeat(ADDRESS_WIDTH)
+    );
+    println!("{border}");
+    println!(
+        "| {:<LABEL_WIDTH$} | {:<KEY_WIDTH$} | {:<ADDRESS_WIDTH$} |",
+        "Label", "UFVK", "Address at height 0"
+    );
+    println!("{border}");
+    let mut valid = true;
+    for miner in &cfg.miners {
+        for key in &miner.keys {
+            let address = derive_first_address(&cfg.chain, key).unwrap_or_else(|reason| {
+                valid = false;
+                eprintln!("{} ({}): {reason}", miner.label, shorten_key(key));
+                "invalid key".to_string()
+            });
+            println!(
+                "| {} | {} | {:<ADDRESS_WIDTH$} |",
+                pad_to_width(&truncate_label(&miner.label, LABEL_WIDTH), LABEL_WIDTH),
+                pad_to_width(&shorten_key(key), KEY_WIDTH),
+                address
+            );
+        }
+    }
+    let mut mapped: Vec<_> = cfg.address_map.iter().collect();
+    mapped.sort_by_key(|(address, label)| (*label, *address));
+    for (address, label) in mapped {
+        println!(
+            "| {} | {} | {:<ADDRESS_WIDTH$} |",
+            pad_to_width(&truncate_label(label, LABEL_WIDTH), LABEL_WIDTH),
+            pad_to_width("address map", KEY_WIDTH),
+            address
+        );
+    }
+    println!("{border}");
+    Ok(valid)
+}
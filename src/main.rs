@@ -1,534 +1,382 @@
 #![forbid(unsafe_code)]
 
-use anyhow::{Context, Result};
-use clap::Parser;
-use pepper_sync::keys::transparent::{self, TransparentScope};
-use rayon::prelude::*;
-use serde::{Deserialize, Serialize};
+use clap::{Parser, Subcommand, ValueEnum, builder::RangedU64ValueParser};
 use std::{
-    collections::{BTreeMap, BTreeSet, HashMap},
-    fs,
-    path::{Path, PathBuf},
-    sync::Arc,
+    io::{IsTerminal, Write},
+    path::PathBuf,
+    process::ExitCode,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tracing::{Level, level_filters::LevelFilter};
+use tracing_subscriber::{filter::filter_fn, prelude::*};
+use uview_miner_stats_lib::{
+    DEFAULT_ES_INDEX, DEFAULT_LABEL_WIDTH, Error, MinerSortOrder, MinerStatsConfig, OutputFormat,
+    RunOptions, Tz, cache_export, cache_repair, cache_stats, diff, list_miners, merge,
+    sharded_cache,
+    telemetry::{self, OtelGuard},
+    validate_config,
 };
-use zcash_primitives::legacy::keys::NonHardenedChildIndex;
-use zingolib::config::{ChainType, chain_from_str};
-use zingolib::wallet::keys::unified::UnifiedKeyStore;
 
 #[derive(Parser, Debug)]
 struct Cli {
     /// Path to the miner stats configuration file (TOML)
     #[arg(long, default_value = "miner-stats-config.toml")]
     config: PathBuf,
-}
-
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let cfg = MinerStatsConfig::from_file(&cli.config)?;
-    let mut cache = BlockCache::load(&cfg.cache_file)?;
-    let client = NodeRpcClient::new(&cfg.rpc_url)?;
-
-    let tip_height = client.block_count().context("fetching block count")?;
-    if tip_height < cfg.start_height {
-        anyhow::bail!(
-            "tip height {tip_height} is below configured start height {}",
-            cfg.start_height
-        );
-    }
-
-    let heights: Vec<u32> = (cfg.start_height..=tip_height).collect();
-    let missing: Vec<u32> = heights
-        .iter()
-        .copied()
-        .filter(|h| !cache.blocks.contains_key(h))
-        .collect();
-    if !missing.is_empty() {
-        println!("Fetching {} blocks from RPC...", missing.len());
-        let client = Arc::new(client.clone());
-        let fetched: Result<Vec<CachedBlock>> = missing
-            .par_iter()
-            .map(|height| client.fetch_block(*height))
-            .collect();
-        for block in fetched? {
-            cache.blocks.insert(block.height, block);
+    /// Minimum level of log events written to stderr [default: info, or the level implied
+    /// by --quiet/--verbose]
+    #[arg(long, value_enum, global = true)]
+    log_level: Option<LogLevel>,
+    /// Print nothing but warnings and errors: no progress bar, summary or table
+    #[arg(long, short, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Also log every matched block while computing the statistics
+    #[arg(long, short, global = true)]
+    verbose: bool,
+    /// Log line format
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+    /// Format of the report written to `output_file`
+    #[arg(long, value_enum, default_value_t = OutputFormat::Json)]
+    output_format: OutputFormat,
+    /// Also write one CSV row per matched block to this path
+    #[arg(long)]
+    detail_output: Option<PathBuf>,
+    /// Also write one JSON object per matched block (JSON Lines) to this path
+    #[arg(long)]
+    stream_output: Option<PathBuf>,
+    /// Also append per-miner and per-block points in InfluxDB line protocol to this path
+    #[arg(long)]
+    influx_output: Option<PathBuf>,
+    /// Also write the matched blocks to this path as an Elasticsearch bulk API request
+    #[arg(long)]
+    es_output: Option<PathBuf>,
+    /// Index named in the `--es-output` actions
+    #[arg(long, default_value = DEFAULT_ES_INDEX, requires = "es_output")]
+    es_index: String,
+    /// Order of the miners in the report and the table
+    #[arg(long, value_enum, default_value_t = MinerSortOrder::Config)]
+    sort_by: MinerSortOrder,
+    /// Display width of the table's label column, at least 8; longer labels are cut with `…`
+    #[arg(
+        long,
+        visible_alias = "table-label-width",
+        default_value_t = DEFAULT_LABEL_WIDTH,
+        value_parser = RangedU64ValueParser::<usize>::new().range(8..)
+    )]
+    label_width: usize,
+    /// Add a column with each miner's share of the total value to the table
+    #[arg(long)]
+    show_value_share: bool,
+    /// Print the table without border lines, for piping through `awk` or `cut`
+    #[arg(long)]
+    table_no_border: bool,
+    /// Omit the `outlier_blocks` section from the report
+    #[arg(long)]
+    no_outliers: bool,
+    /// Add each miner's first and last matched height and blocks since the last to the table
+    #[arg(long)]
+    show_activity: bool,
+    /// Add each miner's mature (spendable) and immature value to the table
+    #[arg(long)]
+    show_maturity: bool,
+    /// Show amounts in the table and the JSON report with a unit (`12.50 WEC`,
+    /// `0.004200 WEC`, `9000 zat`) so small values do not round to 0.00
+    #[arg(long)]
+    human_values: bool,
+    /// IANA timezone of the datetimes in the report, e.g. `America/New_York`
+    #[arg(long, default_value = "UTC", value_parser = parse_timezone)]
+    timezone: Tz,
+    /// Browse the report in an interactive terminal viewer instead of printing the table;
+    /// prints the table as usual when stdout is not a terminal (needs the `tui` feature)
+    #[arg(long, conflicts_with = "watch")]
+    tui: bool,
+    /// Keep running after the first report, polling the node every INTERVAL_SECS (default
+    /// 60) and updating the cache, reports and table until Ctrl-C
+    #[arg(
+        long,
+        value_name = "INTERVAL_SECS",
+        num_args = 0..=1,
+        default_missing_value = "60",
+        value_parser = RangedU64ValueParser::<u64>::new().range(1..)
+    )]
+    watch: Option<u64>,
+    /// Gzip the report and write it to `output_file` with a `.gz` suffix
+    #[arg(long)]
+    compress_output: bool,
+    /// Write the report uncompressed even if the config sets `compress_output`; also applies
+    /// to `--dry-run` and the report path `validate-config` prints
+    #[arg(long, conflicts_with = "compress_output")]
+    decompress_output: bool,
+    /// Leave the per-block `detailed_miners` out of a JSON report
+    #[arg(long)]
+    summary_only: bool,
+    /// Fetch every block from the node without reading or writing the cache
+    #[arg(long)]
+    no_cache: bool,
+    /// Discard the cached blocks and fetch the whole range again, overwriting the cache;
+    /// asks first unless --confirm is given
+    #[arg(long, conflicts_with = "no_cache")]
+    force_refetch: bool,
+    /// Skip the --force-refetch prompt (required when stdout is not a terminal)
+    #[arg(long, requires = "force_refetch")]
+    confirm: bool,
+    /// Remove cached blocks below `start_height` before syncing
+    #[arg(long)]
+    prune_cache: bool,
+    /// Compute and print the statistics without writing the cache or any report file
+    #[arg(long)]
+    dry_run: bool,
+    /// Print the configured miners with each key's address at height 0 and exit, without
+    /// loading the cache or contacting the node; exits with 1 if any key fails to decode
+    #[arg(long)]
+    list_miners: bool,
+    /// Never color the table (also implied by a non-empty `NO_COLOR` environment variable)
+    #[arg(long, global = true, conflicts_with = "force_color")]
+    no_color: bool,
+    /// Color the table even when stdout is not a terminal (e.g. for `less -R`)
+    #[arg(long, global = true)]
+    force_color: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare two JSON reports and show per-miner changes
+    Diff {
+        /// Earlier report (JSON, optionally gzipped)
+        old: PathBuf,
+        /// Later report (JSON, optionally gzipped)
+        new: PathBuf,
+        /// Print the comparison as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Summarize the configured block cache without modifying it or contacting the node
+    CacheStats {
+        /// Print the summary as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Re-fetch cached blocks that are missing, empty or no longer match the node
+    CacheRepair,
+    /// Copy the single-file cache into shards of `cache_shard_size` heights
+    CacheShard,
+    /// Write the cached blocks to a CSV file, one row per block
+    CacheExportCsv {
+        /// Path of the CSV file
+        output: PathBuf,
+        /// Write one row per coinbase output instead, with its index, value and addresses
+        #[arg(long)]
+        expand_outputs: bool,
+    },
+    /// Check the config and every UFVK without contacting the node; exits with 1 if any
+    /// check fails
+    ValidateConfig,
+    /// Combine reports for non-overlapping height ranges into one report
+    Merge {
+        /// Reports to combine (JSON, optionally gzipped)
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+        /// Path of the merged JSON report
+        #[arg(long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => Level::TRACE,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Error => Level::ERROR,
         }
-        cache.last_tip = Some(tip_height);
-        cache.save(&cfg.cache_file)?;
-    } else if cache.last_tip != Some(tip_height) {
-        cache.last_tip = Some(tip_height);
-        cache.save(&cfg.cache_file)?;
     }
-
-    let report = compute_statistics(&cfg, &cache, tip_height)?;
-    report.write(&cfg.output_file)?;
-    println!(
-        "Processed heights {}-{}; matched {} blocks across {} miners.",
-        cfg.start_height,
-        tip_height,
-        report.total_mined_blocks,
-        report.miners.len()
-    );
-    print_table(&report);
-    Ok(())
-}
-
-#[derive(Debug, Deserialize)]
-struct ConfigFile {
-    start_height: u32,
-    chain: String,
-    rpc_url: String,
-    ufvks: Vec<MinerConfigEntry>,
-    cache_file: PathBuf,
-    output_file: PathBuf,
 }
 
-#[derive(Debug, Deserialize)]
-struct MinerConfigEntry {
-    key: String,
-    label: String,
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
 }
 
-#[derive(Debug)]
-struct MinerStatsConfig {
-    start_height: u32,
-    chain: ChainType,
-    rpc_url: String,
-    miners: Vec<MinerEntry>,
-    cache_file: PathBuf,
-    output_file: PathBuf,
-}
-
-#[derive(Debug, Clone)]
-struct MinerEntry {
-    key: String,
-    label: String,
-}
-
-impl MinerStatsConfig {
-    fn from_file(path: &Path) -> Result<Self> {
-        let raw = fs::read_to_string(path)
-            .with_context(|| format!("reading config file {}", path.display()))?;
-        let cfg: ConfigFile = toml::from_str(&raw)
-            .with_context(|| format!("parsing config file {}", path.display()))?;
-        if cfg.ufvks.is_empty() {
-            anyhow::bail!("config must contain at least one UFVK entry");
-        }
-        if let Some(parent) = cfg.cache_file.parent() {
-            if !parent.as_os_str().is_empty() {
-                fs::create_dir_all(parent).with_context(|| {
-                    format!("creating cache file directory {}", parent.display())
-                })?;
+fn main() -> anyhow::Result<ExitCode> {
+    let cli = Cli::parse();
+    let level = cli.log_level.unwrap_or(if cli.quiet {
+        LogLevel::Warn
+    } else if cli.verbose {
+        LogLevel::Debug
+    } else {
+        LogLevel::Info
+    });
+    // Only a scan exports spans; the other subcommands are short and local.
+    let otlp_endpoint = match cli.command {
+        None if !cli.list_miners => telemetry::otlp_endpoint(&cli.config),
+        _ => None,
+    };
+    if cli.tui && !cfg!(feature = "tui") {
+        anyhow::bail!("--tui requires building with the `tui` feature");
+    }
+    let _otel = init_logging(level, cli.log_format, otlp_endpoint.as_deref())?;
+    match &cli.command {
+        Some(Command::Diff { old, new, json }) => diff::run(old, new, *json)?,
+        Some(Command::Merge { inputs, output }) => merge::run(inputs, output)?,
+        Some(Command::CacheStats { json }) => cache_stats::run(&cli.config, *json)?,
+        Some(Command::CacheRepair) => cache_repair::run(&cli.config)?,
+        Some(Command::CacheShard) => sharded_cache::run(&cli.config)?,
+        Some(Command::CacheExportCsv {
+            output,
+            expand_outputs,
+        }) => cache_export::run(&cli.config, output, *expand_outputs)?,
+        Some(Command::ValidateConfig) => {
+            let compress = if cli.decompress_output {
+                Some(false)
+            } else {
+                cli.compress_output.then_some(true)
+            };
+            if !validate_config::run(&cli.config, compress)? {
+                return Ok(ExitCode::FAILURE);
             }
         }
-        if let Some(parent) = cfg.output_file.parent() {
-            if !parent.as_os_str().is_empty() {
-                fs::create_dir_all(parent).with_context(|| {
-                    format!("creating output file directory {}", parent.display())
-                })?;
+        None if cli.list_miners => {
+            if !list_miners::run(&cli.config)? {
+                return Ok(ExitCode::FAILURE);
             }
         }
-        let chain = chain_from_str(&cfg.chain)
-            .map_err(|e| anyhow::anyhow!("invalid chain '{}': {}", cfg.chain, e))?;
-        let miners = cfg
-            .ufvks
-            .into_iter()
-            .map(|entry| MinerEntry {
-                key: entry.key,
-                label: entry.label,
-            })
-            .collect();
-
-        Ok(Self {
-            start_height: cfg.start_height,
-            chain,
-            rpc_url: cfg.rpc_url,
-            miners,
-            cache_file: cfg.cache_file,
-            output_file: cfg.output_file,
-        })
-    }
-}
-
-#[derive(Default, Serialize, Deserialize)]
-struct BlockCache {
-    last_tip: Option<u32>,
-    blocks: BTreeMap<u32, CachedBlock>,
-}
-
-impl BlockCache {
-    fn load(path: &Path) -> Result<Self> {
-        if !path.exists() {
-            return Ok(Self::default());
+        None if cli.force_refetch && !confirm_refetch(&cli)? => {
+            println!("Aborted; the cache was left as is.");
+            return Ok(ExitCode::FAILURE);
         }
-        let raw = fs::read(path).with_context(|| format!("reading cache {}", path.display()))?;
-        let cache = serde_json::from_slice(&raw)
-            .with_context(|| format!("parsing cache {}", path.display()))?;
-        Ok(cache)
-    }
-
-    fn save(&self, path: &Path) -> Result<()> {
-        let json = serde_json::to_vec_pretty(self)
-            .with_context(|| format!("serializing cache {}", path.display()))?;
-        fs::write(path, json).with_context(|| format!("writing cache {}", path.display()))
+        None => match uview_miner_stats_lib::run(
+            &cli.config,
+            &RunOptions {
+                output_format: cli.output_format,
+                detail_output: cli.detail_output.clone(),
+                stream_output: cli.stream_output.clone(),
+                influx_output: cli.influx_output.clone(),
+                es_output: cli.es_output.clone(),
+                es_index: cli.es_index.clone(),
+                compress_output: cli.compress_output,
+                decompress_output: cli.decompress_output,
+                summary_only: cli.summary_only,
+                no_cache: cli.no_cache,
+                force_refetch: cli.force_refetch,
+                dry_run: cli.dry_run,
+                color: use_color(&cli),
+                sort_by: cli.sort_by,
+                prune_cache: cli.prune_cache,
+                label_width: cli.label_width,
+                show_value_share: cli.show_value_share,
+                table_no_border: cli.table_no_border,
+                no_outliers: cli.no_outliers,
+                show_activity: cli.show_activity,
+                show_maturity: cli.show_maturity,
+                human_values: cli.human_values,
+                timezone: cli.timezone,
+                tui: cli.tui,
+                watch: cli.watch.map(Duration::from_secs),
+                interrupt: interrupt_on_ctrl_c()?,
+            },
+        ) {
+            Err(Error::Interrupted) => return Ok(ExitCode::from(130)),
+            result => result?,
+        },
     }
+    Ok(ExitCode::SUCCESS)
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-struct CachedBlock {
-    height: u32,
-    hash: String,
-    outputs: Vec<CoinbaseOutput>,
-}
-
-#[derive(Clone, Serialize, Deserialize)]
-struct CoinbaseOutput {
-    value_zat: i64,
-    addresses: Vec<String>,
-}
-
-#[derive(Clone)]
-struct NodeRpcClient {
-    client: reqwest::blocking::Client,
-    url: String,
-}
-
-impl NodeRpcClient {
-    fn new(url: &str) -> Result<Self> {
-        let client = reqwest::blocking::Client::builder()
-            .timeout(std::time::Duration::from_secs(10))
-            .build()
-            .context("constructing RPC client")?;
-        Ok(Self {
-            client,
-            url: url.to_string(),
-        })
-    }
-
-    fn block_count(&self) -> Result<u32> {
-        self.call_method::<u32>("getblockcount", serde_json::json!([]))
-    }
-
-    fn fetch_block(&self, height: u32) -> Result<CachedBlock> {
-        let hash: String = self.call_method("getblockhash", serde_json::json!([height]))?;
-        let block: BlockResult =
-            self.call_method("getblock", serde_json::json!([hash.clone(), 2]))?;
-        let outputs = block.coinbase_outputs();
-        Ok(CachedBlock {
-            height,
-            hash,
-            outputs,
-        })
-    }
-
-    fn call_method<T: for<'a> Deserialize<'a>>(
-        &self,
-        method: &str,
-        params: serde_json::Value,
-    ) -> Result<T> {
-        let request = RpcRequest {
-            jsonrpc: "2.0",
-            id: "zingo-miner-stats",
-            method,
-            params,
-        };
-        let response = self
-            .client
-            .post(&self.url)
-            .json(&request)
-            .send()
-            .with_context(|| format!("calling RPC method {method}"))?;
-        let status = response.status();
-        if !status.is_success() {
-            anyhow::bail!("RPC {method} failed: HTTP {status}");
+/// Flag set by the first Ctrl-C so the sync can stop and save what it fetched; a second
+/// Ctrl-C exits at once.
+fn interrupt_on_ctrl_c() -> anyhow::Result<Arc<AtomicBool>> {
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let flag = interrupt.clone();
+    ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
         }
-        let rpc_response: RpcResponse<T> = response.json().context("parsing RPC response")?;
-        if let Some(err) = rpc_response.error {
-            anyhow::bail!("RPC error {}: {}", err.code, err.message);
-        }
-        rpc_response
-            .result
-            .ok_or_else(|| anyhow::anyhow!("RPC {method} returned no result"))
-    }
+    })?;
+    Ok(interrupt)
 }
 
-#[derive(Serialize)]
-struct RpcRequest<'a> {
-    jsonrpc: &'a str,
-    id: &'a str,
-    method: &'a str,
-    params: serde_json::Value,
-}
-
-#[derive(Deserialize)]
-struct RpcResponse<T> {
-    result: Option<T>,
-    error: Option<RpcError>,
-    #[allow(dead_code)]
-    id: serde_json::Value,
-}
-
-#[derive(Deserialize)]
-struct RpcError {
-    code: i64,
-    message: String,
-}
-
-#[derive(Deserialize)]
-struct BlockResult {
-    hash: String,
-    height: u32,
-    tx: Vec<BlockTx>,
-}
-
-impl BlockResult {
-    fn coinbase_outputs(&self) -> Vec<CoinbaseOutput> {
-        self.tx
-            .first()
-            .map(|tx| {
-                tx.vout
-                    .iter()
-                    .map(|vout| CoinbaseOutput {
-                        value_zat: vout.value_zat,
-                        addresses: vout.script_pub_key.addresses.clone().unwrap_or_default(),
-                    })
-                    .collect()
-            })
-            .unwrap_or_default()
-    }
-}
-
-#[derive(Deserialize)]
-struct BlockTx {
-    vout: Vec<BlockVout>,
-}
-
-#[derive(Deserialize)]
-struct BlockVout {
-    #[serde(rename = "valueZat")]
-    value_zat: i64,
-    #[serde(rename = "scriptPubKey")]
-    script_pub_key: ScriptPubKey,
-}
-
-#[derive(Deserialize)]
-struct ScriptPubKey {
-    addresses: Option<Vec<String>>,
-}
-
-fn compute_statistics(
-    cfg: &MinerStatsConfig,
-    cache: &BlockCache,
-    tip_height: u32,
-) -> Result<MinerStatsReport> {
-    let heights: Vec<u32> = (cfg.start_height..=tip_height).collect();
-    let total_blocks = heights.len() as u32;
-    let coinbase_totals: HashMap<u32, i64> = heights
-        .iter()
-        .filter_map(|h| {
-            cache.blocks.get(h).map(|block| {
-                let sum: i64 = block.outputs.iter().map(|o| o.value_zat).sum();
-                (*h, sum)
-            })
-        })
-        .collect();
-
-    let mut per_miner = Vec::new();
-    let mut matched_blocks = BTreeSet::new();
-    let mut block_totals: HashMap<u32, i64> = HashMap::new();
-
-    for miner in &cfg.miners {
-        let key_store = UnifiedKeyStore::new_from_ufvk(&cfg.chain, miner.key.clone())
-            .with_context(|| format!("decoding UFVK {}", shorten_key(&miner.key)))?;
-        let mut blocks = 0u32;
-        let mut total_value = 0i64;
-        let mut details = Vec::new();
-        for &height in &heights {
-            let block = match cache.blocks.get(&height) {
-                Some(block) => block,
-                None => continue,
-            };
-            let Some(index) = NonHardenedChildIndex::from_index(height) else {
-                continue;
-            };
-            let address = key_store
-                .generate_transparent_address(index, TransparentScope::External)
-                .with_context(|| format!("deriving address for height {height}"))?;
-            let encoded = transparent::encode_address(&cfg.chain, address);
-
-            let mut matched_value = 0i64;
-            for output in &block.outputs {
-                if output.addresses.iter().any(|addr| addr == &encoded) {
-                    matched_value += output.value_zat;
-                }
-            }
-            if matched_value > 0 {
-                blocks += 1;
-                total_value += matched_value;
-                matched_blocks.insert(height);
-                block_totals
-                    .entry(height)
-                    .and_modify(|v| *v = (*v).max(matched_value))
-                    .or_insert(matched_value);
-                details.push(MinerBlockDetail {
-                    block_height: height,
-                    block_hash: block.hash.clone(),
-                    payout_address: encoded.clone(),
-                });
-            }
-        }
-        per_miner.push(MinerSummary {
-            label: miner.label.clone(),
-            matched_blocks: blocks,
-            total_value_zat: total_value,
-            total_value_wec: zats_to_wec(total_value),
-            share_percent: 0.0,
-            detailed_blocks: details,
-        });
+/// Asks on the terminal whether to go ahead with `--force-refetch`, unless `--confirm` was
+/// given. Without a terminal there is nobody to ask, so the run is refused instead.
+fn confirm_refetch(cli: &Cli) -> anyhow::Result<bool> {
+    if cli.confirm {
+        return Ok(true);
     }
-
-    for miner in &mut per_miner {
-        miner.share_percent = percent_share_blocks(miner.matched_blocks, total_blocks);
+    if !std::io::stdout().is_terminal() {
+        anyhow::bail!(
+            "--force-refetch discards the whole cache; pass --confirm to run it without a terminal"
+        );
     }
-
-    let matched_value_zat: i64 = block_totals.values().sum();
-    let unmatched_blocks = total_blocks.saturating_sub(matched_blocks.len() as u32);
-    let unmatched_value_zat: i64 = coinbase_totals
-        .iter()
-        .filter(|(height, _)| !matched_blocks.contains(height))
-        .map(|(_, value)| *value)
-        .sum();
-    let unmatched_value_wec = zats_to_wec(unmatched_value_zat);
-    let unmatched_share = percent_share_blocks(unmatched_blocks, total_blocks);
-
-    let total_value_zat = matched_value_zat + unmatched_value_zat;
-    let total_value_wec = zats_to_wec(total_value_zat);
-
-    Ok(MinerStatsReport {
-        start_height: cfg.start_height,
-        end_height: tip_height,
-        total_mined_blocks: matched_blocks.len() as u32,
-        total_value_zat,
-        total_value_wec,
-        miners: per_miner
-            .iter()
-            .map(|m| MinerAggregate {
-                label: m.label.clone(),
-                matched_blocks: m.matched_blocks,
-                total_value_zat: m.total_value_zat,
-                total_value_wec: m.total_value_wec,
-                share_percent: m.share_percent,
-            })
-            .collect(),
-        detailed_miners: per_miner,
-        unmatched: UnmatchedSummary {
-            blocks: unmatched_blocks,
-            total_value_zat: unmatched_value_zat,
-            total_value_wec: unmatched_value_wec,
-            share_percent: unmatched_share,
-        },
+    let cfg = MinerStatsConfig::from_file(&cli.config)?;
+    let (cache, _) = cfg.cache_backend(false)?.load()?;
+    let blocks = cache.blocks.len();
+    print!("Re-fetch {blocks} blocks from scratch? [y/N] ");
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse().map_err(|_| {
+        format!("unknown timezone '{name}'; use an IANA name such as `America/New_York` or `UTC`")
     })
 }
 
-#[derive(Serialize)]
-struct MinerAggregate {
-    label: String,
-    matched_blocks: u32,
-    total_value_zat: i64,
-    total_value_wec: f64,
-    share_percent: f64,
-}
-
-#[derive(Serialize)]
-struct MinerSummary {
-    label: String,
-    matched_blocks: u32,
-    total_value_zat: i64,
-    total_value_wec: f64,
-    share_percent: f64,
-    detailed_blocks: Vec<MinerBlockDetail>,
-}
-
-#[derive(Serialize)]
-struct MinerBlockDetail {
-    block_height: u32,
-    block_hash: String,
-    payout_address: String,
-}
-
-#[derive(Serialize)]
-struct MinerStatsReport {
-    start_height: u32,
-    end_height: u32,
-    total_mined_blocks: u32,
-    total_value_zat: i64,
-    total_value_wec: f64,
-    miners: Vec<MinerAggregate>,
-    detailed_miners: Vec<MinerSummary>,
-    #[serde(skip_serializing)]
-    unmatched: UnmatchedSummary,
-}
-
-#[derive(Serialize)]
-struct UnmatchedSummary {
-    blocks: u32,
-    total_value_zat: i64,
-    total_value_wec: f64,
-    share_percent: f64,
-}
-
-impl MinerStatsReport {
-    fn write(&self, path: &Path) -> Result<()> {
-        let json = serde_json::to_vec_pretty(self)
-            .with_context(|| format!("serializing report {}", path.display()))?;
-        fs::write(path, json).with_context(|| format!("writing report {}", path.display()))
+/// `--force-color` wins, then `--no-color` and `NO_COLOR` (see <https://no-color.org>);
+/// otherwise color only when stdout is a terminal.
+fn use_color(cli: &Cli) -> bool {
+    if cli.force_color {
+        return true;
     }
-}
-
-fn shorten_key(key: &str) -> String {
-    if key.len() <= 16 {
-        key.to_string()
-    } else {
-        format!("{}…{}", &key[..8], &key[key.len() - 8..])
-    }
-}
-
-fn zats_to_wec(zats: i64) -> f64 {
-    let wec = (zats as f64) / 100_000_000.0;
-    (wec * 100.0).round() / 100.0
-}
-
-fn percent_share_blocks(part: u32, total: u32) -> f64 {
-    if total == 0 {
-        0.0
-    } else {
-        (((part as f64) / (total as f64)) * 100.0 * 100.0).round() / 100.0
-    }
-}
-
-fn print_table(report: &MinerStatsReport) {
-    println!(
-        "\nMiner stats for heights {}-{} (total {:.2} WEC):",
-        report.start_height, report.end_height, report.total_value_wec
-    );
-    println!("+----------------------+------------+------------+------------+");
-    println!(
-        "| {:<20} | {:>10} | {:>10} | {:>10} |",
-        "Label", "Blocks", "WEC", "% Share"
-    );
-    println!("+----------------------+------------+------------+------------+");
-    for miner in &report.miners {
-        println!(
-            "| {:<20} | {:>10} | {:>10.2} | {:>9.2}% |",
-            miner.label, miner.matched_blocks, miner.total_value_wec, miner.share_percent
-        );
-    }
-    println!("| {:_<20} | {:_<10} | {:_<10} | {:_<10} |", "", "", "", "");
-    println!(
-        "| {:<20} | {:>10} | {:>10.2} | {:>9.2}% |",
-        "Others",
-        report.unmatched.blocks,
-        report.unmatched.total_value_wec,
-        report.unmatched.share_percent
-    );
-    println!("+----------------------+------------+------------+------------+");
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !cli.no_color && !no_color_env && std::io::stdout().is_terminal()
+}
+
+/// Installs the stderr logger and, given an `otlp_endpoint`, the span exporter. Spans only
+/// go to the exporter, so log lines look the same either way.
+fn init_logging(
+    level: LogLevel,
+    format: LogFormat,
+    otlp_endpoint: Option<&str>,
+) -> anyhow::Result<Option<OtelGuard>> {
+    let (otel, guard) = match otlp_endpoint {
+        Some(endpoint) => {
+            let (layer, guard) = telemetry::otlp_layer(endpoint)?;
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+    let max_level = LevelFilter::from_level(Level::from(level));
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_target(false);
+    let fmt = match format {
+        LogFormat::Text => fmt.boxed(),
+        LogFormat::Json => fmt.json().boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(fmt.with_filter(filter_fn(move |meta| {
+            !meta.is_span() && *meta.level() <= max_level
+        })))
+        .with(otel.with_filter(filter_fn(|meta| meta.is_span())))
+        .init();
+    Ok(guard)
 }
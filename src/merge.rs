@@ -0,0 +1,221 @@
+//! `merge` subcommand: combines reports for disjoint height ranges into one report.
+
+use crate::{
+    EpochMinerStats, EpochStats, MinerAggregate, MinerStatsReport, MinerSummary, OutputFormat,
+    TimeRange, UnmatchedSummary, block_activity, block_streaks, blocks_per_day, checked_zat_sum,
+    elapsed_days,
+    error::{Error, Result},
+    percent_share_blocks, percent_share_value, total_fees, zats_to_wec,
+};
+use std::{
+    cmp::Reverse,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+pub fn run(inputs: &[PathBuf], output: &Path) -> Result<()> {
+    let reports = inputs
+        .iter()
+        .map(|path| MinerStatsReport::read(path))
+        .collect::<Result<Vec<_>>>()?;
+    let merged = merge(reports)?;
+    merged.write(output, OutputFormat::Json, false, false)?;
+    info!(
+        "Merged {} reports covering heights {}-{} into {}.",
+        inputs.len(),
+        merged.start_height,
+        merged.end_height,
+        output.display()
+    );
+    Ok(())
+}
+
+fn merge(mut reports: Vec<MinerStatsReport>) -> Result<MinerStatsReport> {
+    reports.sort_by_key(|r| r.start_height);
+    for pair in reports.windows(2) {
+        if pair[1].start_height <= pair[0].end_height {
+            return Err(Error::ReportsOverlap {
+                first: [pair[0].start_height, pair[0].end_height],
+                second: [pair[1].start_height, pair[1].end_height],
+            });
+        }
+    }
+    let (Some(first), Some(last)) = (reports.first(), reports.last()) else {
+        return Err(Error::NoReports);
+    };
+    let start_height = first.start_height;
+    let end_height = last.end_height;
+
+    let total_blocks: u32 = reports
+        .iter()
+        .map(|r| (r.end_height - r.start_height + 1) as u32 - r.excluded_heights.len() as u32)
+        .sum();
+    let total_mined_blocks: u32 = reports.iter().map(|r| r.total_mined_blocks).sum();
+    let total_value_zat = checked_zat_sum(reports.iter().map(|r| r.total_value_zat), || {
+        "total value of the merged reports".into()
+    })?;
+
+    let time_range = reports.iter().filter_map(|r| r.time_range.as_ref()).fold(
+        None,
+        |acc: Option<TimeRange>, range| {
+            Some(match acc {
+                Some(acc) => TimeRange::new(
+                    acc.start_unix.min(range.start_unix),
+                    acc.end_unix.max(range.end_unix),
+                ),
+                None => TimeRange::new(range.start_unix, range.end_unix),
+            })
+        },
+    );
+    let elapsed_days = elapsed_days(time_range.as_ref(), start_height, end_height);
+
+    let mut detailed_miners: Vec<MinerSummary> = Vec::new();
+    let mut halvings: Vec<EpochStats> = Vec::new();
+    let mut excluded_heights = Vec::new();
+    let mut warnings: Vec<String> = Vec::new();
+    let mut coverage_gaps = Vec::new();
+    let mut skipped_blocks = Vec::new();
+    // The ranges are disjoint, so the top blocks of the merged range are among those of the
+    // reports, as long as each listed as many.
+    let top_n = reports
+        .iter()
+        .map(|r| r.top_blocks.len())
+        .max()
+        .unwrap_or(0);
+    let mut top_blocks = Vec::new();
+    for report in reports {
+        excluded_heights.extend(report.excluded_heights);
+        top_blocks.extend(report.top_blocks);
+        skipped_blocks.extend(report.skipped_blocks);
+        coverage_gaps.extend(report.coverage_gaps);
+        for warning in report.warnings {
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+        for summary in report.detailed_miners {
+            match detailed_miners
+                .iter_mut()
+                .find(|m| m.label == summary.label)
+            {
+                Some(existing) => {
+                    existing.matched_blocks += summary.matched_blocks;
+                    existing.total_value_zat = checked_zat_sum(
+                        [existing.total_value_zat, summary.total_value_zat],
+                        || format!("total value of miner {:?}", existing.label),
+                    )?;
+                    // Kept as each report split them: the depth is not in the reports,
+                    // so blocks immature at an earlier report's tip stay immature here.
+                    existing.mature_value_zat += summary.mature_value_zat;
+                    existing.immature_value_zat += summary.immature_value_zat;
+                    existing.detailed_blocks.extend(summary.detailed_blocks);
+                    existing.moving_avg_share.extend(summary.moving_avg_share);
+                    existing
+                        .inactivity_periods
+                        .extend(summary.inactivity_periods);
+                    // Only complete when every report counted its blocks per weekday.
+                    existing.day_of_week_stats =
+                        match (existing.day_of_week_stats, summary.day_of_week_stats) {
+                            (Some(mut days), Some(more)) => {
+                                for (day, more) in days.iter_mut().zip(more) {
+                                    day.block_count += more.block_count;
+                                    day.value_zat += more.value_zat;
+                                }
+                                Some(days)
+                            }
+                            _ => None,
+                        };
+                }
+                None => detailed_miners.push(summary),
+            }
+        }
+        for epoch in report.halvings {
+            match halvings.iter_mut().find(|e| e.epoch == epoch.epoch) {
+                Some(existing) => {
+                    existing.start_height = existing.start_height.min(epoch.start_height);
+                    existing.end_height = existing.end_height.max(epoch.end_height);
+                    existing.total_blocks += epoch.total_blocks;
+                    for miner in epoch.miners {
+                        match existing.miners.iter_mut().find(|m| m.label == miner.label) {
+                            Some(m) => m.matched_blocks += miner.matched_blocks,
+                            None => existing.miners.push(EpochMinerStats {
+                                label: miner.label,
+                                matched_blocks: miner.matched_blocks,
+                            }),
+                        }
+                    }
+                }
+                None => halvings.push(epoch),
+            }
+        }
+    }
+    for miner in &mut detailed_miners {
+        // Histograms of different ranges have different bounds and cannot be added up.
+        miner.value_histogram = None;
+        miner.total_value_wec = zats_to_wec(miner.total_value_zat);
+        miner.share_percent = percent_share_blocks(miner.matched_blocks, total_blocks);
+    }
+
+    top_blocks.sort_by_key(|block| (Reverse(block.total_coinbase_zat), block.height));
+    top_blocks.truncate(top_n);
+
+    let unmatched_blocks = total_blocks.saturating_sub(total_mined_blocks);
+    Ok(MinerStatsReport {
+        start_height,
+        end_height,
+        total_mined_blocks,
+        total_value_zat,
+        total_value_wec: zats_to_wec(total_value_zat),
+        miners: detailed_miners
+            .iter()
+            .map(|m| {
+                let (longest_streak, current_streak) =
+                    block_streaks(&m.detailed_blocks, end_height);
+                let (first_block, last_block, inactive_since) =
+                    block_activity(&m.detailed_blocks, end_height);
+                let total_fees_zat = total_fees(m)?;
+                Ok::<_, Error>(MinerAggregate {
+                    label: m.label.clone(),
+                    matched_blocks: m.matched_blocks,
+                    total_value_zat: m.total_value_zat,
+                    total_value_wec: m.total_value_wec,
+                    share_percent: m.share_percent,
+                    blocks_per_day: blocks_per_day(m.matched_blocks, elapsed_days),
+                    longest_streak,
+                    current_streak,
+                    total_fees_zat,
+                    total_fees_wec: zats_to_wec(total_fees_zat),
+                    value_share_percent: percent_share_value(m.total_value_zat, total_value_zat),
+                    first_block,
+                    last_block,
+                    inactive_since,
+                    mature_value_zat: m.mature_value_zat,
+                    immature_value_zat: m.immature_value_zat,
+                    total_value_fiat: None,
+                    fiat_currency: None,
+                })
+            })
+            .collect::<Result<_>>()?,
+        detailed_miners,
+        unmatched: UnmatchedSummary {
+            blocks: unmatched_blocks,
+            blocks_per_day: blocks_per_day(unmatched_blocks, elapsed_days),
+            share_percent: percent_share_blocks(unmatched_blocks, total_blocks),
+            ..UnmatchedSummary::default()
+        },
+        window: None,
+        time_range,
+        halvings,
+        excluded_heights,
+        // Each report's z-scores are relative to its own range, and the unmatched blocks'
+        // values needed to recompute them are not in the reports.
+        outlier_blocks: Vec::new(),
+        top_blocks,
+        skipped_blocks,
+        // The reports' rates were fetched at different times.
+        fiat_rate: None,
+        value_histogram: None,
+        warnings,
+        coverage_gaps,
+    })
+}
@@ -0,0 +1,180 @@
+//! Prometheus text-format metrics served over HTTP from a background thread, optionally
+//! also pushed to a StatsD daemon as they change.
+
+use crate::error::{Error, Result};
+use cadence::{MetricResult, StatsdClient, UdpMetricSink, prelude::*};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::UdpSocket,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+use tracing::{debug, info};
+
+/// Gauges and counters updated by the main pipeline and rendered on every scrape.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Label -> (matched blocks, matched value in zats).
+    miners: Mutex<BTreeMap<String, (u32, i64)>>,
+    cache_blocks: AtomicU64,
+    last_tip: AtomicU64,
+    rpc_errors: AtomicU64,
+    blocks_fetched: AtomicU64,
+    /// Set by [`Metrics::with_statsd`]; every update below is also sent there.
+    statsd: Option<StatsdClient>,
+}
+
+impl Metrics {
+    /// Metrics that are also sent as StatsD datagrams to `addr` (`host:port`), every name
+    /// prefixed with `prefix`.
+    pub fn with_statsd(addr: &str, prefix: &str) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")
+            .and_then(|socket| socket.set_nonblocking(true).map(|()| socket))
+            .map_err(|e| Error::config("statsd_addr", format!("opening a UDP socket: {e}")))?;
+        let sink = UdpMetricSink::from(addr, socket)
+            .map_err(|e| Error::config("statsd_addr", format!("{addr}: {e}")))?;
+        info!(addr, prefix, "sending StatsD metrics");
+        Ok(Self {
+            statsd: Some(StatsdClient::from_sink(prefix, sink)),
+            ..Self::default()
+        })
+    }
+
+    /// Records a miner's totals. StatsD adds up counter increments, so `miner_blocks` is sent
+    /// only as the change since the previous call for `label`, which re-orgs can make
+    /// negative.
+    pub(crate) fn set_miner(&self, label: &str, matched_blocks: u32, value_zat: i64) {
+        let previous = self
+            .miners
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(label.to_string(), (matched_blocks, value_zat))
+            .map_or(0, |(blocks, _)| blocks);
+        let increase = i64::from(matched_blocks) - i64::from(previous);
+        if increase == 0 {
+            return;
+        }
+        self.send_statsd(|statsd| {
+            statsd
+                .count_with_tags("miner_blocks", increase)
+                .with_tag("label", label)
+                .try_send()
+        });
+    }
+
+    pub(crate) fn add_blocks_fetched(&self, blocks: usize) {
+        let total = self
+            .blocks_fetched
+            .fetch_add(blocks as u64, Ordering::Relaxed)
+            + blocks as u64;
+        self.send_statsd(|statsd| statsd.gauge("blocks_fetched", total));
+    }
+
+    pub(crate) fn record_rpc_duration(&self, duration: Duration) {
+        let millis = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        self.send_statsd(|statsd| statsd.time("rpc_duration_ms", millis));
+    }
+
+    /// A lost datagram only costs a data point, so send failures are logged and dropped.
+    fn send_statsd<T>(&self, send: impl FnOnce(&StatsdClient) -> MetricResult<T>) {
+        if let Some(statsd) = &self.statsd
+            && let Err(err) = send(statsd)
+        {
+            debug!("failed to send StatsD metric: {err}");
+        }
+    }
+
+    pub(crate) fn set_cache_blocks(&self, blocks: usize) {
+        self.cache_blocks.store(blocks as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_last_tip(&self, height: u64) {
+        self.last_tip.store(height, Ordering::Relaxed);
+    }
+
+    pub(crate) fn inc_rpc_errors(&self) {
+        self.rpc_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP miner_matched_blocks_total Blocks matched to each miner.\n");
+        out.push_str("# TYPE miner_matched_blocks_total gauge\n");
+        let miners = self.miners.lock().map(|m| m.clone()).unwrap_or_default();
+        for (label, (blocks, _)) in &miners {
+            let _ = writeln!(
+                out,
+                "miner_matched_blocks_total{{label=\"{}\"}} {blocks}",
+                escape_label(label)
+            );
+        }
+        out.push_str("# HELP miner_value_zat_total Coinbase value credited to each miner.\n");
+        out.push_str("# TYPE miner_value_zat_total gauge\n");
+        for (label, (_, value)) in &miners {
+            let _ = writeln!(
+                out,
+                "miner_value_zat_total{{label=\"{}\"}} {value}",
+                escape_label(label)
+            );
+        }
+        let _ = write!(
+            out,
+            "# HELP cache_blocks_cached Blocks currently held in the block cache.\n\
+             # TYPE cache_blocks_cached gauge\n\
+             cache_blocks_cached {}\n\
+             # HELP last_tip_height Chain tip height seen on the last poll.\n\
+             # TYPE last_tip_height gauge\n\
+             last_tip_height {}\n\
+             # HELP rpc_errors_total Failed RPC attempts, including retried ones.\n\
+             # TYPE rpc_errors_total counter\n\
+             rpc_errors_total {}\n\
+             # HELP blocks_fetched_total Blocks fetched from the node during this run.\n\
+             # TYPE blocks_fetched_total counter\n\
+             blocks_fetched_total {}\n",
+            self.cache_blocks.load(Ordering::Relaxed),
+            self.last_tip.load(Ordering::Relaxed),
+            self.rpc_errors.load(Ordering::Relaxed),
+            self.blocks_fetched.load(Ordering::Relaxed),
+        );
+        out
+    }
+}
+
+/// Binds `addr` and answers `GET /metrics` from a detached thread for the rest of the process.
+pub(crate) fn serve(addr: &str, metrics: Arc<Metrics>) -> Result<()> {
+    let server = tiny_http::Server::http(addr).map_err(|source| Error::MetricsBind {
+        addr: addr.to_string(),
+        source,
+    })?;
+    info!(addr, "serving Prometheus metrics on /metrics");
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                let mut response = tiny_http::Response::from_string(metrics.render());
+                if let Ok(header) = "Content-Type: text/plain; version=0.0.4".parse() {
+                    response = response.with_header::<tiny_http::Header>(header);
+                }
+                response
+            } else {
+                tiny_http::Response::from_string("not found")
+                    .with_status_code(tiny_http::StatusCode(404))
+            };
+            if let Err(err) = request.respond(response) {
+                debug!("failed to answer metrics request: {err}");
+            }
+        }
+    });
+    Ok(())
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,274 @@
+//! `cache_shard_size`: the `json` backend's cache split into one file per run of heights,
+//! e.g. `cache-0000000-0099999.json`, so a run only reads the shards from `start_height` on.
+//! The `cache-shard` subcommand converts an existing single-file cache.
+
+use crate::{
+    BlockCache, CacheBackend, CacheFormat, CachedBlock, MinerStatsConfig,
+    error::{Error, Result},
+    warn_readonly_save, write_cache_file,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+use tracing::{debug, info};
+
+/// One shard file: the fields of a [`BlockCache`], holding only the shard's blocks.
+#[derive(Serialize)]
+struct Shard<'a> {
+    version: u32,
+    last_tip: Option<u64>,
+    blocks: BTreeMap<u64, &'a CachedBlock>,
+}
+
+/// Stores the blocks of heights `n * shard_size..(n + 1) * shard_size` in one file next to
+/// `cache_file`, named after its stem and extension. Each shard is a complete cache file
+/// (plain or compressed, JSON or MessagePack) that [`BlockCache::load`] could read alone.
+#[derive(Debug)]
+pub struct ShardedFileCache {
+    path: PathBuf,
+    shard_size: u64,
+    start_height: u64,
+    compressed: bool,
+    format: CacheFormat,
+    readonly: bool,
+    /// Fingerprint of the blocks of each shard as last loaded or saved, keyed by the shard's
+    /// first height; a save skips shards whose blocks did not change. `None` for shards
+    /// migrated from an older schema on load, which the next save rewrites.
+    written: Mutex<HashMap<u64, Option<u64>>>,
+}
+
+impl ShardedFileCache {
+    /// Shards ending below `start_height` are never loaded, so a run neither reads nor
+    /// rewrites them.
+    pub fn new(
+        path: PathBuf,
+        shard_size: u64,
+        start_height: u64,
+        compressed: bool,
+        format: CacheFormat,
+        readonly: bool,
+    ) -> Self {
+        Self {
+            path,
+            shard_size,
+            start_height,
+            compressed,
+            format,
+            readonly,
+            written: Mutex::default(),
+        }
+    }
+
+    /// `{stem}-` and `.{extension}` of `cache_file`, between which shard names put their
+    /// height range.
+    fn name_parts(&self) -> (String, String) {
+        let stem = self
+            .path
+            .file_stem()
+            .map_or_else(|| "cache".into(), |stem| stem.to_string_lossy());
+        let extension = self
+            .path
+            .extension()
+            .map_or_else(String::new, |ext| format!(".{}", ext.to_string_lossy()));
+        (format!("{stem}-"), extension)
+    }
+
+    fn shard_path(&self, first: u64) -> PathBuf {
+        let (prefix, extension) = self.name_parts();
+        let last = first + self.shard_size - 1;
+        self.path
+            .with_file_name(format!("{prefix}{first:07}-{last:07}{extension}"))
+    }
+
+    /// The shard files present, as `(first height, last height, path)`, lowest first.
+    fn shard_files(&self) -> Result<Vec<(u64, u64, PathBuf)>> {
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(Error::io("listing cache shards in", dir)(err)),
+        };
+        let (prefix, extension) = self.name_parts();
+        let mut shards = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(Error::io("listing cache shards in", dir))?;
+            let name = entry.file_name();
+            let range = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|name| name.strip_suffix(&extension))
+                .and_then(|range| range.split_once('-'));
+            if let Some((Ok(first), Ok(last))) =
+                range.map(|(first, last)| (first.parse::<u64>(), last.parse::<u64>()))
+            {
+                shards.push((first, last, entry.path()));
+            }
+        }
+        shards.sort();
+        Ok(shards)
+    }
+
+    fn written(&self) -> MutexGuard<'_, HashMap<u64, Option<u64>>> {
+        self.written.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CacheBackend for ShardedFileCache {
+    /// Reads the shards that end at or above `start_height`. The tip is not known before
+    /// syncing, so every shard above it is read as well; it only holds blocks the node
+    /// dropped, which syncing re-checks like any other cached block.
+    fn load(&self) -> Result<(BlockCache, bool)> {
+        let mut cache = BlockCache::default();
+        let mut any_migrated = false;
+        let mut written = self.written();
+        written.clear();
+        for (first, last, path) in self.shard_files()? {
+            if last < self.start_height {
+                continue;
+            }
+            let (shard, migrated) = BlockCache::load(&path)?;
+            debug!(
+                first,
+                last,
+                blocks = shard.blocks.len(),
+                "loaded cache shard"
+            );
+            written.insert(first, (!migrated).then(|| fingerprint(shard.blocks.iter())));
+            any_migrated |= migrated;
+            cache.last_tip = cache.last_tip.max(shard.last_tip);
+            cache.blocks.extend(shard.blocks);
+        }
+        Ok((cache, any_migrated))
+    }
+
+    /// Writes each shard whose blocks changed since it was loaded or last saved, and always
+    /// the highest one, which carries the current `last_tip`. Shards of loaded heights that
+    /// no longer hold any block are removed.
+    fn save(&self, cache: &BlockCache) -> Result<()> {
+        if self.readonly {
+            warn_readonly_save(&self.path);
+            return Ok(());
+        }
+        let mut shards: BTreeMap<u64, BTreeMap<u64, &CachedBlock>> = BTreeMap::new();
+        for (height, block) in &cache.blocks {
+            let first = height / self.shard_size * self.shard_size;
+            shards.entry(first).or_default().insert(*height, block);
+        }
+        let highest = shards.keys().next_back().copied();
+        let mut written = self.written();
+        for (first, blocks) in shards {
+            let print = fingerprint(blocks.iter().map(|(height, block)| (height, *block)));
+            if written.get(&first) == Some(&Some(print)) && Some(first) != highest {
+                continue;
+            }
+            let path = self.shard_path(first);
+            let shard = Shard {
+                version: cache.version,
+                last_tip: cache.last_tip,
+                blocks,
+            };
+            write_cache_file(&shard, &path, self.format, self.compressed)?;
+            written.insert(first, Some(print));
+        }
+        let emptied: Vec<u64> = written
+            .keys()
+            .filter(|first| {
+                let end = **first + self.shard_size;
+                cache.blocks.range(**first..end).next().is_none()
+            })
+            .copied()
+            .collect();
+        for first in emptied {
+            let path = self.shard_path(first);
+            match fs::remove_file(&path) {
+                Ok(()) => debug!(first, "removed empty cache shard"),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(Error::io("removing cache shard", &path)(err)),
+            }
+            written.remove(&first);
+        }
+        Ok(())
+    }
+}
+
+/// Digest of the stored fields of `blocks`, to tell whether a shard needs rewriting.
+fn fingerprint<'a>(blocks: impl Iterator<Item = (&'a u64, &'a CachedBlock)>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for (height, block) in blocks {
+        height.hash(&mut hasher);
+        block.hash.hash(&mut hasher);
+        block.time.hash(&mut hasher);
+        for output in &block.outputs {
+            output.value_zat.hash(&mut hasher);
+            output.addresses.hash(&mut hasher);
+            output.hex.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// `cache-shard` subcommand: copies the single-file cache at `cache_file` into shards of
+/// `cache_shard_size` heights, reads them back and checks that every block survived. The
+/// original file is left in place; the sharded backend ignores it.
+pub fn run(config_path: &Path) -> Result<()> {
+    let cfg = MinerStatsConfig::from_file(config_path)?;
+    let Some(shard_size) = cfg.cache_shard_size else {
+        return Err(Error::config(
+            "cache_shard_size",
+            "must be set to the number of heights per shard to convert the cache",
+        ));
+    };
+    if cfg.cache_readonly {
+        return Err(Error::config(
+            "cache_readonly",
+            "cache-shard writes the shards; unset cache_readonly to run it",
+        ));
+    }
+    if !cfg.cache_file.exists() {
+        info!(
+            "No single-file cache at {}; nothing to convert.",
+            cfg.cache_file.display()
+        );
+        return Ok(());
+    }
+    let (cache, _) = BlockCache::load(&cfg.cache_file)?;
+    // Every shard, not only those from `start_height` on: nothing may be dropped.
+    let sharded = ShardedFileCache::new(
+        cfg.cache_file.clone(),
+        shard_size,
+        0,
+        cfg.compressed,
+        cfg.cache_format,
+        false,
+    );
+    sharded.save(&cache)?;
+    let (reloaded, _) = sharded.load()?;
+    let lost = cache.blocks.iter().find(|(height, block)| {
+        reloaded
+            .blocks
+            .get(height)
+            .is_none_or(|copy| copy.hash != block.hash || copy.outputs.len() != block.outputs.len())
+    });
+    if let Some((&height, _)) = lost {
+        return Err(Error::CacheShardMismatch {
+            path: cfg.cache_file.clone(),
+            height,
+        });
+    }
+    info!(
+        "Wrote {} blocks of {} into shards of {shard_size} heights; it can be removed once \
+         the sharded cache is in use.",
+        cache.blocks.len(),
+        cfg.cache_file.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! Shielded receivers of a UFVK, matched against coinbase outputs alongside the
+//! height-derived transparent addresses (`sapling` and `orchard` features).
+
+use crate::{ChainType, ReceiverType, Result};
+#[cfg(any(feature = "sapling", feature = "orchard"))]
+use crate::{Error, shorten_key};
+#[cfg(any(feature = "sapling", feature = "orchard"))]
+use zcash_keys::{address::Address, keys::UnifiedFullViewingKey};
+
+/// Encoded shielded receivers of `key` on `chain` that the enabled features match: the
+/// default Sapling payment address, and the external Orchard address at index 0 as a
+/// unified address holding only that receiver. Neither depends on the block height. Empty
+/// when both features are off.
+#[cfg_attr(
+    not(any(feature = "sapling", feature = "orchard")),
+    allow(unused_variables, unused_mut)
+)]
+pub(crate) fn receivers(chain: &ChainType, key: &str) -> Result<Vec<(ReceiverType, String)>> {
+    let mut receivers = Vec::new();
+    #[cfg(any(feature = "sapling", feature = "orchard"))]
+    {
+        let ufvk = UnifiedFullViewingKey::decode(chain, key).map_err(|e| Error::KeyDecode {
+            key: shorten_key(key),
+            source: e.into(),
+        })?;
+        #[cfg(feature = "sapling")]
+        if let Some(dfvk) = ufvk.sapling() {
+            let address = Address::Sapling(dfvk.default_address().1);
+            receivers.push((ReceiverType::Sapling, address.encode(chain)));
+        }
+        #[cfg(feature = "orchard")]
+        if let Some(fvk) = ufvk.orchard() {
+            let receiver = fvk.address_at(0u32, orchard::keys::Scope::External);
+            if let Some(unified) =
+                zcash_keys::address::UnifiedAddress::from_receivers(Some(receiver), None, None)
+            {
+                receivers.push((
+                    ReceiverType::Orchard,
+                    Address::Unified(unified).encode(chain),
+                ));
+            }
+        }
+    }
+    Ok(receivers)
+}
@@ -0,0 +1,163 @@
+//! SQLite [`CacheBackend`](crate::CacheBackend): one row per cached block.
+
+use crate::{
+    BlockCache, CACHE_VERSION, CacheBackend, CachedBlock, Error, Result, create_parent_dir,
+    warn_readonly_save,
+};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use std::path::PathBuf;
+
+const SCHEMA: &str = "\
+CREATE TABLE IF NOT EXISTS blocks (
+    height INTEGER PRIMARY KEY,
+    hash TEXT NOT NULL,
+    time INTEGER NOT NULL,
+    outputs TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value INTEGER NOT NULL
+);";
+
+/// Stores each block as a row of `blocks` (coinbase outputs as JSON), and the last tip and
+/// schema version in `meta`. A connection is opened per call, so the backend is `Sync`
+/// without locking.
+#[derive(Debug, Clone)]
+pub struct SqliteCache {
+    path: PathBuf,
+    readonly: bool,
+}
+
+impl SqliteCache {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            readonly: false,
+        }
+    }
+
+    /// `cache_readonly`: opens the database read-only and never creates or saves it.
+    pub fn read_only(path: PathBuf) -> Self {
+        Self {
+            path,
+            readonly: true,
+        }
+    }
+
+    fn open(&self) -> Result<Connection> {
+        if self.readonly {
+            return Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .map_err(|e| self.error(e));
+        }
+        create_parent_dir(&self.path, "creating cache file directory")?;
+        let conn = Connection::open(&self.path).map_err(|e| self.error(e))?;
+        conn.execute_batch(SCHEMA).map_err(|e| self.error(e))?;
+        Ok(conn)
+    }
+
+    fn error(&self, source: rusqlite::Error) -> Error {
+        Error::Sqlite {
+            path: self.path.clone(),
+            source,
+        }
+    }
+}
+
+impl CacheBackend for SqliteCache {
+    fn load(&self) -> Result<(BlockCache, bool)> {
+        if self.readonly && !self.path.exists() {
+            return Ok((BlockCache::default(), false));
+        }
+        let conn = self.open()?;
+        let meta = |key: &str| {
+            conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+                row.get::<_, u64>(0)
+            })
+            .optional()
+            .map_err(|e| self.error(e))
+        };
+        let last_tip = meta("last_tip")?;
+        // Databases from before the version row existed always recorded a tip; a database
+        // without either is new.
+        let default_version = if last_tip.is_some() { 0 } else { CACHE_VERSION };
+        let version = meta("version")?.map_or(default_version, |v| v as u32);
+        let mut stmt = conn
+            .prepare("SELECT height, hash, time, outputs FROM blocks")
+            .map_err(|e| self.error(e))?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u32>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })
+            .map_err(|e| self.error(e))?;
+        let mut cache = BlockCache {
+            version,
+            last_tip,
+            ..BlockCache::default()
+        };
+        for row in rows {
+            let (height, hash, time, outputs) = row.map_err(|e| self.error(e))?;
+            let outputs = serde_json::from_str(&outputs).map_err(|source| Error::CacheCorrupt {
+                path: self.path.clone(),
+                source,
+            })?;
+            cache.blocks.insert(
+                height,
+                CachedBlock {
+                    height,
+                    hash,
+                    time,
+                    outputs,
+                    subsidy_zat: None,
+                },
+            );
+        }
+        drop(stmt);
+        let migrated = cache.migrate(&self.path)?;
+        Ok((cache, migrated))
+    }
+
+    fn save(&self, cache: &BlockCache) -> Result<()> {
+        if self.readonly {
+            warn_readonly_save(&self.path);
+            return Ok(());
+        }
+        let mut conn = self.open()?;
+        let tx = conn.transaction().map_err(|e| self.error(e))?;
+        tx.execute("DELETE FROM blocks", [])
+            .map_err(|e| self.error(e))?;
+        {
+            let mut insert = tx
+                .prepare("INSERT INTO blocks (height, hash, time, outputs) VALUES (?1, ?2, ?3, ?4)")
+                .map_err(|e| self.error(e))?;
+            for block in cache.blocks.values() {
+                let outputs =
+                    serde_json::to_string(&block.outputs).map_err(|source| Error::Serialize {
+                        what: format!("cache {}", self.path.display()),
+                        source,
+                    })?;
+                insert
+                    .execute(params![block.height, block.hash, block.time, outputs])
+                    .map_err(|e| self.error(e))?;
+            }
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('version', ?1)",
+            params![cache.version],
+        )
+        .map_err(|e| self.error(e))?;
+        match cache.last_tip {
+            Some(tip) => tx.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('last_tip', ?1)",
+                params![tip],
+            ),
+            None => tx.execute("DELETE FROM meta WHERE key = 'last_tip'", []),
+        }
+        .map_err(|e| self.error(e))?;
+        tx.commit().map_err(|e| self.error(e))
+    }
+}
@@ -0,0 +1,86 @@
+//! `Type=notify` support (`systemd = true`): readiness, watchdog and status messages sent to
+//! the datagram socket systemd names in `NOTIFY_SOCKET`. Written by hand rather than pulling
+//! in a crate, as the protocol is one datagram per message.
+
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+/// Set once by [`enable`]; until then every function here does nothing.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LAST_WATCHDOG: Mutex<Option<Instant>> = Mutex::new(None);
+
+pub(crate) fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Sends `state` (newline-separated `KEY=value` assignments, e.g. `READY=1`) to systemd.
+/// Failures are logged at debug level only: the service keeps running either way.
+pub(crate) fn notify(state: &str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        debug!("systemd = true but NOTIFY_SOCKET is not set; not notifying");
+        return;
+    };
+    if let Err(err) = send(&path, state) {
+        debug!("failed to notify systemd: {err}");
+    }
+}
+
+/// Sends `WATCHDOG=1` if half of the `WATCHDOG_USEC` interval passed since the last one, so
+/// it can be called from any loop that makes progress.
+pub(crate) fn watchdog() {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(interval) = std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse().ok())
+        .map(|usec: u64| Duration::from_micros(usec) / 2)
+    else {
+        return;
+    };
+    let Ok(mut last) = LAST_WATCHDOG.lock() else {
+        return;
+    };
+    if last.is_some_and(|last| last.elapsed() < interval) {
+        return;
+    }
+    *last = Some(Instant::now());
+    notify("WATCHDOG=1");
+}
+
+#[cfg(unix)]
+fn send(path: &std::ffi::OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::{ffi::OsStrExt, net::UnixDatagram};
+
+    let socket = UnixDatagram::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        // A leading `@` names a socket in the Linux abstract namespace.
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+            let addr = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_path: &std::ffi::OsStr, _state: &str) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "systemd notifications need a Unix socket",
+    ))
+}
@@ -0,0 +1,77 @@
+//! OpenTelemetry export of the pipeline's tracing spans to `otlp_endpoint` (`otel` feature).
+//!
+//! The endpoint is read on its own, ahead of the rest of the config, because the tracing
+//! subscriber has to be installed before the config is loaded and validated.
+
+use crate::error::{Error, Result};
+use serde::Deserialize;
+use std::{fs, path::Path};
+use tracing::Subscriber;
+use tracing_subscriber::{Layer, registry::LookupSpan};
+
+/// Flushes the spans still queued for export when dropped; keep it alive until exit.
+pub struct OtelGuard {
+    #[cfg(feature = "otel")]
+    provider: opentelemetry_sdk::trace::SdkTracerProvider,
+}
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Err(err) = self.provider.shutdown() {
+            eprintln!("failed to flush OpenTelemetry spans: {err}");
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OtelSettings {
+    otlp_endpoint: Option<String>,
+}
+
+/// `otlp_endpoint` from the config file at `path`. Any problem reading the file yields
+/// `None`; loading the config proper reports it afterwards.
+pub fn otlp_endpoint(path: &Path) -> Option<String> {
+    let raw = fs::read_to_string(path).ok()?;
+    toml::from_str::<OtelSettings>(&raw).ok()?.otlp_endpoint
+}
+
+/// A layer exporting every span it receives to the OTLP/HTTP traces URL `endpoint` (e.g.
+/// `http://localhost:4318/v1/traces`) from a background thread.
+#[cfg(feature = "otel")]
+pub fn otlp_layer<S>(endpoint: &str) -> Result<(impl Layer<S>, OtelGuard)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| Error::config("otlp_endpoint", format!("{endpoint}: {e}")))?;
+    let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            opentelemetry_sdk::Resource::builder()
+                .with_service_name(env!("CARGO_PKG_NAME"))
+                .build(),
+        )
+        .build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    let layer = tracing_opentelemetry::layer().with_tracer(tracer);
+    Ok((layer, OtelGuard { provider }))
+}
+
+/// Without the `otel` feature there is nothing to export to.
+#[cfg(not(feature = "otel"))]
+pub fn otlp_layer<S>(_endpoint: &str) -> Result<(impl Layer<S>, OtelGuard)>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    Err::<(tracing_subscriber::layer::Identity, _), _>(Error::config(
+        "otlp_endpoint",
+        "exporting traces requires building with the `otel` feature",
+    ))
+}
@@ -0,0 +1,205 @@
+//! `--tui` (`tui` feature): an interactive view of the finished report in place of the
+//! static table. The miners table re-sorts with ←/→, the selected miner's matched blocks
+//! are listed below it, and `q` quits.
+
+use crate::{
+    MinerBlockDetail, MinerSortOrder, MinerStatsReport,
+    error::{Error, Result},
+    format_value, shorten_key,
+};
+use clap::ValueEnum;
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    widgets::{Block, Paragraph, Row, Table, TableState},
+};
+
+/// Takes over the terminal until the user quits. `report` is expected in `sort_by` order;
+/// sorting in the viewer reorders it.
+pub(crate) fn show(
+    report: &mut MinerStatsReport,
+    sort_by: MinerSortOrder,
+    human_values: bool,
+) -> Result<()> {
+    let mut terminal = ratatui::try_init().map_err(Error::Terminal)?;
+    let result = Viewer::new(report, sort_by, human_values).run(&mut terminal);
+    ratatui::restore();
+    result.map_err(Error::Terminal)
+}
+
+struct Viewer<'a> {
+    report: &'a mut MinerStatsReport,
+    human_values: bool,
+    /// Index of the current order in [`MinerSortOrder::value_variants`].
+    sort_by: usize,
+    miners: TableState,
+    /// Matched blocks of the selected miner scrolled past in the detail pane.
+    detail_offset: usize,
+}
+
+impl<'a> Viewer<'a> {
+    fn new(report: &'a mut MinerStatsReport, sort_by: MinerSortOrder, human_values: bool) -> Self {
+        let selected = (!report.miners.is_empty()).then_some(0);
+        Self {
+            report,
+            human_values,
+            sort_by: MinerSortOrder::value_variants()
+                .iter()
+                .position(|order| *order == sort_by)
+                .unwrap_or_default(),
+            miners: TableState::default().with_selected(selected),
+            detail_offset: 0,
+        }
+    }
+
+    fn run(mut self, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Down => self.select(1),
+                KeyCode::Up => self.select(-1),
+                KeyCode::Right => self.sort(1),
+                KeyCode::Left => self.sort(-1),
+                KeyCode::PageDown => self.detail_offset += 10,
+                KeyCode::PageUp => self.detail_offset = self.detail_offset.saturating_sub(10),
+                _ => {}
+            }
+        }
+    }
+
+    fn select(&mut self, step: isize) {
+        let Some(last) = self.report.miners.len().checked_sub(1) else {
+            return;
+        };
+        let current = self.miners.selected().unwrap_or(0);
+        self.miners
+            .select(Some(current.saturating_add_signed(step).min(last)));
+        self.detail_offset = 0;
+    }
+
+    /// Moves to the next (`step` 1) or previous (`step` -1) sort order, keeping the selected
+    /// miner selected.
+    fn sort(&mut self, step: isize) {
+        let orders = MinerSortOrder::value_variants();
+        self.sort_by = (self.sort_by as isize + step).rem_euclid(orders.len() as isize) as usize;
+        let selected = self.selected_label().map(str::to_owned);
+        self.report.sort_miners(orders[self.sort_by]);
+        let index = selected.and_then(|label| {
+            self.report
+                .miners
+                .iter()
+                .position(|miner| miner.label == label)
+        });
+        self.miners.select(index.or(self.miners.selected()));
+    }
+
+    fn selected_label(&self) -> Option<&str> {
+        let index = self.miners.selected()?;
+        Some(self.report.miners.get(index)?.label.as_str())
+    }
+
+    fn selected_blocks(&self) -> &[MinerBlockDetail] {
+        let Some(label) = self.selected_label() else {
+            return &[];
+        };
+        self.report
+            .detailed_miners
+            .iter()
+            .find(|miner| miner.label == label)
+            .map_or(&[], |miner| miner.detailed_blocks.as_slice())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [miners_area, blocks_area, status_area] = Layout::vertical([
+            Constraint::Percentage(45),
+            Constraint::Min(4),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let header = Row::new(["Label", "Blocks", "Value", "% Share", "% Value"]).bold();
+        let rows: Vec<Row> = self
+            .report
+            .miners
+            .iter()
+            .map(|miner| {
+                Row::new([
+                    miner.label.clone(),
+                    miner.matched_blocks.to_string(),
+                    format_value(miner.total_value_zat, self.human_values),
+                    format!("{:.2}%", miner.share_percent),
+                    format!("{:.2}%", miner.value_share_percent),
+                ])
+            })
+            .collect();
+        let miners = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(10),
+                Constraint::Length(15),
+                Constraint::Length(9),
+                Constraint::Length(9),
+            ],
+        )
+        .header(header)
+        .block(Block::bordered().title(" Miners "))
+        .row_highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(miners, miners_area, &mut self.miners);
+
+        let blocks = self.selected_blocks();
+        let offset = self.detail_offset.min(blocks.len().saturating_sub(1));
+        let rows: Vec<Row> = blocks
+            .iter()
+            .skip(offset)
+            .map(|block| {
+                Row::new([
+                    block.block_height.to_string(),
+                    block.block_datetime.clone(),
+                    shorten_key(&block.block_hash),
+                    block.payout_address.clone(),
+                    format_value(block.value_zat, self.human_values),
+                ])
+            })
+            .collect();
+        let title = format!(
+            " Blocks of {} ({}) ",
+            self.selected_label().unwrap_or("-"),
+            blocks.len()
+        );
+        let detail = Table::new(
+            rows,
+            [
+                Constraint::Length(10),
+                Constraint::Length(25),
+                Constraint::Length(17),
+                Constraint::Fill(1),
+                Constraint::Length(15),
+            ],
+        )
+        .header(Row::new(["Height", "Time", "Hash", "Address", "Value"]).bold())
+        .block(Block::bordered().title(title));
+        frame.render_widget(detail, blocks_area);
+        self.detail_offset = offset;
+
+        let order = MinerSortOrder::value_variants()[self.sort_by]
+            .to_possible_value()
+            .map_or_else(String::new, |value| value.get_name().to_owned());
+        let status = format!(
+            " Heights {}-{} | {} total | ↑/↓ miner  ←/→ sort ({order})  PgUp/PgDn blocks  q quit",
+            self.report.start_height,
+            self.report.end_height,
+            format_value(self.report.total_value_zat, true),
+        );
+        frame.render_widget(Paragraph::new(status).reversed(), status_area);
+    }
+}
@@ -0,0 +1,79 @@
+//! `validate-config` subcommand: checks a config file offline before a long sync.
+
+use crate::{ChainType, MinerStatsConfig, error::Result, gz_path, shorten_key};
+use pepper_sync::keys::transparent::{self, TransparentScope};
+use std::{fs, path::Path};
+use zcash_primitives::legacy::keys::NonHardenedChildIndex;
+use zingolib::wallet::keys::unified::UnifiedKeyStore;
+
+/// Loads the config, decodes every UFVK and checks that the cache and output directories
+/// are writable, printing one line per check, then the file the report would be written to.
+/// `compress` is `Some` when `--compress-output` or `--decompress-output` overrides the
+/// config's `compress_output`. Returns whether every check passed; details of failed checks
+/// go to stderr. Never contacts the node.
+pub fn run(config_path: &Path, compress: Option<bool>) -> Result<bool> {
+    let cfg = MinerStatsConfig::from_file(config_path)?;
+    let mut valid = true;
+    for miner in &cfg.miners {
+        for key in &miner.keys {
+            match derive_first_address(&cfg.chain, key) {
+                Ok(address) => println!(
+                    "ok       {} ({}): height 0 pays {address}",
+                    miner.label,
+                    shorten_key(key)
+                ),
+                Err(reason) => {
+                    valid = false;
+                    println!("invalid  {} ({})", miner.label, shorten_key(key));
+                    eprintln!("{}: {reason}", miner.label);
+                }
+            }
+        }
+    }
+    for (field, file) in [
+        ("cache_file", &cfg.cache_file),
+        ("output_file", &cfg.output_file),
+    ] {
+        let dir = match file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        match check_writable(dir) {
+            Ok(()) => println!("ok       {field}: {} is writable", dir.display()),
+            Err(err) => {
+                valid = false;
+                println!("invalid  {field}: {} is not writable", dir.display());
+                eprintln!("{field}: {}: {err}", dir.display());
+            }
+        }
+    }
+    let report_path = if compress.unwrap_or(cfg.compress_output) {
+        gz_path(&cfg.output_file)
+    } else {
+        cfg.output_file.clone()
+    };
+    println!("report   {}", report_path.display());
+    Ok(valid)
+}
+
+/// Encoded external transparent address of `key` at height 0.
+pub(crate) fn derive_first_address(
+    chain: &ChainType,
+    key: &str,
+) -> std::result::Result<String, String> {
+    let key_store =
+        UnifiedKeyStore::new_from_ufvk(chain, key.to_string()).map_err(|e| e.to_string())?;
+    let index = NonHardenedChildIndex::from_index(0).ok_or("child index 0 is not valid")?;
+    let address = key_store
+        .generate_transparent_address(index, TransparentScope::External)
+        .map_err(|e| e.to_string())?;
+    Ok(transparent::encode_address(chain, address))
+}
+
+/// Creates and removes a probe file, which also catches read-only mounts that permission
+/// bits alone would not reveal.
+fn check_writable(dir: &Path) -> std::io::Result<()> {
+    let probe = dir.join(format!(".uview-miner-stats-probe-{}", std::process::id()));
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)
+}
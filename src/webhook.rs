@@ -0,0 +1,145 @@
+//! `webhook_url`: with `--watch`, POSTs each block newly credited to a configured miner as
+//! JSON, signed with `webhook_secret` when one is set.
+
+use crate::{
+    MinerBlockDetail, MinerStatsReport,
+    error::{Error, Result},
+};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::{collections::HashMap, time::Duration};
+use tracing::{debug, warn};
+
+/// Further attempts after a failed POST, each after [`RETRY_DELAY`].
+const RETRIES: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Header carrying `sha256=<hex HMAC of the body>`, in the format GitHub webhooks use.
+const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// Body of one webhook request.
+#[derive(Serialize)]
+struct BlockNotification<'a> {
+    miner_label: &'a str,
+    block_height: u64,
+    block_hash: &'a str,
+    value_zat: i64,
+    /// Block time, in Unix seconds.
+    timestamp: u32,
+}
+
+pub(crate) struct Webhook {
+    url: String,
+    secret: Option<String>,
+    threshold: usize,
+    client: reqwest::blocking::Client,
+    /// Highest height posted (or present in the first report) per miner label; `None` until
+    /// the first report is seen.
+    posted: Option<HashMap<String, u64>>,
+}
+
+impl Webhook {
+    pub(crate) fn new(url: String, secret: Option<String>, threshold: u32) -> Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(TIMEOUT)
+            .build()
+            .map_err(Error::HttpClient)?;
+        Ok(Self {
+            url,
+            secret,
+            threshold: threshold as usize,
+            client,
+            posted: None,
+        })
+    }
+
+    /// Posts the blocks each miner gained since the last post, once there are at least
+    /// `threshold` of them. The first report only sets the baseline: blocks already mined
+    /// when watching started are never posted. Failed posts are logged, not returned, so a
+    /// webhook outage does not stop the watch.
+    pub(crate) fn notify(&mut self, report: &MinerStatsReport) {
+        let baseline = self.posted.is_none();
+        let mut posted = self.posted.take().unwrap_or_default();
+        for miner in &report.detailed_miners {
+            let last = posted.get(&miner.label).copied();
+            let new: Vec<&MinerBlockDetail> = miner
+                .detailed_blocks
+                .iter()
+                .filter(|block| last.is_none_or(|last| block.block_height > last))
+                .collect();
+            let Some(newest) = new.iter().map(|block| block.block_height).max() else {
+                continue;
+            };
+            if !baseline {
+                if new.len() < self.threshold {
+                    continue;
+                }
+                for block in new {
+                    self.post(&BlockNotification {
+                        miner_label: &miner.label,
+                        block_height: block.block_height,
+                        block_hash: &block.block_hash,
+                        value_zat: block.value_zat,
+                        timestamp: block.block_time,
+                    });
+                }
+            }
+            posted.insert(miner.label.clone(), newest);
+        }
+        self.posted = Some(posted);
+    }
+
+    fn post(&self, notification: &BlockNotification) {
+        let body = match serde_json::to_vec(notification) {
+            Ok(body) => body,
+            Err(err) => {
+                warn!("serializing webhook body: {err}");
+                return;
+            }
+        };
+        let signature = self.secret.as_deref().map(|secret| sign(secret, &body));
+        for attempt in 0..=RETRIES {
+            if attempt > 0 {
+                std::thread::sleep(RETRY_DELAY);
+            }
+            let mut request = self
+                .client
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            match request
+                .send()
+                .and_then(|response| response.error_for_status())
+            {
+                Ok(_) => {
+                    debug!(
+                        miner = notification.miner_label,
+                        height = notification.block_height,
+                        "posted block to webhook"
+                    );
+                    return;
+                }
+                Err(err) => warn!(attempt = attempt + 1, "webhook {} failed: {err}", self.url),
+            }
+        }
+        warn!(
+            miner = notification.miner_label,
+            height = notification.block_height,
+            "giving up on posting block to webhook after {} attempts",
+            RETRIES + 1
+        );
+    }
+}
+
+/// `sha256=` followed by the hex HMAC-SHA256 of `body` keyed with `secret`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        unreachable!("HMAC accepts keys of any length");
+    };
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
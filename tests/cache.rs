@@ -0,0 +1,306 @@
+//! Round trips through the `CacheBackend` implementations.
+
+use std::path::PathBuf;
+use uview_miner_stats_lib::{
+    BlockCache, CACHE_VERSION, CacheBackend, CacheFormat, CachedBlock, CoinbaseOutput, Error,
+    JsonFileCache, NoOpCache, ShardedFileCache, cache_export::write_csv,
+};
+
+fn sample_cache() -> BlockCache {
+    let mut cache = BlockCache {
+        last_tip: Some(42),
+        ..BlockCache::default()
+    };
+    for height in 40..=42 {
+        cache.blocks.insert(
+            height,
+            CachedBlock {
+                height,
+                hash: format!("{height:064x}"),
+                time: 1_700_000_000 + height as u32,
+                outputs: vec![CoinbaseOutput {
+                    value_zat: 312_500_000,
+                    addresses: vec!["t1exampleaddress".to_string()],
+                    hex: None,
+                }],
+                subsidy_zat: None,
+            },
+        );
+    }
+    cache
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("uview-miner-stats-{}-{name}", std::process::id()))
+}
+
+fn assert_same(loaded: &BlockCache, expected: &BlockCache) {
+    assert_eq!(loaded.last_tip, expected.last_tip);
+    assert_eq!(
+        loaded.blocks.keys().collect::<Vec<_>>(),
+        expected.blocks.keys().collect::<Vec<_>>()
+    );
+    for (height, block) in &expected.blocks {
+        assert_eq!(loaded.blocks[height].hash, block.hash);
+        assert_eq!(loaded.blocks[height].time, block.time);
+        assert_eq!(
+            loaded.blocks[height].outputs[0].value_zat,
+            block.outputs[0].value_zat
+        );
+    }
+}
+
+#[test]
+fn noop_cache_always_loads_empty() {
+    let backend = NoOpCache;
+    backend.save(&sample_cache()).unwrap();
+    let loaded = backend.load().unwrap().0;
+    assert!(loaded.blocks.is_empty());
+    assert_eq!(loaded.last_tip, None);
+}
+
+#[test]
+fn json_file_cache_round_trips() {
+    for compressed in [false, true] {
+        for format in [CacheFormat::Json, CacheFormat::Msgpack] {
+            let path = temp_path(&format!("cache-{compressed}-{format:?}.json"));
+            let backend = JsonFileCache {
+                path: path.clone(),
+                compressed,
+                format,
+                readonly: false,
+            };
+            let cache = sample_cache();
+            backend.save(&cache).unwrap();
+            assert_same(&backend.load().unwrap().0, &cache);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
+
+#[test]
+fn failed_save_leaves_the_existing_cache_intact() {
+    let path = temp_path("cache-failed-save.json");
+    let tmp = temp_path("cache-failed-save.json.tmp");
+    let _ = std::fs::remove_dir_all(&tmp);
+    let cache = sample_cache();
+    cache.save(&path, false).unwrap();
+    let before = std::fs::read(&path).unwrap();
+
+    // A directory where the temp file goes makes writing it fail.
+    std::fs::create_dir(&tmp).unwrap();
+    let mut changed = sample_cache();
+    changed.blocks.remove(&40);
+    changed.last_tip = Some(43);
+    assert!(matches!(changed.save(&path, false), Err(Error::Io { .. })));
+
+    assert_eq!(std::fs::read(&path).unwrap(), before);
+    assert_same(&BlockCache::load(&path).unwrap().0, &cache);
+    std::fs::remove_dir_all(&tmp).unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn format_is_detected_on_load_whatever_the_configured_one() {
+    let path = temp_path("cache-switched-format.json");
+    let cache = sample_cache();
+    cache.save_as(&path, CacheFormat::Msgpack, false).unwrap();
+    assert_ne!(std::fs::read(&path).unwrap()[0], b'{');
+    let backend = JsonFileCache {
+        path: path.clone(),
+        compressed: false,
+        format: CacheFormat::Json,
+        readonly: false,
+    };
+    assert_same(&backend.load().unwrap().0, &cache);
+    backend.save(&cache).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap()[0], b'{');
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn sharded_cache_reads_only_the_shards_from_start_height() {
+    let dir = temp_path("shards");
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("cache.json");
+    let shards = |start_height| {
+        ShardedFileCache::new(
+            path.clone(),
+            2,
+            start_height,
+            false,
+            CacheFormat::Json,
+            false,
+        )
+    };
+    let cache = sample_cache();
+    shards(0).save(&cache).unwrap();
+    let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        ["cache-0000040-0000041.json", "cache-0000042-0000043.json"]
+    );
+    assert_same(&shards(0).load().unwrap().0, &cache);
+
+    let recent = shards(42).load().unwrap().0;
+    assert_eq!(recent.last_tip, Some(42));
+    assert_eq!(recent.blocks.keys().collect::<Vec<_>>(), [&42]);
+
+    // A shard whose blocks are all dropped is removed rather than left to be read again.
+    let backend = shards(42);
+    let mut recent = backend.load().unwrap().0;
+    recent.blocks.clear();
+    backend.save(&recent).unwrap();
+    assert!(!dir.join("cache-0000042-0000043.json").exists());
+    assert!(dir.join("cache-0000040-0000041.json").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cache_csv_export_has_a_row_per_block_or_per_output() {
+    let mut cache = sample_cache();
+    cache
+        .blocks
+        .get_mut(&41)
+        .unwrap()
+        .outputs
+        .push(CoinbaseOutput {
+            value_zat: 12_500_000,
+            addresses: vec!["t1a".to_string(), "t1\"b\",c".to_string()],
+            hex: None,
+        });
+    cache.blocks.get_mut(&42).unwrap().outputs.clear();
+    let path = temp_path("cache-export.csv");
+    let hash = |height: u64| format!("{height:064x}");
+
+    write_csv(&cache, &path, false).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        format!(
+            "height,hash,num_outputs,total_coinbase_zat\r\n\
+             40,{},1,312500000\r\n\
+             41,{},2,325000000\r\n\
+             42,{},0,0\r\n",
+            hash(40),
+            hash(41),
+            hash(42)
+        )
+    );
+
+    write_csv(&cache, &path, true).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        format!(
+            "height,hash,num_outputs,total_coinbase_zat,output_index,value_zat,addresses\r\n\
+             40,{h40},1,312500000,0,312500000,t1exampleaddress\r\n\
+             41,{h41},2,325000000,0,312500000,t1exampleaddress\r\n\
+             41,{h41},2,325000000,1,12500000,\"t1a t1\"\"b\"\",c\"\r\n\
+             42,{h42},0,0,,,\r\n",
+            h40 = hash(40),
+            h41 = hash(41),
+            h42 = hash(42)
+        )
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn coverage_gaps_are_the_uncached_runs_of_the_range() {
+    let mut cache = sample_cache();
+    cache.blocks.remove(&41);
+
+    assert_eq!(cache.coverage_gaps(40, 42), vec![41..=41]);
+    assert_eq!(cache.coverage_gaps(37, 45), vec![37..=39, 41..=41, 43..=45]);
+    assert_eq!(cache.coverage_gaps(42, 42), vec![]);
+    assert_eq!(cache.coverage_gaps(45, 40), vec![]);
+}
+
+#[test]
+fn unversioned_cache_is_migrated_in_memory_only() {
+    let path = temp_path("cache-v0.json");
+    std::fs::write(&path, include_bytes!("fixtures/cache-v0.json")).unwrap();
+
+    let (cache, migrated) = BlockCache::load(&path).unwrap();
+    assert!(migrated);
+    assert_eq!(cache.version, CACHE_VERSION);
+    assert_eq!(cache.last_tip, Some(101));
+    assert_eq!(cache.blocks.len(), 2);
+    // `time` did not exist yet: it loads as "unknown" so the block is re-fetched once.
+    assert_eq!(cache.blocks[&100].time, 0);
+    assert!(!cache.blocks[&100].is_complete());
+    assert_eq!(
+        std::fs::read(&path).unwrap(),
+        include_bytes!("fixtures/cache-v0.json")
+    );
+
+    cache.save(&path, false).unwrap();
+    assert!(!BlockCache::load(&path).unwrap().1);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn readonly_cache_is_never_saved() {
+    let path = temp_path("cache-readonly-v0.json");
+    std::fs::write(&path, include_bytes!("fixtures/cache-v0.json")).unwrap();
+    let backend = JsonFileCache {
+        path: path.clone(),
+        compressed: false,
+        format: CacheFormat::Json,
+        readonly: true,
+    };
+
+    let (cache, migrated) = backend.load().unwrap();
+    assert!(migrated);
+    assert_eq!(cache.version, CACHE_VERSION);
+    backend.save(&cache).unwrap();
+    assert_eq!(
+        std::fs::read(&path).unwrap(),
+        include_bytes!("fixtures/cache-v0.json")
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    let missing = JsonFileCache {
+        path: temp_path("readonly-missing-dir").join("cache.json"),
+        ..backend
+    };
+    assert!(missing.load().unwrap().0.blocks.is_empty());
+    missing.save(&sample_cache()).unwrap();
+    assert!(!missing.path.parent().unwrap().exists());
+}
+
+#[test]
+fn cache_from_a_newer_version_is_rejected() {
+    let path = temp_path("cache-v99.json");
+    std::fs::write(&path, include_bytes!("fixtures/cache-v99.json")).unwrap();
+
+    let err = BlockCache::load(&path).err().unwrap();
+    assert!(
+        matches!(err, Error::CacheVersionUnsupported { found: 99, .. }),
+        "{err}"
+    );
+    assert!(err.to_string().contains("upgrade"), "{err}");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_cache_round_trips() {
+    use uview_miner_stats_lib::SqliteCache;
+
+    let path = temp_path("cache.sqlite");
+    let backend = SqliteCache::new(path.clone());
+    let mut cache = sample_cache();
+    backend.save(&cache).unwrap();
+    assert_same(&backend.load().unwrap().0, &cache);
+
+    // Saving again replaces the stored blocks rather than appending.
+    cache.blocks.remove(&40);
+    cache.last_tip = Some(41);
+    backend.save(&cache).unwrap();
+    assert_same(&backend.load().unwrap().0, &cache);
+    std::fs::remove_file(&path).unwrap();
+}
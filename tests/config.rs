@@ -0,0 +1,120 @@
+//! Building a `MinerStatsConfig` in code through `MinerStatsConfigBuilder`.
+
+use std::path::{Path, PathBuf};
+use uview_miner_stats_lib::{Error, MinerStatsConfig, MinerStatsConfigBuilder};
+use zingolib::config::chain_from_str;
+
+fn work_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "uview-miner-stats-config-{}-{name}",
+        std::process::id()
+    ))
+}
+
+fn builder(dir: &Path) -> MinerStatsConfigBuilder {
+    MinerStatsConfig::builder()
+        .start_height(100)
+        .chain(chain_from_str("regtest").unwrap())
+        .rpc_url("http://127.0.0.1:18232")
+        .cache_file(dir.join("cache").join("stats-cache.json"))
+        .output_file(dir.join("out").join("miner-stats.json"))
+}
+
+fn invalid_field(err: Error) -> String {
+    match err {
+        Error::ConfigInvalid { field, .. } => field,
+        other => panic!("expected ConfigInvalid, got {other}"),
+    }
+}
+
+#[test]
+fn build_creates_only_the_output_directory() {
+    let dir = work_dir("dirs");
+    let cfg = builder(&dir).add_miner("Alpha", "uview1example").build();
+
+    assert!(cfg.is_ok(), "{:?}", cfg.err());
+    // The cache directory waits for the first cache write, which `--no-cache` never does.
+    assert!(!dir.join("cache").exists());
+    assert!(dir.join("out").is_dir());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn build_applies_the_file_validation() {
+    let dir = work_dir("validation");
+    assert_eq!(invalid_field(builder(&dir).build().unwrap_err()), "ufvks");
+
+    let no_chain = MinerStatsConfig::builder()
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", "uview1example")
+        .cache_file(dir.join("stats-cache.json"))
+        .output_file(dir.join("miner-stats.json"));
+    assert_eq!(invalid_field(no_chain.build().unwrap_err()), "chain");
+}
+
+#[test]
+fn build_rejects_a_ufvk_listed_twice() {
+    let dir = work_dir("duplicates");
+    let err = builder(&dir)
+        .add_miner("Alpha", "uview1example")
+        .add_miner("Alpha (old)", "uview1example")
+        .build()
+        .unwrap_err();
+
+    assert!(err.to_string().contains("'Alpha', 'Alpha (old)'"), "{err}");
+    assert_eq!(invalid_field(err), "ufvks");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn non_ascii_ufvk_is_shortened_on_char_boundaries() {
+    let dir = work_dir("non-ascii");
+    let key = format!("uview1{}", "€".repeat(12));
+    let err = builder(&dir)
+        .add_miner("Alpha", &key)
+        .add_miner("Beta", &key)
+        .build()
+        .unwrap_err();
+
+    assert!(err.to_string().contains("uview1€€…€€€€€€€€ "), "{err}");
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn duplicate_labels_fail_only_under_strict_labels() {
+    let dir = work_dir("labels");
+    let shared = || {
+        builder(&dir)
+            .add_miner("Pool", "uview1first")
+            .add_miner("Pool", "uview1second")
+    };
+    assert!(shared().build().is_ok());
+
+    let err = shared().strict_labels(true).build().unwrap_err();
+    assert!(err.to_string().contains("'Pool'"), "{err}");
+    assert_eq!(invalid_field(err), "ufvks");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn build_rejects_unknown_explorer_url_placeholders() {
+    let dir = work_dir("explorer");
+    let with_template = |template: &str| {
+        builder(&dir)
+            .add_miner("Alpha", "uview1example")
+            .explorer_url_template(template)
+            .build()
+    };
+
+    assert!(with_template("https://explorer.example/block/{hash}?h={height}").is_ok());
+    for bad in [
+        "https://explorer.example/{txid}",
+        "https://explorer.example/{hash",
+    ] {
+        assert_eq!(
+            invalid_field(with_template(bad).unwrap_err()),
+            "explorer_url_template"
+        );
+    }
+    std::fs::remove_dir_all(&dir).ok();
+}
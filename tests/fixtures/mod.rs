@@ -0,0 +1,165 @@
+//! Shared fixtures for integration tests.
+//!
+//! [`MockZcashdServer`] answers the JSON-RPC methods the scanner uses (`getinfo`,
+//! `getblockcount`, `getblockhash`, `getblock`, `decodescript`), single or batched, from an
+//! in-memory chain. [`regtest_chain`] is a 10-block regtest chain whose coinbases pay two
+//! synthetic miners, [`ALPHA_UFVK`] and [`BETA_UFVK`], at their height-derived transparent
+//! addresses.
+
+#![allow(dead_code)]
+
+use mockito::{Mock, Server, ServerGuard};
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
+
+/// Synthetic regtest UFVK (transparent + Sapling) paid at heights 1, 2, 5 and 8.
+pub const ALPHA_UFVK: &str = "uviewregtest1w5eddcgh298pcg7p4uda6u2fdtgz8pvpm96mvjm8nwhr7zefr08h3aptk5gdl5cn8kt7k2vm0wrw02tm4vuv87c9j84nn5jytdhkl3s8w3lqf89fcnqc8wk4y4slq68dqmuvadjxhamnr2lv2waqx4cq4s8uwmsqw7dnt8r8gmznyrqgd2g5glrav3aqzf23pxtdw58dj9f40sg8rfxvag4xgnnj56wnpflkuwkyd9x4xu65ff5m4v2yzzuurzhnknemx679ndslw2tlqcm2yw49aujdfuqe8nec6elwxl4lhm85l5c9ke0h2952dy5y20ncmke39fahyqr34fk9w2lahrh";
+
+/// Synthetic regtest UFVK (transparent + Sapling) paid at heights 3 and 6.
+pub const BETA_UFVK: &str = "uviewregtest1h6rdhy9f0ah5p3j8p0tjqnq08yq6mlwu3p3ktpqrvf7gmukqvdav8sdmcmhny8ltux8xn3vdvh27urm4wm2vutzljjpd3elc7dq34zh06wt9hffcjyt8tc35dukfu4z96ymrczyuq4qnpjml2lht5c0dqfpdp3frxsmfxjytgtqqw9f0p48syadsy82wzatu7zxmrv26v93kf5lj2mgfsz02rjwjhatvqcnjqh4f2wv5lz6ckt2sh9ep7mhpqylrlxxz0mzk4j8rs3sntralwhqaefm8yul2lyz9ktjw59np463vzs048gjh8elw8tjvw7nm042pn5qvpfmxajtyk9tva9m";
+
+/// Payout address of every block in [`regtest_chain`], by height. Entries for the two miners
+/// are their external transparent addresses at child index == height.
+const PAYOUTS: [&str; 10] = [
+    "tmY1LKuLbLYWagDa8pWnk6rB9qtZKrFiGqT",
+    "tmBvXxBvuKdnRuog38zVY5YYjxAGYpEoyrS",
+    "tmSYcB6bddgHN7DkzRD3fP14CCr7UgJEidy",
+    "tmP1g6GYpbhP4NDMxx7PsvgrXhs5Y2S641j",
+    "tmY1LKuLbLYWagDa8pWnk6rB9qtZKrFiGqT",
+    "tmX7D6QszKrkomoksEn33MG5g375fxHbyZt",
+    "tmURWMhcNXnMHGy4cmWxvT5eJTQARLBhvJP",
+    "tmY1LKuLbLYWagDa8pWnk6rB9qtZKrFiGqT",
+    "tmXJCj98hruiDEwaAfu7Fp3qBy7SKDGUXWQ",
+    "tmY1LKuLbLYWagDa8pWnk6rB9qtZKrFiGqT",
+];
+
+/// Coinbase value of every block in [`regtest_chain`].
+pub const BLOCK_VALUE_ZAT: i64 = 625_000_000;
+
+#[derive(Debug, Clone)]
+pub struct MockBlock {
+    pub height: u64,
+    pub hash: String,
+    pub time: u32,
+    pub payout_address: String,
+    pub value_zat: i64,
+    /// When set, `getblock` lists only this script `hex` for the coinbase output, and
+    /// `decodescript` decodes it to `payout_address`.
+    pub payout_script: Option<String>,
+}
+
+pub fn block_hash(height: u64) -> String {
+    format!("{:064x}", 0x0b10_c000_0000_u64 + height)
+}
+
+pub fn regtest_chain() -> Vec<MockBlock> {
+    PAYOUTS
+        .iter()
+        .enumerate()
+        .map(|(height, address)| MockBlock {
+            height: height as u64,
+            hash: block_hash(height as u64),
+            time: 1_700_000_000 + height as u32 * 75,
+            payout_address: address.to_string(),
+            value_zat: BLOCK_VALUE_ZAT,
+            payout_script: None,
+        })
+        .collect()
+}
+
+/// Local HTTP server speaking just enough zcashd JSON-RPC for the scanner. The chain can be
+/// swapped between runs with [`MockZcashdServer::set_chain`] to simulate new blocks or
+/// re-orgs.
+pub struct MockZcashdServer {
+    server: ServerGuard,
+    chain: Arc<Mutex<Vec<MockBlock>>>,
+    _mock: Mock,
+}
+
+impl MockZcashdServer {
+    pub fn start(chain: Vec<MockBlock>) -> Self {
+        let mut server = Server::new();
+        let chain = Arc::new(Mutex::new(chain));
+        let state = chain.clone();
+        let mock = server
+            .mock("POST", "/")
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let body: Value = serde_json::from_slice(request.body().expect("request body"))
+                    .expect("JSON-RPC request");
+                let chain = state.lock().expect("chain lock");
+                let response = match body {
+                    Value::Array(requests) => {
+                        Value::Array(requests.iter().map(|r| answer(&chain, r)).collect())
+                    }
+                    request => answer(&chain, &request),
+                };
+                serde_json::to_vec(&response).expect("JSON-RPC response")
+            })
+            .expect_at_least(1)
+            .create();
+        Self {
+            server,
+            chain,
+            _mock: mock,
+        }
+    }
+
+    pub fn url(&self) -> String {
+        self.server.url()
+    }
+
+    pub fn set_chain(&self, chain: Vec<MockBlock>) {
+        *self.chain.lock().expect("chain lock") = chain;
+    }
+}
+
+fn answer(chain: &[MockBlock], request: &Value) -> Value {
+    let id = request["id"].clone();
+    let params = &request["params"];
+    let result = match request["method"].as_str() {
+        Some("getinfo") => Some(json!({ "version": 6000050, "subversion": "/MagicBean:6.0.0/" })),
+        Some("getblockcount") => chain.last().map(|b| json!(b.height)),
+        Some("getblockhash") => params[0]
+            .as_u64()
+            .and_then(|height| chain.iter().find(|b| b.height == height))
+            .map(|b| json!(b.hash)),
+        Some("getblock") => params[0]
+            .as_str()
+            .and_then(|hash| chain.iter().find(|b| b.hash == hash))
+            .map(|b| {
+                json!({
+                    "hash": b.hash,
+                    "height": b.height,
+                    "time": b.time,
+                    "tx": [{
+                        "vout": [{
+                            "valueZat": b.value_zat,
+                            "scriptPubKey": match &b.payout_script {
+                                Some(hex) => json!({ "hex": hex }),
+                                None => json!({ "addresses": [b.payout_address] }),
+                            },
+                        }],
+                    }],
+                })
+            }),
+        Some("decodescript") => params[0]
+            .as_str()
+            .and_then(|hex| {
+                chain
+                    .iter()
+                    .find(|b| b.payout_script.as_deref() == Some(hex))
+            })
+            .map(|b| json!({ "type": "scripthash", "addresses": [b.payout_address] })),
+        _ => None,
+    };
+    match result {
+        Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result, "error": null }),
+        None => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": null,
+            "error": { "code": -8, "message": "Block not found" },
+        }),
+    }
+}
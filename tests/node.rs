@@ -0,0 +1,100 @@
+//! Decoding `getblock` replies as each supported node formats them, and how
+//! [`NodeRpcClient`] retries failed calls.
+
+use std::time::Duration;
+use uview_miner_stats_lib::{
+    Error, NodeRpcClient, NodeType, RetryPolicy, RpcClient, RpcClientOptions, RpcTls,
+};
+use zingolib::config::chain_from_str;
+
+/// Client for `url` retrying up to three attempts without backoff.
+fn retrying_client(url: String) -> NodeRpcClient {
+    NodeRpcClient::new(
+        vec![url],
+        RpcClientOptions {
+            auth: None,
+            tls: RpcTls::Default,
+            retry: RetryPolicy {
+                max_attempts: 3,
+                base_delay_ms: 0,
+                jitter_ms: 0,
+            },
+            timeout: Duration::from_secs(5),
+            fetch_timeout: Duration::from_secs(5),
+            max_concurrent: 1,
+            metrics: None,
+            chain: chain_from_str("regtest").unwrap(),
+            node_type: Some(NodeType::Zcashd),
+        },
+    )
+    .unwrap()
+}
+
+#[test]
+fn zebrad_getblock_yields_coinbase_outputs() {
+    let block = include_str!("fixtures/zebrad-getblock.json");
+    let outputs = NodeType::Zebrad
+        .coinbase_outputs(block)
+        .expect("zebrad getblock");
+    let outputs: Vec<(i64, Vec<String>)> = outputs
+        .into_iter()
+        .map(|output| (output.value_zat, output.addresses))
+        .collect();
+    assert_eq!(
+        outputs,
+        [
+            (
+                156_250_000,
+                vec!["t1Zebrad2500000MinerPayoutAddressxx".to_string()]
+            ),
+            (
+                12_500_000,
+                vec!["t3Zebrad2500000FundingStreamAddrxxx".to_string()]
+            ),
+            (0, Vec::new()),
+        ]
+    );
+}
+
+#[test]
+fn zcashd_decoding_requires_value_zat() {
+    let block = include_str!("fixtures/zebrad-getblock.json");
+    assert!(NodeType::Zcashd.coinbase_outputs(block).is_err());
+}
+
+#[test]
+fn unavailable_node_is_retried_until_it_answers() {
+    let mut server = mockito::Server::new();
+    // A mock serves requests until it has had its expected hits, then the next one takes
+    // over.
+    let unavailable = server.mock("POST", "/").with_status(503).expect(2).create();
+    let answering = server
+        .mock("POST", "/")
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"result": 42, "error": null, "id": "zingo-miner-stats"}"#)
+        .expect(1)
+        .create();
+
+    assert_eq!(retrying_client(server.url()).block_count().unwrap(), 42);
+    unavailable.assert();
+    answering.assert();
+}
+
+#[test]
+fn rpc_errors_sent_with_http_500_are_not_retried() {
+    let mut server = mockito::Server::new();
+    // zcashd reports every JSON-RPC error with HTTP 500.
+    let out_of_range = server
+        .mock("POST", "/")
+        .with_status(500)
+        .with_header("content-type", "application/json")
+        .with_body(
+            r#"{"result": null, "error": {"code": -8, "message": "Block height out of range"}, "id": "zingo-miner-stats"}"#,
+        )
+        .expect(1)
+        .create();
+
+    let err = retrying_client(server.url()).fetch_block(99).unwrap_err();
+    assert!(matches!(err, Error::RpcError { code: -8, .. }), "{err}");
+    out_of_range.assert();
+}
@@ -0,0 +1,1010 @@
+//! End-to-end runs of the scanner against [`fixtures::MockZcashdServer`].
+
+mod fixtures;
+
+use fixtures::{
+    ALPHA_UFVK, BETA_UFVK, BLOCK_VALUE_ZAT, MockBlock, MockZcashdServer, block_hash, regtest_chain,
+};
+use serde_json::Value;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use uview_miner_stats_lib::{
+    BlockCache, CACHE_VERSION, CacheBackend, CacheFormat, DEFAULT_ES_INDEX, DEFAULT_LABEL_WIDTH,
+    Error, MinerSortOrder, OutputFormat, RunOptions, ShardedFileCache, Tz, merge,
+};
+
+/// Report fields whose values are fully determined by the fixture chain. `halvings` is
+/// left out because it depends on the regtest activation heights of the linked zingolib.
+const SNAPSHOT_FIELDS: &[&str] = &[
+    "start_height",
+    "end_height",
+    "total_mined_blocks",
+    "total_value_zat",
+    "total_value_wec",
+    "miners",
+    "detailed_miners",
+    "time_range",
+];
+
+fn work_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "uview-miner-stats-pipeline-{}-{name}",
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn write_config(dir: &Path, rpc_url: &str) -> PathBuf {
+    write_config_with_miners(
+        dir,
+        rpc_url,
+        &format!(
+            "{{ label = \"Alpha\", key = \"{ALPHA_UFVK}\" }},\n\
+             {{ label = \"Beta\", key = \"{BETA_UFVK}\" }},\n"
+        ),
+    )
+}
+
+fn write_config_with_miners(dir: &Path, rpc_url: &str, ufvks: &str) -> PathBuf {
+    let path = dir.join("config.toml");
+    fs::write(
+        &path,
+        format!(
+            "start_height = 0\n\
+             chain = \"regtest\"\n\
+             rpc_url = \"{rpc_url}\"\n\
+             rpc_batch_size = 4\n\
+             cache_file = \"{}\"\n\
+             output_file = \"{}\"\n\
+             ufvks = [\n{ufvks}]\n",
+            dir.join("cache.json").display(),
+            dir.join("report.json").display(),
+        ),
+    )
+    .unwrap();
+    path
+}
+
+fn run(config: &Path) {
+    run_with(config, &run_options());
+}
+
+fn run_with(config: &Path, options: &RunOptions) {
+    uview_miner_stats_lib::run(config, options).unwrap();
+}
+
+/// The options of a plain `uview-miner-stats --format json` run.
+fn run_options() -> RunOptions {
+    RunOptions {
+        output_format: OutputFormat::Json,
+        detail_output: None,
+        stream_output: None,
+        influx_output: None,
+        es_output: None,
+        es_index: DEFAULT_ES_INDEX.to_string(),
+        compress_output: false,
+        decompress_output: false,
+        summary_only: false,
+        no_cache: false,
+        force_refetch: false,
+        dry_run: false,
+        color: false,
+        sort_by: MinerSortOrder::Config,
+        prune_cache: false,
+        label_width: DEFAULT_LABEL_WIDTH,
+        show_value_share: false,
+        table_no_border: false,
+        no_outliers: false,
+        show_activity: false,
+        show_maturity: false,
+        human_values: false,
+        timezone: Tz::UTC,
+        tui: false,
+        watch: None,
+        interrupt: Default::default(),
+    }
+}
+
+fn read_json(path: &Path) -> Value {
+    serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+}
+
+/// Rewrites the JSON cache at `path` as a version 0 cache, from before the schema version
+/// was recorded.
+fn unversion_cache(path: &Path) {
+    let mut cache = read_json(path);
+    cache.as_object_mut().unwrap().remove("version");
+    fs::write(path, serde_json::to_vec(&cache).unwrap()).unwrap();
+}
+
+/// Runs the scanner from `start_height` over `chain` in a work directory of its own and
+/// returns the path of the JSON report.
+fn report_for(name: &str, chain: Vec<MockBlock>, start_height: u64) -> PathBuf {
+    let server = MockZcashdServer::start(chain);
+    let dir = work_dir(name);
+    let config = write_config(&dir, &server.url());
+    let toml = fs::read_to_string(&config).unwrap().replace(
+        "start_height = 0\n",
+        &format!("start_height = {start_height}\n"),
+    );
+    fs::write(&config, toml).unwrap();
+    run(&config);
+    dir.join("report.json")
+}
+
+#[test]
+fn report_matches_snapshot_and_cache_is_written() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("snapshot");
+    run(&write_config(&dir, &server.url()));
+
+    let report = read_json(&dir.join("report.json"));
+    let snapshot = read_json(Path::new("tests/fixtures/regtest-report.json"));
+    for field in SNAPSHOT_FIELDS {
+        assert_eq!(report[field], snapshot[field], "report field `{field}`");
+    }
+
+    let cache = BlockCache::load(&dir.join("cache.json")).unwrap().0;
+    assert_eq!(cache.last_tip, Some(9));
+    assert_eq!(cache.blocks.len(), 10);
+    assert_eq!(cache.blocks[&7].hash, block_hash(7));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rerun_extends_the_cache_to_the_new_tip() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("rerun");
+    let config = write_config(&dir, &server.url());
+    run(&config);
+
+    let mut chain = regtest_chain();
+    let tip = chain.last().unwrap().clone();
+    chain.push(MockBlock {
+        height: 10,
+        hash: block_hash(10),
+        time: tip.time + 75,
+        ..tip
+    });
+    server.set_chain(chain);
+    run(&config);
+
+    let report = read_json(&dir.join("report.json"));
+    assert_eq!(report["end_height"], 10);
+    assert_eq!(report["total_mined_blocks"], 6);
+    assert_eq!(report["miners"][0]["share_percent"], 36.36);
+    let cache = BlockCache::load(&dir.join("cache.json")).unwrap().0;
+    assert_eq!(cache.last_tip, Some(10));
+    assert_eq!(cache.blocks.len(), 11);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn outputs_without_addresses_are_matched_through_decodescript() {
+    let mut chain = regtest_chain();
+    chain[1].payout_script = Some("a914d45cb1adffb5215a42720532a076f02c7c778c9087".to_string());
+    let server = MockZcashdServer::start(chain);
+    let dir = work_dir("decodescript");
+    run(&write_config(&dir, &server.url()));
+
+    let report = read_json(&dir.join("report.json"));
+    let snapshot = read_json(Path::new("tests/fixtures/regtest-report.json"));
+    assert_eq!(report["miners"], snapshot["miners"]);
+    let cache = BlockCache::load(&dir.join("cache.json")).unwrap().0;
+    let output = &cache.blocks[&1].outputs[0];
+    assert_eq!(
+        output.addresses,
+        [regtest_chain()[1].payout_address.clone()]
+    );
+    assert!(output.hex.is_some());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dry_run_leaves_the_filesystem_untouched() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("dry-run");
+    let config = write_config(&dir, &server.url());
+    fs::write(
+        &config,
+        fs::read_to_string(&config).unwrap()
+            + &format!(
+                "per_miner_output_dir = \"{}\"\n",
+                dir.join("per-miner").display()
+            ),
+    )
+    .unwrap();
+    run_with(
+        &config,
+        &RunOptions {
+            dry_run: true,
+            ..run_options()
+        },
+    );
+
+    let entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, ["config.toml"]);
+
+    // A cache with an older schema is migrated in memory only, with or without new blocks.
+    let cache_path = dir.join("cache.json");
+    for synced in [false, true] {
+        if synced {
+            run(&config);
+            fs::remove_dir_all(dir.join("per-miner")).unwrap();
+            fs::remove_file(dir.join("report.json")).unwrap();
+        } else {
+            fs::write(&cache_path, include_bytes!("fixtures/cache-v0.json")).unwrap();
+        }
+        unversion_cache(&cache_path);
+        let cached = fs::read(&cache_path).unwrap();
+        run_with(
+            &config,
+            &RunOptions {
+                dry_run: true,
+                ..run_options()
+            },
+        );
+        assert_eq!(fs::read(&cache_path).unwrap(), cached, "synced: {synced}");
+        assert!(!dir.join("report.json").exists());
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn migrated_cache_is_saved_back_without_new_blocks() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("migrated-cache");
+    let config = write_config(&dir, &server.url());
+    run(&config);
+    let cache_path = dir.join("cache.json");
+    unversion_cache(&cache_path);
+
+    run(&config);
+    assert_eq!(read_json(&cache_path)["version"], CACHE_VERSION);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn decompress_output_overrides_compress_output_in_the_config() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("decompress-output");
+    let config = write_config(&dir, &server.url());
+    fs::write(
+        &config,
+        fs::read_to_string(&config).unwrap() + "compress_output = true\n",
+    )
+    .unwrap();
+    run_with(
+        &config,
+        &RunOptions {
+            decompress_output: true,
+            ..run_options()
+        },
+    );
+
+    assert!(!dir.join("report.json.gz").exists());
+    assert_eq!(
+        read_json(&dir.join("report.json"))["total_mined_blocks"],
+        10
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn no_cache_writes_only_the_report() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("no-cache");
+    let config = write_config(&dir, &server.url());
+    let cache_dir = dir.join("cache");
+    let toml = fs::read_to_string(&config).unwrap().replace(
+        &dir.join("cache.json").display().to_string(),
+        &cache_dir.join("cache.json").display().to_string(),
+    );
+    fs::write(&config, toml).unwrap();
+    run_with(
+        &config,
+        &RunOptions {
+            no_cache: true,
+            ..run_options()
+        },
+    );
+
+    assert!(!cache_dir.exists());
+    let report = read_json(&dir.join("report.json"));
+    assert_eq!(report["total_mined_blocks"], 10);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn force_refetch_replaces_stale_cached_blocks() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("force-refetch");
+    let config = write_config(&dir, &server.url());
+    run(&config);
+    let cache_path = dir.join("cache.json");
+    let mut cache = BlockCache::load(&cache_path).unwrap().0;
+    cache.blocks.get_mut(&3).unwrap().outputs.clear();
+    cache.save(&cache_path, false).unwrap();
+
+    run_with(
+        &config,
+        &RunOptions {
+            force_refetch: true,
+            ..run_options()
+        },
+    );
+
+    let cache = BlockCache::load(&cache_path).unwrap().0;
+    assert_eq!(cache.blocks.len(), 10);
+    assert!(!cache.blocks[&3].outputs.is_empty());
+    let report = read_json(&dir.join("report.json"));
+    let snapshot = read_json(Path::new("tests/fixtures/regtest-report.json"));
+    assert_eq!(report["miners"], snapshot["miners"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn readonly_cache_is_never_written_and_missing_blocks_are_left_out() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("cache-readonly");
+    let config = write_config(&dir, &server.url());
+    run(&config);
+    let cache_path = dir.join("cache.json");
+    let mut cache = BlockCache::load(&cache_path).unwrap().0;
+    cache.blocks.remove(&8);
+    cache.blocks.remove(&9);
+    cache.save(&cache_path, false).unwrap();
+    let cached = fs::read(&cache_path).unwrap();
+    let plain = fs::read_to_string(&config).unwrap();
+    fs::write(
+        &config,
+        format!(
+            "{plain}cache_readonly = true
+"
+        ),
+    )
+    .unwrap();
+
+    run(&config);
+
+    assert_eq!(fs::read(&cache_path).unwrap(), cached);
+    let report = read_json(&dir.join("report.json"));
+    assert_eq!(report["end_height"], 9);
+    assert_eq!(report["coverage_gaps"], serde_json::json!([[8, 9]]));
+    assert_eq!(report["miners"][0]["matched_blocks"], 3);
+    assert_eq!(report["miners"][0]["share_percent"], 37.5);
+
+    let err = uview_miner_stats_lib::run(
+        &config,
+        &RunOptions {
+            force_refetch: true,
+            ..run_options()
+        },
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("cache_readonly"), "{err}");
+    assert_eq!(fs::read(&cache_path).unwrap(), cached);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn cache_shard_converts_the_single_file_cache() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("cache-shard");
+    let config = write_config(&dir, &server.url());
+    run(&config);
+    let plain = fs::read_to_string(&config).unwrap();
+    fs::write(
+        &config,
+        format!(
+            "{plain}cache_shard_size = 4
+"
+        ),
+    )
+    .unwrap();
+
+    uview_miner_stats_lib::sharded_cache::run(&config).unwrap();
+
+    for shard in [
+        "cache-0000000-0000003.json",
+        "cache-0000004-0000007.json",
+        "cache-0000008-0000011.json",
+    ] {
+        assert!(dir.join(shard).exists(), "{shard}");
+    }
+    let sharded = ShardedFileCache::new(
+        dir.join("cache.json"),
+        4,
+        0,
+        false,
+        CacheFormat::Json,
+        false,
+    );
+    assert_eq!(
+        sharded.load().unwrap().0.blocks.len(),
+        BlockCache::load(&dir.join("cache.json"))
+            .unwrap()
+            .0
+            .blocks
+            .len()
+    );
+    // Runs now read the shards; the original file is no longer needed.
+    fs::remove_file(dir.join("cache.json")).unwrap();
+    run(&config);
+    assert!(!dir.join("cache.json").exists());
+    let report = read_json(&dir.join("report.json"));
+    let snapshot = read_json(Path::new("tests/fixtures/regtest-report.json"));
+    assert_eq!(report["miners"], snapshot["miners"]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn summary_only_leaves_out_the_matched_blocks() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("summary-only");
+    run_with(
+        &write_config(&dir, &server.url()),
+        &RunOptions {
+            summary_only: true,
+            ..run_options()
+        },
+    );
+
+    let report = read_json(&dir.join("report.json"));
+    let fields: Vec<&str> = report
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    assert_eq!(
+        fields,
+        [
+            "start_height",
+            "end_height",
+            "total_mined_blocks",
+            "miners",
+            "unmatched"
+        ]
+    );
+    let snapshot = read_json(Path::new("tests/fixtures/regtest-report.json"));
+    assert_eq!(report["miners"], snapshot["miners"]);
+    assert_eq!(report["unmatched"]["blocks"], 4);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn human_values_format_the_json_amounts() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("human-values");
+    run_with(
+        &write_config(&dir, &server.url()),
+        &RunOptions {
+            human_values: true,
+            ..run_options()
+        },
+    );
+
+    let report = read_json(&dir.join("report.json"));
+    assert_eq!(report["total_value_wec"], "62.50 WEC");
+    assert_eq!(report["miners"][0]["total_value_wec"], "25.00 WEC");
+    assert_eq!(report["miners"][0]["total_fees_wec"], "0 zat");
+    assert_eq!(report["miners"][0]["total_value_zat"], 2_500_000_000i64);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn influx_output_accumulates_across_runs() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("influx");
+    let config = write_config(&dir, &server.url());
+    let options = RunOptions {
+        influx_output: Some(dir.join("stats.influx")),
+        ..run_options()
+    };
+    run_with(&config, &options);
+
+    let influx = fs::read_to_string(dir.join("stats.influx")).unwrap();
+    let lines: Vec<&str> = influx.lines().collect();
+    assert_eq!(
+        lines[0],
+        "miner_stats,label=Alpha matched_blocks=4i,value_zat=2500000000i,share_percent=40 \
+         1700000675000000000"
+    );
+    assert_eq!(
+        lines[2],
+        "miner_block,label=Alpha,height=1 value_zat=625000000i 1700000075000000000"
+    );
+    assert_eq!(lines.len(), 2 + 6);
+
+    run_with(&config, &options);
+    let appended = fs::read_to_string(dir.join("stats.influx")).unwrap();
+    assert_eq!(appended, influx.repeat(2));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn es_output_pairs_an_index_action_with_each_block() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("es-bulk");
+    run_with(
+        &write_config(&dir, &server.url()),
+        &RunOptions {
+            es_output: Some(dir.join("bulk.ndjson")),
+            es_index: "zcash-miners".to_string(),
+            ..run_options()
+        },
+    );
+
+    let bulk = fs::read_to_string(dir.join("bulk.ndjson")).unwrap();
+    let lines: Vec<Value> = bulk
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2 * 6);
+    assert_eq!(
+        lines[0],
+        serde_json::json!({ "index": { "_index": "zcash-miners", "_id": "Alpha:1" } })
+    );
+    assert_eq!(lines[1]["miner_label"], "Alpha");
+    assert_eq!(lines[1]["block_height"], 1);
+    assert_eq!(lines[1]["value_zat"], BLOCK_VALUE_ZAT);
+    assert_eq!(lines[1]["block_datetime"], "2023-11-14T22:14:35Z");
+    assert_eq!(lines[11]["miner_label"], "Beta");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn timezone_applies_to_every_datetime() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("timezone");
+    run_with(
+        &write_config(&dir, &server.url()),
+        &RunOptions {
+            timezone: "America/New_York".parse().unwrap(),
+            ..run_options()
+        },
+    );
+
+    let report = read_json(&dir.join("report.json"));
+    assert_eq!(
+        report["time_range"]["start_datetime"],
+        "2023-11-14T17:13:20-05:00"
+    );
+    assert_eq!(
+        report["detailed_miners"][0]["detailed_blocks"][0]["block_datetime"],
+        "2023-11-14T17:14:35-05:00"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fiat_values_use_the_rate_fetched_at_startup() {
+    let mut prices = mockito::Server::new();
+    let price = prices
+        .mock("GET", "/simple/price")
+        .match_query(mockito::Matcher::UrlEncoded(
+            "vs_currencies".into(),
+            "usd".into(),
+        ))
+        .with_body(r#"{"w-cash": {"usd": 0.5}}"#)
+        .create();
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("fiat");
+    let config = write_config(&dir, &server.url());
+    let plain = fs::read_to_string(&config).unwrap();
+    fs::write(
+        &config,
+        format!(
+            "{plain}fiat_currency = \"USD\"\n\
+             price_api_url = \"{}/simple/price?ids=w-cash&vs_currencies={{currency}}\"\n",
+            prices.url()
+        ),
+    )
+    .unwrap();
+    run(&config);
+
+    price.assert();
+    let report = read_json(&dir.join("report.json"));
+    assert_eq!(report["fiat_rate"], 0.5);
+    assert_eq!(report["miners"][0]["total_value_fiat"], 12.5);
+    assert_eq!(report["miners"][0]["fiat_currency"], "USD");
+
+    // An unreachable price service only costs the fiat figures.
+    fs::write(
+        &config,
+        format!("{plain}fiat_currency = \"USD\"\nprice_api_url = \"http://127.0.0.1:9/\"\n"),
+    )
+    .unwrap();
+    run(&config);
+    let report = read_json(&dir.join("report.json"));
+    assert!(report.get("fiat_rate").is_none());
+    assert!(report["miners"][0].get("total_value_fiat").is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn webhook_posts_blocks_found_while_watching() {
+    use hmac::{Hmac, Mac};
+    use std::sync::{Arc, Mutex, atomic::Ordering};
+
+    let mut receiver = mockito::Server::new();
+    let posts = Arc::new(Mutex::new(Vec::new()));
+    let recorded = posts.clone();
+    let _hook = receiver
+        .mock("POST", "/hook")
+        .with_body_from_request(move |request| {
+            let body = request.body().unwrap().clone();
+            let signature = request.header("x-signature-256")[0]
+                .to_str()
+                .unwrap()
+                .to_string();
+            recorded.lock().unwrap().push((body, signature));
+            Vec::new()
+        })
+        .create();
+    let server = MockZcashdServer::start(regtest_chain()[..5].to_vec());
+    let dir = work_dir("webhook");
+    let config = write_config(&dir, &server.url());
+    let toml = fs::read_to_string(&config).unwrap()
+        + &format!(
+            "webhook_url = \"{}/hook\"\nwebhook_secret = \"s3cret\"\n",
+            receiver.url()
+        );
+    fs::write(&config, toml).unwrap();
+    let options = RunOptions {
+        watch: Some(std::time::Duration::from_millis(50)),
+        ..run_options()
+    };
+    let interrupt = options.interrupt.clone();
+    let watcher = std::thread::spawn(move || uview_miner_stats_lib::run(&config, &options));
+
+    let report_path = dir.join("report.json");
+    let wait_for_tip = |tip: u64| {
+        while !fs::read(&report_path)
+            .ok()
+            .and_then(|raw| serde_json::from_slice::<Value>(&raw).ok())
+            .is_some_and(|report| report["end_height"] == tip)
+        {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+    };
+    wait_for_tip(4);
+    server.set_chain(regtest_chain());
+    wait_for_tip(9);
+    interrupt.store(true, Ordering::SeqCst);
+    assert!(watcher.join().unwrap().is_err());
+
+    // Blocks 0-4 were there before watching started; of the rest, Alpha mined 5 and 8
+    // and Beta 6.
+    let posts = posts.lock().unwrap();
+    let mut heights: Vec<(String, u64)> = posts
+        .iter()
+        .map(|(body, _)| {
+            let body: Value = serde_json::from_slice(body).unwrap();
+            assert_eq!(body["value_zat"], BLOCK_VALUE_ZAT);
+            (
+                body["miner_label"].as_str().unwrap().to_string(),
+                body["block_height"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    heights.sort();
+    assert_eq!(
+        heights,
+        [
+            ("Alpha".to_string(), 5),
+            ("Alpha".to_string(), 8),
+            ("Beta".to_string(), 6)
+        ]
+    );
+    for (body, signature) in posts.iter() {
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"s3cret").unwrap();
+        mac.update(body);
+        let expected = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        assert_eq!(*signature, expected);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn multi_key_miner_combines_matches_of_all_keys() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("multi-key");
+    let config = write_config_with_miners(
+        &dir,
+        &server.url(),
+        &format!("{{ label = \"Pool\", keys = [\"{ALPHA_UFVK}\", \"{BETA_UFVK}\"] }},\n"),
+    );
+    run(&config);
+
+    let report = read_json(&dir.join("report.json"));
+    assert_eq!(report["miners"][0]["label"], "Pool");
+    assert_eq!(report["miners"][0]["matched_blocks"], 6);
+    assert_eq!(report["miners"][0]["total_value_zat"], 6 * BLOCK_VALUE_ZAT);
+    let hints: Vec<&str> = report["detailed_miners"][0]["detailed_blocks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|block| block["matched_key_hint"].as_str().unwrap())
+        .collect();
+    assert_eq!(hints.len(), 6);
+    assert!(hints.iter().all(|hint| !hint.is_empty()));
+    assert_eq!(
+        hints
+            .iter()
+            .collect::<std::collections::BTreeSet<_>>()
+            .len(),
+        2
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn miner_height_range_limits_credit_but_not_the_share_base() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("miner-range");
+    let config = write_config_with_miners(
+        &dir,
+        &server.url(),
+        &format!(
+            "{{ label = \"Alpha\", key = \"{ALPHA_UFVK}\", start_height = 3, end_height = 7 }},\n"
+        ),
+    );
+    run(&config);
+
+    let report = read_json(&dir.join("report.json"));
+    // Alpha is paid at heights 1, 2, 5 and 8; only 5 falls inside its range.
+    assert_eq!(report["miners"][0]["matched_blocks"], 1);
+    assert_eq!(report["miners"][0]["share_percent"], 10.0);
+    assert_eq!(
+        report["detailed_miners"][0]["detailed_blocks"][0]["block_height"],
+        5
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Adds `address_map_file` to `config`, mapping the payout addresses at `heights` to `label`.
+fn write_address_map(dir: &Path, config: &Path, label: &str, heights: &[usize]) {
+    let chain = regtest_chain();
+    let map: serde_json::Map<String, Value> = heights
+        .iter()
+        .map(|h| (chain[*h].payout_address.clone(), label.into()))
+        .collect();
+    let map_path = dir.join("address-map.json");
+    fs::write(&map_path, Value::Object(map).to_string()).unwrap();
+    let mut raw = fs::read_to_string(config).unwrap();
+    raw.push_str(&format!("address_map_file = \"{}\"\n", map_path.display()));
+    fs::write(config, raw).unwrap();
+}
+
+#[test]
+fn address_map_miners_are_reported_alongside_ufvk_miners() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("address-map");
+    let config = write_config_with_miners(
+        &dir,
+        &server.url(),
+        &format!("{{ label = \"Alpha\", key = \"{ALPHA_UFVK}\" }},\n"),
+    );
+    // Beta's payouts, credited through the map instead of its key.
+    write_address_map(&dir, &config, "Mapped", &[3, 6]);
+    run(&config);
+
+    let report = read_json(&dir.join("report.json"));
+    let labels: Vec<&str> = report["miners"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["label"].as_str().unwrap())
+        .collect();
+    assert_eq!(labels, ["Alpha", "Mapped"]);
+    assert_eq!(report["miners"][1]["matched_blocks"], 2);
+    assert_eq!(report["miners"][1]["total_value_zat"], 2 * BLOCK_VALUE_ZAT);
+    let mapped = report["detailed_miners"][1]["detailed_blocks"]
+        .as_array()
+        .unwrap();
+    let heights: Vec<u64> = mapped
+        .iter()
+        .map(|b| b["block_height"].as_u64().unwrap())
+        .collect();
+    assert_eq!(heights, [3, 6]);
+    assert!(
+        mapped
+            .iter()
+            .all(|b| b["matched_key_hint"] == "address map")
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn address_map_entry_derived_from_a_ufvk_is_rejected() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("address-map-conflict");
+    let config = write_config(&dir, &server.url());
+    // Alpha's key derives the payout address at height 2.
+    write_address_map(&dir, &config, "Mapped", &[2]);
+    let err = uview_miner_stats_lib::run(&config, &run_options()).unwrap_err();
+
+    assert!(err.to_string().contains("address_map_file"), "{err}");
+    assert!(err.to_string().contains("'Alpha'"), "{err}");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn duplicate_labels_are_listed_in_the_report() {
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("duplicate-labels");
+    let config = write_config_with_miners(
+        &dir,
+        &server.url(),
+        &format!(
+            "{{ label = \"Pool\", key = \"{ALPHA_UFVK}\" }},\n\
+             {{ label = \"Pool\", key = \"{BETA_UFVK}\" }},\n"
+        ),
+    );
+    run(&config);
+
+    let report = read_json(&dir.join("report.json"));
+    let warnings = report["warnings"].as_array().unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].as_str().unwrap().contains("'Pool'"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn statsd_metrics_are_sent_when_configured() {
+    let statsd = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    statsd
+        .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .unwrap();
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("statsd");
+    let config = write_config(&dir, &server.url());
+    let mut toml = fs::read_to_string(&config).unwrap();
+    toml.push_str(&format!(
+        "statsd_addr = \"{}\"\nstatsd_prefix = \"test\"\n",
+        statsd.local_addr().unwrap()
+    ));
+    fs::write(&config, toml).unwrap();
+    run(&config);
+
+    let mut received = Vec::new();
+    let mut buf = [0; 512];
+    while let Ok(len) = statsd.recv(&mut buf) {
+        let datagram = String::from_utf8_lossy(&buf[..len]).into_owned();
+        let done = datagram.contains("label:Beta");
+        received.push(datagram);
+        if done {
+            break;
+        }
+    }
+    assert!(
+        received
+            .iter()
+            .any(|d| d.starts_with("test.blocks_fetched:10|g")),
+        "{received:?}"
+    );
+    assert!(
+        received
+            .iter()
+            .any(|d| d.starts_with("test.rpc_duration_ms:")),
+        "{received:?}"
+    );
+    assert!(
+        received.contains(&"test.miner_blocks:4|c|#label:Alpha".to_string()),
+        "{received:?}"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn systemd_is_told_about_progress_and_readiness() {
+    use std::os::unix::net::UnixDatagram;
+
+    let server = MockZcashdServer::start(regtest_chain());
+    let dir = work_dir("systemd");
+    let socket_path = dir.join("notify.sock");
+    let socket = UnixDatagram::bind(&socket_path).unwrap();
+    socket
+        .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .unwrap();
+    // SAFETY: no other test in this binary reads or writes NOTIFY_SOCKET.
+    unsafe { std::env::set_var("NOTIFY_SOCKET", &socket_path) };
+    let config = write_config(&dir, &server.url());
+    let mut toml = fs::read_to_string(&config).unwrap();
+    toml.push_str("systemd = true\n");
+    fs::write(&config, toml).unwrap();
+    run(&config);
+
+    let mut received = Vec::new();
+    let mut buf = [0; 512];
+    while let Ok(len) = socket.recv(&mut buf) {
+        let message = String::from_utf8_lossy(&buf[..len]).into_owned();
+        let done = message.starts_with("READY=1");
+        received.push(message);
+        if done {
+            break;
+        }
+    }
+    assert!(
+        received.contains(&"STATUS=Fetching 10 blocks".to_string()),
+        "{received:?}"
+    );
+    assert_eq!(
+        received.last().map(String::as_str),
+        Some("READY=1\nSTATUS=Up to date at height 9")
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn merged_reports_of_disjoint_ranges_match_the_whole_range() {
+    let chain = regtest_chain();
+    let tip = chain.len() as u64 - 1;
+    let early = report_for("merge-early", chain[..5].to_vec(), 0);
+    let late = report_for("merge-late", chain.clone(), 5);
+    let whole = read_json(&report_for("merge-whole", chain, 0));
+    let output = work_dir("merge-output").join("merged.json");
+
+    merge::run(&[late.clone(), early.clone()], &output).unwrap();
+
+    let merged = read_json(&output);
+    let (early, late) = (read_json(&early), read_json(&late));
+    assert_eq!(merged["start_height"], 0);
+    assert_eq!(merged["end_height"], tip);
+    assert_eq!(merged["total_mined_blocks"], whole["total_mined_blocks"]);
+    for (index, miner) in merged["miners"].as_array().unwrap().iter().enumerate() {
+        let parts = [&early["miners"][index], &late["miners"][index]];
+        assert_eq!(miner["label"], whole["miners"][index]["label"]);
+        for field in ["matched_blocks", "total_value_zat"] {
+            let sum: u64 = parts.iter().map(|part| part[field].as_u64().unwrap()).sum();
+            assert_eq!(miner[field], sum, "{field}");
+            assert_eq!(miner[field], whole["miners"][index][field], "{field}");
+        }
+        // Recomputed over the merged range rather than taken from either report.
+        assert_eq!(
+            miner["share_percent"],
+            whole["miners"][index]["share_percent"]
+        );
+    }
+    for name in ["merge-early", "merge-late", "merge-whole", "merge-output"] {
+        fs::remove_dir_all(work_dir(name)).unwrap();
+    }
+}
+
+#[test]
+fn merging_overlapping_ranges_names_both() {
+    let chain = regtest_chain();
+    let tip = chain.len() as u64 - 1;
+    let early = report_for("merge-overlap-early", chain[..5].to_vec(), 0);
+    let late = report_for("merge-overlap-late", chain, 3);
+    let output = work_dir("merge-overlap-output").join("merged.json");
+
+    let err = merge::run(&[late, early], &output).unwrap_err();
+
+    assert!(
+        matches!(err, Error::ReportsOverlap { first: [0, 4], second: [3, end] } if end == tip),
+        "{err}"
+    );
+    assert!(
+        err.to_string().contains(&format!("0-4 and 3-{tip}")),
+        "{err}"
+    );
+    assert!(!output.exists());
+    for name in [
+        "merge-overlap-early",
+        "merge-overlap-late",
+        "merge-overlap-output",
+    ] {
+        fs::remove_dir_all(work_dir(name)).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+//! Properties of the amount and share helpers over their whole input range.
+
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+use uview_miner_stats_lib::{percent_share_blocks, zats_to_wec};
+
+#[test]
+fn zats_to_wec_edge_cases() {
+    assert_eq!(zats_to_wec(0), Decimal::ZERO);
+    assert_eq!(zats_to_wec(100_000_000), Decimal::ONE);
+    assert_eq!(
+        zats_to_wec(i64::MAX),
+        "92233720368.55".parse::<Decimal>().unwrap()
+    );
+    assert_eq!(
+        zats_to_wec(i64::MIN),
+        "-92233720368.55".parse::<Decimal>().unwrap()
+    );
+}
+
+#[test]
+fn percent_share_blocks_edge_cases() {
+    assert_eq!(percent_share_blocks(0, 0), 0.0);
+    assert_eq!(percent_share_blocks(5, 0), 0.0);
+    assert_eq!(percent_share_blocks(0, 7), 0.0);
+    assert_eq!(percent_share_blocks(3, 2), 150.0);
+}
+
+proptest! {
+    // `-x` of every `i64` but `i64::MIN`, which has no positive counterpart.
+    #[test]
+    fn zats_to_wec_is_symmetric(x in (i64::MIN + 1)..=i64::MAX) {
+        prop_assert_eq!(
+            zats_to_wec(x).abs(),
+            zats_to_wec(-x).abs(),
+            "zats_to_wec({}) and zats_to_wec({}) differ in magnitude",
+            x,
+            -x
+        );
+        prop_assert_eq!(zats_to_wec(x), -zats_to_wec(-x), "x = {}", x);
+    }
+
+    #[test]
+    fn zats_to_wec_of_non_negative_is_its_own_abs(x in 0..=i64::MAX) {
+        prop_assert_eq!(zats_to_wec(x), zats_to_wec(-x).abs(), "x = {}", x);
+    }
+
+    #[test]
+    fn whole_is_a_hundred_percent(x in 1..=u32::MAX) {
+        prop_assert_eq!(percent_share_blocks(x, x), 100.0, "x = {}", x);
+    }
+
+    #[test]
+    fn nothing_is_zero_percent(x in any::<u32>()) {
+        prop_assert_eq!(percent_share_blocks(0, x), 0.0, "total = {}", x);
+    }
+
+    #[test]
+    fn part_of_the_total_is_between_zero_and_a_hundred(
+        (part, total) in any::<u32>().prop_flat_map(|total| (0..=total, Just(total)))
+    ) {
+        let share = percent_share_blocks(part, total);
+        prop_assert!(
+            (0.0..=100.0).contains(&share),
+            "percent_share_blocks({}, {}) = {}",
+            part,
+            total,
+            share
+        );
+    }
+}
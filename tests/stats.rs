@@ -0,0 +1,679 @@
+//! `compute_statistics` over hand-built caches.
+
+mod fixtures;
+
+use fixtures::{ALPHA_UFVK, BETA_UFVK};
+use std::path::{Path, PathBuf};
+use uview_miner_stats_lib::{
+    AddressScope, BlockCache, CachedBlock, ChainType, CoinbaseOutput, DerivedAddressCache, Error,
+    MinerStatsConfig, MinerStatsReport, Tz, block_subsidy, compute_statistics,
+    compute_statistics_with_addresses,
+};
+use zingolib::config::chain_from_str;
+
+/// The same synthetic key material (transparent, Sapling and Orchard components) encoded
+/// for each network, as (chain, UFVK, unified address prefix).
+#[cfg(feature = "orchard")]
+const ORCHARD_UFVKS: [(&str, &str, &str); 3] = [
+    (
+        "regtest",
+        "uviewregtest1d9urqkz8k2tdx2hzsj7zy3ggryddgcvmrr5406wmer90ajlmlll666zzutfttp00ha0x3nnzwut28323tfxnmwvqrqfyrkpw0464s46732s064tpcfkyxurcdtqjfu0as98z443ywslu2e3ljqnja2m6g6h6fx0ev9d49ehyj99t8ahqcemamcrtp4ez9c3j5z86fhyh4ay67j9tgle49wjt4ntjlwq5k87sxr322uucylsc32f7l5x7nx2t4yk98jc05uxk6w5jyyuv8u908xz668u4xa0utntur6glqxj633z87wwcy48f6r0zmhrfgwchyatvhpqfar02jgj9s28wj9lzjp0kswkhnz3w34dgk8ycmf5effu7hxjgnfnqu23d98t6fh2cay9hk5fngk9gsrqhw703jzepg83y5rt7ptqmkyfklldnc6vxtes68n3kmcq4q9xwcmjjul3eg5zjtgwtj7j2mkpap8fyca8dfm6rnc4l6lsg",
+        "uregtest1",
+    ),
+    (
+        "mainnet",
+        "uview1vg6kzt23a88czfs654ue3naunj4z8y9vvnwf8xn38geayaf2e9x7z0u5m0wkjx8fur4dpu8y286um62nn5jznsdyr04ygraz9wfzggns85mjd2n67escccm7425mw4u7ecuk4gkn324lg6r6qe5856ezemgfhmlft26m3dkj203zgjs9kuskmgs2j8gp3pkactykl844690dmxxy8rhsfq63feq3m0aqrhsjw82nt05tk5r9e34qgzd7cwtdyr25qpkt73pqavsed85tk8g2gu8g77au0fx549zahe446txgt3x4vxxz5hn00a85zvhzywczsl6gnsqv9kn8pmzkk2ssnrvln0dtfeduh64q600fly67dwh05akdrtt48e4qzkqh23g6mje3ylfft94d97hxmucnx0a5n3cppl4c30663509g5ymq5ycjyh3s3uz97qqtpdzz7wvtxkmtz9ag6r3tqvnq6wh7gxyxpun7yhuhz8lpycsu2fz",
+        "u1",
+    ),
+    (
+        "testnet",
+        "uviewtest1nxr5h0x4mdnttz3hr43hl85ucm2shqxsjg3hxz42xc0d2mpc85ngh6l62phyh2n5xs64rurde5aw87se08geedenkacnzqle8wg8jsrzdzkcyrey8vxske2uw5t09g95j79nj25tdhyy4m7r7c43555y4y6a4gsv24k2zr6z2j2eczvfw0yk4yvvdtu6g2fupgsjwnhddm2wwk0w9vv25a9a3qphaklrz89al6vycrd9dfa9hysmfmp2vpl6h0dum3rh3taq4pncy3gvg4y5glcsmpm7mdykl6j2xmdytd978ka52fjhyqav5k0fdf8rg057yggx7j7af4ea79slw7aqd7uptrkhstnluh0nuknv4rx2ue393fcss9auxck7f98esw92wgglxh5pflszdddxndzmekss8u6zg39wp4d0adw4npytm8ghf439jxn0dkuewdpgzpn2cs4qufjm6j7sdjyqjswfkqjk5sghncfmnexeyggy749w",
+        "utest1",
+    ),
+];
+
+/// Transparent address none of the configured miners derives.
+const UNKNOWN: &str = "tmUnknownMinerAddress";
+
+fn work_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "uview-miner-stats-stats-{}-{name}",
+        std::process::id()
+    ))
+}
+
+fn config(dir: &Path) -> MinerStatsConfig {
+    config_for(dir, chain_from_str("regtest").unwrap(), ALPHA_UFVK)
+}
+
+fn config_for(dir: &Path, chain: ChainType, ufvk: &str) -> MinerStatsConfig {
+    MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain)
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ufvk)
+        .cache_file(dir.join("stats-cache.json"))
+        .output_file(dir.join("miner-stats.json"))
+        .build()
+        .unwrap()
+}
+
+/// One block per entry, from height 0 up, with a single coinbase output.
+fn cache_paying(payouts: &[(i64, &str)]) -> BlockCache {
+    let mut cache = BlockCache::default();
+    for (height, (value, address)) in payouts.iter().enumerate() {
+        let height = height as u64;
+        cache.blocks.insert(
+            height,
+            CachedBlock {
+                height,
+                hash: format!("{height:064x}"),
+                time: 1_700_000_000 + height as u32 * 75,
+                outputs: vec![CoinbaseOutput {
+                    value_zat: *value,
+                    addresses: vec![address.to_string()],
+                    hex: None,
+                }],
+                subsidy_zat: None,
+            },
+        );
+    }
+    cache
+}
+
+#[test]
+fn value_totals_that_overflow_i64_are_an_error() {
+    let dir = work_dir("overflow");
+    let cfg = config(&dir);
+    let big = i64::MAX / 2 + 1;
+    let cache = cache_paying(&[(big, UNKNOWN), (big, UNKNOWN)]);
+
+    let err = compute_statistics(&cfg, &cache, 1).err().unwrap();
+    assert!(matches!(err, Error::ValueOverflow { .. }), "{err}");
+
+    let fits = cache_paying(&[(i64::MAX / 2, UNKNOWN), (i64::MAX / 2, UNKNOWN)]);
+    let report = compute_statistics(&cfg, &fits, 1).unwrap();
+    assert_eq!(report.total_value_zat, i64::MAX - 1);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn derived_addresses_are_cached_and_reused() {
+    let dir = work_dir("address-cache");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("derived-addresses.json");
+    let cfg = config(&dir);
+    let cache = cache_paying(&[(625_000_000, UNKNOWN); 3]);
+
+    let mut addresses = DerivedAddressCache::load(&path).unwrap();
+    assert!(addresses.is_empty());
+    compute_statistics_with_addresses(&cfg, &cache, 2, &mut addresses).unwrap();
+    addresses.save(&path).unwrap();
+    let loaded = DerivedAddressCache::load(&path).unwrap();
+    assert_eq!(loaded.len(), 3);
+    assert_eq!(loaded.get(ALPHA_UFVK, 1), addresses.get(ALPHA_UFVK, 1));
+
+    // A cached address is trusted as is, so planting one shows it was not derived again.
+    let mut planted = DerivedAddressCache::default();
+    planted.insert(ALPHA_UFVK, 1, "tmPlantedAddress".to_string());
+    let cache = cache_paying(&[(625_000_000, UNKNOWN), (625_000_000, "tmPlantedAddress")]);
+    let report = compute_statistics_with_addresses(&cfg, &cache, 1, &mut planted).unwrap();
+    assert_eq!(report.miners[0].matched_blocks, 1);
+    assert_eq!(planted.len(), 2);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn payouts_to_the_internal_scope_are_matched_when_configured() {
+    use pepper_sync::keys::transparent::{self, TransparentScope};
+    use zcash_primitives::legacy::keys::NonHardenedChildIndex;
+    use zingolib::wallet::keys::unified::UnifiedKeyStore;
+
+    let dir = work_dir("internal-scope");
+    let chain = chain_from_str("regtest").unwrap();
+    let key_store = UnifiedKeyStore::new_from_ufvk(&chain, ALPHA_UFVK.to_string()).unwrap();
+    let index = NonHardenedChildIndex::from_index(1).unwrap();
+    let change = transparent::encode_address(
+        &chain,
+        key_store
+            .generate_transparent_address(index, TransparentScope::Internal)
+            .unwrap(),
+    );
+    let cache = cache_paying(&[(625_000_000, UNKNOWN), (625_000_000, &change)]);
+
+    let report = compute_statistics(&config(&dir), &cache, 1).unwrap();
+    assert_eq!(report.miners[0].matched_blocks, 0);
+
+    let cfg = MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain)
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ALPHA_UFVK)
+        .transparent_scopes([AddressScope::External, AddressScope::Internal])
+        .cache_file(dir.join("stats-cache.json"))
+        .output_file(dir.join("miner-stats.json"))
+        .build()
+        .unwrap();
+    let mut addresses = DerivedAddressCache::default();
+    let report = compute_statistics_with_addresses(&cfg, &cache, 1, &mut addresses).unwrap();
+    assert_eq!(report.miners[0].matched_blocks, 1);
+    assert_eq!(report.miners[0].total_value_zat, 625_000_000);
+    let block = &report.detailed_miners[0].detailed_blocks[0];
+    assert_eq!(block.payout_address, change);
+    assert_eq!(block.scope, AddressScope::Internal);
+    // Only external addresses are cached.
+    assert_eq!(addresses.len(), 2);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn blocks_per_day_of_a_legacy_cache_assumes_75_second_blocks() {
+    let dir = work_dir("legacy-rate");
+    let cfg = config(&dir);
+    // 1152 block intervals of 75 s make one day; Alpha mines the even heights.
+    let tip = 1152;
+    let mut addresses = DerivedAddressCache::default();
+    let mut cache = BlockCache::default();
+    for height in 0..=tip {
+        addresses.insert(ALPHA_UFVK, height, format!("tmAlpha{height}"));
+        let payee = if height % 2 == 0 {
+            format!("tmAlpha{height}")
+        } else {
+            UNKNOWN.to_string()
+        };
+        cache.blocks.insert(
+            height,
+            CachedBlock {
+                height,
+                hash: format!("{height:064x}"),
+                time: 0,
+                outputs: vec![CoinbaseOutput {
+                    value_zat: 625_000_000,
+                    addresses: vec![payee],
+                    hex: None,
+                }],
+                subsidy_zat: None,
+            },
+        );
+    }
+
+    let report = compute_statistics_with_addresses(&cfg, &cache, tip, &mut addresses).unwrap();
+    assert_eq!(report.miners[0].matched_blocks, 577);
+    assert_eq!(report.miners[0].blocks_per_day, 577.0);
+    assert_eq!(report.unmatched.blocks_per_day, 576.0);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn moving_avg_share_slides_back_from_the_tip() {
+    let dir = work_dir("moving-avg");
+    let mut addresses = DerivedAddressCache::default();
+    for height in 0..10 {
+        addresses.insert(ALPHA_UFVK, height, format!("tmAlpha{height}"));
+    }
+    // Alpha mines 0-3 and 8-9.
+    let payees: Vec<String> = (0..10)
+        .map(|height| match height {
+            0..=3 | 8 | 9 => format!("tmAlpha{height}"),
+            _ => UNKNOWN.to_string(),
+        })
+        .collect();
+    let payouts: Vec<(i64, &str)> = payees.iter().map(|p| (625_000_000, p.as_str())).collect();
+    let cache = cache_paying(&payouts);
+    let mut shares = |cfg: &MinerStatsConfig| {
+        let report = compute_statistics_with_addresses(cfg, &cache, 9, &mut addresses).unwrap();
+        report.detailed_miners[0]
+            .moving_avg_share
+            .iter()
+            .map(|w| (w.window_end_height, w.window_size, w.share_percent))
+            .collect::<Vec<_>>()
+    };
+
+    let builder = || {
+        MinerStatsConfig::builder()
+            .start_height(0)
+            .chain(chain_from_str("regtest").unwrap())
+            .rpc_url("http://127.0.0.1:18232")
+            .add_miner("Alpha", ALPHA_UFVK)
+            .cache_file(dir.join("stats-cache.json"))
+            .output_file(dir.join("miner-stats.json"))
+    };
+    assert!(shares(&builder().build().unwrap()).is_empty());
+    assert_eq!(
+        shares(&builder().moving_avg(4, 3).build().unwrap()),
+        [(3, 4, 100.0), (6, 4, 25.0), (9, 4, 50.0)]
+    );
+    assert_eq!(
+        shares(&builder().moving_avg(4, 3).max_windows(2).build().unwrap()),
+        [(6, 4, 25.0), (9, 4, 50.0)]
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn blocks_two_sigma_from_the_mean_are_outliers() {
+    let dir = work_dir("outliers");
+    let cfg = config(&dir);
+    let mut payouts = vec![(625_000_000, UNKNOWN); 10];
+    payouts[6].0 = 5_000_000_000;
+    let report = compute_statistics(&cfg, &cache_paying(&payouts), 9).unwrap();
+
+    let outliers: Vec<(u64, i64, f64)> = report
+        .outlier_blocks
+        .iter()
+        .map(|b| (b.height, b.value_zat, b.z_score))
+        .collect();
+    assert_eq!(outliers, [(6, 5_000_000_000, 3.0)]);
+
+    let uniform = compute_statistics(&cfg, &cache_paying(&[(625_000_000, UNKNOWN); 10]), 9);
+    assert!(uniform.unwrap().outlier_blocks.is_empty());
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn top_blocks_are_the_highest_coinbase_values_of_the_range() {
+    let dir = work_dir("top-blocks");
+    let cfg = MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain_from_str("regtest").unwrap())
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ALPHA_UFVK)
+        .cache_file(dir.join("stats-cache.json"))
+        .output_file(dir.join("miner-stats.json"))
+        .top_n_blocks(3)
+        .build()
+        .unwrap();
+    let mut addresses = DerivedAddressCache::default();
+    addresses.insert(ALPHA_UFVK, 4, "tmAlpha4".to_string());
+    let mut payouts = vec![(625_000_000, UNKNOWN); 6];
+    payouts[2].0 = 700_000_000;
+    payouts[4] = (900_000_000, "tmAlpha4");
+    payouts[5].0 = 700_000_000;
+    let cache = cache_paying(&payouts);
+
+    let report = compute_statistics_with_addresses(&cfg, &cache, 5, &mut addresses).unwrap();
+    let top: Vec<(u64, i64, Option<&str>)> = report
+        .top_blocks
+        .iter()
+        .map(|b| (b.height, b.total_coinbase_zat, b.matched_miner.as_deref()))
+        .collect();
+    // Equal values keep the lower height first.
+    assert_eq!(
+        top,
+        [
+            (4, 900_000_000, Some("Alpha")),
+            (2, 700_000_000, None),
+            (5, 700_000_000, None),
+        ]
+    );
+    assert_eq!(report.top_blocks[0].hash, format!("{:064x}", 4));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn day_of_week_stats_follow_the_report_timezone() {
+    let dir = work_dir("day-stats");
+    let cfg = MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain_from_str("regtest").unwrap())
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ALPHA_UFVK)
+        .cache_file(dir.join("stats-cache.json"))
+        .output_file(dir.join("miner-stats.json"))
+        .include_day_stats(true)
+        .build()
+        .unwrap();
+    let mut addresses = DerivedAddressCache::default();
+    addresses.insert(ALPHA_UFVK, 1, "tmAlpha1".to_string());
+    addresses.insert(ALPHA_UFVK, 2, "tmAlpha2".to_string());
+    let mut cache = cache_paying(&[
+        (625_000_000, UNKNOWN),
+        (625_000_000, "tmAlpha1"),
+        (600_000_000, "tmAlpha2"),
+    ]);
+    // Height 1 is Tuesday 22:13 UTC; height 2 two hours later, past midnight UTC.
+    cache.blocks.get_mut(&2).unwrap().time = 1_700_000_000 + 2 * 3600;
+
+    let mut report = compute_statistics_with_addresses(&cfg, &cache, 2, &mut addresses).unwrap();
+    let counts = |report: &MinerStatsReport| -> Vec<(u32, i64)> {
+        report.detailed_miners[0]
+            .day_of_week_stats
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|day| (day.block_count, day.value_zat))
+            .collect()
+    };
+    let mut utc = vec![(0, 0); 7];
+    utc[1] = (1, 625_000_000);
+    utc[2] = (1, 600_000_000);
+    assert_eq!(counts(&report), utc);
+
+    // Both fall on Tuesday evening in New York.
+    report.set_timezone(Tz::America__New_York);
+    let mut new_york = vec![(0, 0); 7];
+    new_york[1] = (2, 1_225_000_000);
+    assert_eq!(counts(&report), new_york);
+
+    let plain = compute_statistics_with_addresses(&config(&dir), &cache, 2, &mut addresses);
+    assert!(
+        plain.unwrap().detailed_miners[0]
+            .day_of_week_stats
+            .is_none()
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn inactivity_periods_are_gaps_above_the_threshold() {
+    let dir = work_dir("inactivity");
+    let mut addresses = DerivedAddressCache::default();
+    for height in 0..10 {
+        addresses.insert(ALPHA_UFVK, height, format!("tmAlpha{height}"));
+    }
+    // Alpha mines 0, 1, 5 and 6, then nothing up to the tip at 9.
+    let payees: Vec<String> = (0..10)
+        .map(|height| match height {
+            0 | 1 | 5 | 6 => format!("tmAlpha{height}"),
+            _ => UNKNOWN.to_string(),
+        })
+        .collect();
+    let payouts: Vec<(i64, &str)> = payees.iter().map(|p| (625_000_000, p.as_str())).collect();
+    let cache = cache_paying(&payouts);
+    let mut periods = |threshold: u32| {
+        let cfg = MinerStatsConfig::builder()
+            .start_height(0)
+            .chain(chain_from_str("regtest").unwrap())
+            .rpc_url("http://127.0.0.1:18232")
+            .add_miner("Alpha", ALPHA_UFVK)
+            .cache_file(dir.join("stats-cache.json"))
+            .output_file(dir.join("miner-stats.json"))
+            .inactivity_threshold_blocks(threshold)
+            .build()
+            .unwrap();
+        let report = compute_statistics_with_addresses(&cfg, &cache, 9, &mut addresses).unwrap();
+        report.detailed_miners[0]
+            .inactivity_periods
+            .iter()
+            .map(|p| (p.from_height, p.to_height, p.duration_blocks))
+            .collect::<Vec<_>>()
+    };
+
+    assert!(periods(0).is_empty());
+    assert_eq!(periods(2), [(2, 4, 3), (7, 9, 3)]);
+    assert!(periods(3).is_empty());
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn value_histogram_spans_the_lowest_to_the_highest_coinbase() {
+    let dir = work_dir("histogram");
+    let mut addresses = DerivedAddressCache::default();
+    for height in 0..10 {
+        addresses.insert(ALPHA_UFVK, height, format!("tmAlpha{height}"));
+    }
+    // Coinbase values 100, 200, ..., 1000 zat; Alpha mines the lowest and the highest.
+    let payees: Vec<String> = (0..10)
+        .map(|height| match height {
+            0 | 9 => format!("tmAlpha{height}"),
+            _ => UNKNOWN.to_string(),
+        })
+        .collect();
+    let payouts: Vec<(i64, &str)> = payees
+        .iter()
+        .enumerate()
+        .map(|(height, p)| ((height as i64 + 1) * 100, p.as_str()))
+        .collect();
+    let cache = cache_paying(&payouts);
+    let builder = || {
+        MinerStatsConfig::builder()
+            .start_height(0)
+            .chain(chain_from_str("regtest").unwrap())
+            .rpc_url("http://127.0.0.1:18232")
+            .add_miner("Alpha", ALPHA_UFVK)
+            .cache_file(dir.join("stats-cache.json"))
+            .output_file(dir.join("miner-stats.json"))
+    };
+
+    let cfg = builder().build().unwrap();
+    let report = compute_statistics_with_addresses(&cfg, &cache, 9, &mut addresses).unwrap();
+    assert!(report.value_histogram.is_none());
+
+    let cfg = builder().include_histogram(true).build().unwrap();
+    let report = compute_statistics_with_addresses(&cfg, &cache, 9, &mut addresses).unwrap();
+    let buckets = report.value_histogram.unwrap();
+    let counts: Vec<u32> = buckets.iter().map(|b| b.block_count).collect();
+    assert_eq!(
+        counts,
+        [1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1]
+    );
+    assert_eq!((buckets[0].min_zat, buckets[0].max_zat), (100, 145));
+    assert_eq!((buckets[19].min_zat, buckets[19].max_zat), (955, 1000));
+
+    let alpha = report.detailed_miners[0].value_histogram.as_ref().unwrap();
+    let alpha_counts: Vec<u32> = alpha.iter().map(|b| b.block_count).collect();
+    assert_eq!(alpha_counts.iter().sum::<u32>(), 2);
+    assert_eq!((alpha_counts[0], alpha_counts[19]), (1, 1));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn streaks_count_consecutive_heights_per_miner() {
+    let dir = work_dir("streaks");
+    let cfg = MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain_from_str("regtest").unwrap())
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ALPHA_UFVK)
+        .add_miner("Beta", BETA_UFVK)
+        .cache_file(dir.join("stats-cache.json"))
+        .output_file(dir.join("miner-stats.json"))
+        .build()
+        .unwrap();
+    // Alpha: 0 1 2 _ 4 _ 6 7 8 9; Beta: _ _ _ _ 4 5 _ _ _ _. Height 4 is shared.
+    let payees = ["a", "a", "a", "x", "ab", "b", "a", "a", "a", "a"];
+    let mut addresses = DerivedAddressCache::default();
+    for height in 0..payees.len() as u64 {
+        addresses.insert(ALPHA_UFVK, height, format!("tmAlpha{height}"));
+        addresses.insert(BETA_UFVK, height, format!("tmBeta{height}"));
+    }
+    let mut cache = BlockCache::default();
+    for (height, payee) in payees.iter().enumerate() {
+        let height = height as u64;
+        let mut outputs = Vec::new();
+        for (miner, prefix) in [('a', "tmAlpha"), ('b', "tmBeta")] {
+            if payee.contains(miner) {
+                outputs.push(CoinbaseOutput {
+                    value_zat: 312_500_000,
+                    addresses: vec![format!("{prefix}{height}")],
+                    hex: None,
+                });
+            }
+        }
+        cache.blocks.insert(
+            height,
+            CachedBlock {
+                height,
+                hash: format!("{height:064x}"),
+                time: 1_700_000_000 + height as u32 * 75,
+                outputs,
+                subsidy_zat: None,
+            },
+        );
+    }
+
+    let report = compute_statistics_with_addresses(&cfg, &cache, 9, &mut addresses).unwrap();
+    let streaks: Vec<_> = report
+        .miners
+        .iter()
+        .map(|m| (m.label.as_str(), m.longest_streak, m.current_streak))
+        .collect();
+    assert_eq!(streaks, [("Alpha", 4, 4), ("Beta", 2, 0)]);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn subsidy_follows_the_slow_start_and_halvings() {
+    let mainnet = chain_from_str("mainnet").unwrap();
+    assert_eq!(block_subsidy(1, &mainnet), 62_500);
+    assert_eq!(block_subsidy(10_000, &mainnet), 625_062_500);
+    assert_eq!(block_subsidy(20_000, &mainnet), 1_250_000_000);
+    // Blossom halves the block interval and with it the subsidy.
+    assert_eq!(block_subsidy(653_600, &mainnet), 625_000_000);
+    assert_eq!(block_subsidy(1_046_399, &mainnet), 625_000_000);
+    assert_eq!(block_subsidy(1_046_400, &mainnet), 312_500_000);
+    assert_eq!(block_subsidy(2_726_400, &mainnet), 156_250_000);
+}
+
+#[test]
+fn coinbase_value_above_the_subsidy_is_reported_as_fees() {
+    let dir = work_dir("fees");
+    let cfg = config(&dir);
+    let mut addresses = DerivedAddressCache::default();
+    addresses.insert(ALPHA_UFVK, 0, "tmAlpha0".to_string());
+    addresses.insert(ALPHA_UFVK, 1, "tmAlpha1".to_string());
+    let mut cache = cache_paying(&[(625_010_000, "tmAlpha0"), (600_000_000, "tmAlpha1")]);
+    for block in cache.blocks.values_mut() {
+        block.subsidy_zat = Some(625_000_000);
+    }
+
+    let report = compute_statistics_with_addresses(&cfg, &cache, 1, &mut addresses).unwrap();
+    let fees: Vec<_> = report.detailed_miners[0]
+        .detailed_blocks
+        .iter()
+        .map(|b| b.fees_zat)
+        .collect();
+    // A coinbase claiming less than the subsidy has no fees rather than negative ones.
+    assert_eq!(fees, [10_000, 0]);
+    assert_eq!(report.miners[0].total_fees_zat, 10_000);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn matched_blocks_link_to_the_explorer_template() {
+    let dir = work_dir("explorer");
+    let cfg = MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain_from_str("regtest").unwrap())
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ALPHA_UFVK)
+        .cache_file(dir.join("stats-cache.json"))
+        .output_file(dir.join("miner-stats.json"))
+        .explorer_url_template("https://explorer.example/{height}/{hash}")
+        .build()
+        .unwrap();
+    let mut addresses = DerivedAddressCache::default();
+    addresses.insert(ALPHA_UFVK, 1, "tmAlpha1".to_string());
+    let cache = cache_paying(&[(625_000_000, UNKNOWN), (625_000_000, "tmAlpha1")]);
+
+    let report = compute_statistics_with_addresses(&cfg, &cache, 1, &mut addresses).unwrap();
+    assert_eq!(
+        report.detailed_miners[0].detailed_blocks[0]
+            .explorer_url
+            .as_deref(),
+        Some(format!("https://explorer.example/1/{:064x}", 1).as_str())
+    );
+    assert_eq!(
+        report.detailed_miners[0].detailed_blocks[0].derivation_index,
+        1
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn matched_blocks_carry_their_annotation() {
+    let dir = work_dir("annotations");
+    let cfg = MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain_from_str("regtest").unwrap())
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ALPHA_UFVK)
+        .cache_file(dir.join("stats-cache.json"))
+        .output_file(dir.join("miner-stats.json"))
+        .annotate_block(0, "genesis")
+        .annotate_block(2, "pool launch")
+        .build()
+        .unwrap();
+    let mut addresses = DerivedAddressCache::default();
+    addresses.insert(ALPHA_UFVK, 1, "tmAlpha1".to_string());
+    addresses.insert(ALPHA_UFVK, 2, "tmAlpha2".to_string());
+    let cache = cache_paying(&[
+        (625_000_000, UNKNOWN),
+        (625_000_000, "tmAlpha1"),
+        (625_000_000, "tmAlpha2"),
+    ]);
+
+    let report = compute_statistics_with_addresses(&cfg, &cache, 2, &mut addresses).unwrap();
+    let annotations: Vec<_> = report.detailed_miners[0]
+        .detailed_blocks
+        .iter()
+        .map(|b| (b.block_height, b.annotation.as_deref()))
+        .collect();
+    // Height 0 is annotated but not matched, so the note does not show up anywhere.
+    assert_eq!(annotations, [(1, None), (2, Some("pool launch"))]);
+
+    let csv_path = dir.join("details.csv");
+    report.write_detail_csv(&csv_path).unwrap();
+    let csv = std::fs::read_to_string(&csv_path).unwrap();
+    let mut lines = csv.lines();
+    assert!(lines.next().unwrap().ends_with(",annotation"));
+    assert!(lines.next().unwrap().ends_with(','));
+    assert!(lines.next().unwrap().ends_with(",pool launch"));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[cfg(feature = "sapling")]
+#[test]
+fn payouts_to_the_sapling_receiver_are_matched() {
+    use uview_miner_stats_lib::ReceiverType;
+    use zcash_keys::{address::Address, keys::UnifiedFullViewingKey};
+
+    let dir = work_dir("sapling");
+    let cfg = config(&dir);
+    let chain = chain_from_str("regtest").unwrap();
+    let ufvk = UnifiedFullViewingKey::decode(&chain, ALPHA_UFVK).unwrap();
+    let sapling = Address::Sapling(ufvk.sapling().unwrap().default_address().1).encode(&chain);
+    let cache = cache_paying(&[(625_000_000, UNKNOWN), (625_000_000, &sapling)]);
+
+    let report = compute_statistics(&cfg, &cache, 1).unwrap();
+    let blocks = &report.detailed_miners[0].detailed_blocks;
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].block_height, 1);
+    assert_eq!(blocks[0].payout_address, sapling);
+    assert_eq!(blocks[0].receiver_type, ReceiverType::Sapling);
+    assert_eq!(blocks[0].derivation_index, 0);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[cfg(feature = "orchard")]
+#[test]
+fn payouts_to_the_orchard_receiver_are_matched_on_every_network() {
+    use uview_miner_stats_lib::ReceiverType;
+    use zcash_keys::{
+        address::{Address, UnifiedAddress},
+        keys::UnifiedFullViewingKey,
+    };
+
+    for (network, ufvk, prefix) in ORCHARD_UFVKS {
+        let dir = work_dir(&format!("orchard-{network}"));
+        let chain = chain_from_str(network).unwrap();
+        let fvk = UnifiedFullViewingKey::decode(&chain, ufvk).unwrap();
+        let receiver = fvk
+            .orchard()
+            .unwrap()
+            .address_at(0u32, orchard::keys::Scope::External);
+        let unified = UnifiedAddress::from_receivers(Some(receiver), None, None).unwrap();
+        let orchard = Address::Unified(unified).encode(&chain);
+        assert!(orchard.starts_with(prefix), "{network}: {orchard}");
+        let cache = cache_paying(&[(625_000_000, &orchard), (625_000_000, UNKNOWN)]);
+        let cfg = config_for(&dir, chain, ufvk);
+
+        let report = compute_statistics(&cfg, &cache, 1).unwrap();
+        let blocks = &report.detailed_miners[0].detailed_blocks;
+        assert_eq!(blocks.len(), 1, "{network}");
+        assert_eq!(blocks[0].block_height, 0);
+        assert_eq!(blocks[0].receiver_type, ReceiverType::Orchard);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
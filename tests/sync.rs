@@ -0,0 +1,517 @@
+//! `sync_cache` and the `--watch` loop against an in-memory chain instead of a live node.
+
+mod fixtures;
+
+use fixtures::{ALPHA_UFVK, regtest_chain};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use uview_miner_stats_lib::{
+    BlockCache, CacheBackend, CacheFormat, CachedBlock, CoinbaseOutput, DEFAULT_ES_INDEX,
+    DEFAULT_LABEL_WIDTH, Error, JsonFileCache, MinerSortOrder, MinerStatsConfig, OutputFormat,
+    Result, RpcClient, RunOptions, SharedCache, Tz,
+    cache_repair::{RepairSummary, repair_cache},
+    compute_statistics,
+    metrics::Metrics,
+    run_with_client, sync_cache, sync_cache_with_checkpoints, sync_shared_cache,
+};
+use zingolib::config::chain_from_str;
+
+/// Serves blocks from a `BTreeMap`; heights listed in `failing` answer with an RPC error.
+struct MockRpcClient {
+    blocks: BTreeMap<u64, CachedBlock>,
+    failing: Vec<u64>,
+}
+
+impl MockRpcClient {
+    fn chain(tip: u64, fork: &str) -> Self {
+        Self {
+            blocks: (0..=tip).map(|h| (h, block(h, fork))).collect(),
+            failing: Vec::new(),
+        }
+    }
+}
+
+impl RpcClient for MockRpcClient {
+    fn block_count(&self) -> Result<u64> {
+        Ok(self.blocks.keys().next_back().copied().unwrap_or_default())
+    }
+
+    fn fetch_block(&self, height: u64) -> Result<CachedBlock> {
+        if self.failing.contains(&height) {
+            return Err(Error::RpcError {
+                method: "getblock".to_string(),
+                code: -1,
+                message: "connection reset".to_string(),
+            });
+        }
+        self.blocks
+            .get(&height)
+            .cloned()
+            .ok_or_else(|| Error::RpcError {
+                method: "getblockhash".to_string(),
+                code: -8,
+                message: "Block height out of range".to_string(),
+            })
+    }
+}
+
+/// Wraps [`MockRpcClient`] with a concurrency limit and records the most calls that were
+/// ever in flight at once.
+struct CountingRpcClient {
+    inner: MockRpcClient,
+    limit: usize,
+    in_flight: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl RpcClient for CountingRpcClient {
+    fn max_concurrent(&self) -> usize {
+        self.limit
+    }
+
+    fn block_count(&self) -> Result<u64> {
+        self.inner.block_count()
+    }
+
+    fn fetch_block(&self, height: u64) -> Result<CachedBlock> {
+        self.inner.fetch_block(height)
+    }
+
+    fn fetch_blocks(&self, heights: &[u64]) -> Result<Vec<CachedBlock>> {
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(5));
+        let blocks = heights.iter().map(|h| self.fetch_block(*h)).collect();
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        blocks
+    }
+}
+
+/// Reports the next of `tips` on every `block_count` call and raises `interrupt` once the
+/// last one has been handed out, like a node that keeps mining until the operator presses
+/// Ctrl-C.
+struct GrowingRpcClient {
+    inner: MockRpcClient,
+    tips: Mutex<Vec<u64>>,
+    interrupt: Arc<AtomicBool>,
+}
+
+impl RpcClient for GrowingRpcClient {
+    fn block_count(&self) -> Result<u64> {
+        let mut tips = self.tips.lock().unwrap();
+        let tip = tips.remove(0);
+        if tips.is_empty() {
+            self.interrupt.store(true, Ordering::SeqCst);
+        }
+        Ok(tip)
+    }
+
+    fn fetch_block(&self, height: u64) -> Result<CachedBlock> {
+        self.inner.fetch_block(height)
+    }
+}
+
+fn not_interrupted() -> AtomicBool {
+    AtomicBool::new(false)
+}
+
+fn block(height: u64, fork: &str) -> CachedBlock {
+    CachedBlock {
+        height,
+        hash: format!("{fork}-{height:08}"),
+        time: 1_700_000_000 + height as u32 * 75,
+        outputs: vec![CoinbaseOutput {
+            value_zat: 312_500_000,
+            addresses: vec![format!("t1miner{height}")],
+            hex: None,
+        }],
+        subsidy_zat: None,
+    }
+}
+
+#[test]
+fn fetches_every_height_into_an_empty_cache() {
+    let client = MockRpcClient::chain(20, "main");
+    let mut cache = BlockCache::default();
+
+    let summary = sync_cache(&client, &mut cache, 5, 4).unwrap();
+
+    assert_eq!(summary.tip_height, 20);
+    assert_eq!(summary.fetched_blocks, 16);
+    assert_eq!(summary.reorged_blocks, 0);
+    assert_eq!(cache.last_tip, Some(20));
+    assert_eq!(
+        cache.blocks.keys().copied().collect::<Vec<_>>(),
+        (5..=20).collect::<Vec<_>>()
+    );
+    assert_eq!(cache.blocks[&12].hash, "main-00000012");
+}
+
+#[test]
+fn refetches_blocks_replaced_by_a_reorg() {
+    let mut client = MockRpcClient::chain(20, "main");
+    let mut cache = BlockCache::default();
+    sync_cache(&client, &mut cache, 0, 8).unwrap();
+
+    // The node switches to a fork from height 18 on and grows by two blocks.
+    for height in 18..=22 {
+        client.blocks.insert(height, block(height, "fork"));
+    }
+    let summary = sync_cache(&client, &mut cache, 0, 8).unwrap();
+
+    assert_eq!(summary.tip_height, 22);
+    assert_eq!(summary.reorged_blocks, 3);
+    assert_eq!(summary.fetched_blocks, 5);
+    assert_eq!(cache.blocks[&17].hash, "main-00000017");
+    for height in 18..=22 {
+        assert_eq!(cache.blocks[&height].hash, format!("fork-{height:08}"));
+    }
+}
+
+#[test]
+fn failed_fetch_leaves_the_cache_untouched() {
+    let mut client = MockRpcClient::chain(10, "main");
+    let mut cache = BlockCache::default();
+    sync_cache(&client, &mut cache, 0, 4).unwrap();
+
+    for height in 11..=15 {
+        client.blocks.insert(height, block(height, "main"));
+    }
+    client.failing.push(13);
+    let err = sync_cache(&client, &mut cache, 0, 4).unwrap_err();
+
+    assert!(matches!(err, Error::RpcError { code: -1, .. }), "{err}");
+    assert_eq!(cache.last_tip, Some(10));
+    assert_eq!(cache.blocks.len(), 11);
+}
+
+#[test]
+fn never_exceeds_max_concurrent_requests() {
+    let client = CountingRpcClient {
+        inner: MockRpcClient::chain(199, "main"),
+        limit: 2,
+        in_flight: AtomicUsize::new(0),
+        peak: AtomicUsize::new(0),
+    };
+    let mut cache = BlockCache::default();
+
+    // One height per batch gives rayon 200 calls to run in parallel.
+    sync_cache(&client, &mut cache, 0, 1).unwrap();
+
+    assert_eq!(cache.blocks.len(), 200);
+    let peak = client.peak.load(Ordering::SeqCst);
+    assert!((1..=2).contains(&peak), "{peak} requests in flight");
+}
+
+#[test]
+fn checkpoints_between_whole_batch_segments() {
+    let client = MockRpcClient::chain(19, "main");
+    let mut cache = BlockCache::default();
+    let mut saved = Vec::new();
+
+    // An interval of 5 rounds up to segments of three batches of 2 heights.
+    sync_cache_with_checkpoints(&client, &mut cache, 0, 2, 5, &not_interrupted(), |cache| {
+        saved.push(cache.blocks.len());
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(saved, [6, 12, 18]);
+    assert_eq!(cache.blocks.len(), 20);
+}
+
+/// Joins `handle`, failing the test if it has not finished within `timeout`.
+fn join_within<T>(handle: JoinHandle<T>, timeout: Duration) -> T {
+    let deadline = Instant::now() + timeout;
+    while !handle.is_finished() {
+        assert!(
+            Instant::now() < deadline,
+            "thread still running (deadlock?)"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    handle.join().unwrap()
+}
+
+#[test]
+fn statistics_read_a_shared_cache_while_it_is_synced() {
+    let dir = std::env::temp_dir().join(format!(
+        "uview-miner-stats-sync-shared-{}",
+        std::process::id()
+    ));
+    let cfg = MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain_from_str("regtest").unwrap())
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ALPHA_UFVK)
+        .cache_file(dir.join("cache.json"))
+        .output_file(dir.join("report.json"))
+        .build()
+        .unwrap();
+    let client = Arc::new(CountingRpcClient {
+        inner: MockRpcClient::chain(199, "main"),
+        limit: 2,
+        in_flight: AtomicUsize::new(0),
+        peak: AtomicUsize::new(0),
+    });
+    let cache = SharedCache::default();
+    let synced = Arc::new(AtomicBool::new(false));
+
+    let writer = thread::spawn({
+        let (client, cache, synced) = (client.clone(), cache.clone(), synced.clone());
+        move || {
+            let summary =
+                sync_shared_cache(&*client, &cache, 0, 2, 10, &not_interrupted(), |_| Ok(()));
+            synced.store(true, Ordering::SeqCst);
+            summary
+        }
+    });
+    let reader = thread::spawn({
+        let (cache, synced) = (cache.clone(), synced.clone());
+        move || {
+            let (mut reads, mut seen) = (0, 0);
+            loop {
+                let done = synced.load(Ordering::SeqCst);
+                let cache = cache.read().unwrap();
+                // Segments of 10 heights are inserted under one write lock.
+                assert_eq!(cache.blocks.len() % 10, 0);
+                assert!(cache.blocks.len() >= seen);
+                seen = cache.blocks.len();
+                compute_statistics(&cfg, &cache, 199).unwrap();
+                reads += 1;
+                if done {
+                    return (reads, seen);
+                }
+            }
+        }
+    });
+
+    let summary = join_within(writer, Duration::from_secs(30)).unwrap();
+    let (reads, seen) = join_within(reader, Duration::from_secs(30));
+    assert_eq!(summary.fetched_blocks, 200);
+    assert!(reads > 0);
+    assert_eq!(seen, 200);
+    assert_eq!(cache.read().unwrap().last_tip, Some(199));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn failed_segment_keeps_earlier_checkpointed_blocks() {
+    let mut client = MockRpcClient::chain(19, "main");
+    client.failing.push(15);
+    let mut cache = BlockCache::default();
+
+    let err =
+        sync_cache_with_checkpoints(&client, &mut cache, 0, 2, 6, &not_interrupted(), |_| Ok(()))
+            .unwrap_err();
+
+    assert!(matches!(err, Error::RpcError { code: -1, .. }), "{err}");
+    assert_eq!(cache.blocks.len(), 12);
+    assert_eq!(cache.last_tip, None);
+}
+
+#[test]
+fn interrupted_sync_keeps_completed_segments_without_a_tip() {
+    let client = MockRpcClient::chain(19, "main");
+    let mut cache = BlockCache::default();
+    let interrupt = AtomicBool::new(false);
+
+    // Ctrl-C arrives while the first segment is being checkpointed.
+    let err = sync_cache_with_checkpoints(&client, &mut cache, 0, 2, 6, &interrupt, |_| {
+        interrupt.store(true, Ordering::SeqCst);
+        Ok(())
+    })
+    .unwrap_err();
+
+    assert!(matches!(err, Error::Interrupted), "{err}");
+    assert_eq!(cache.blocks.len(), 6);
+    assert_eq!(cache.last_tip, None);
+}
+
+#[test]
+fn repair_refetches_missing_empty_and_stale_blocks() {
+    let client = MockRpcClient::chain(30, "main");
+    let mut cache = BlockCache::default();
+    sync_cache(&client, &mut cache, 0, 8).unwrap();
+
+    // A gap, a block whose outputs were lost, and one far below the re-org check depth
+    // that was cached from another fork.
+    cache.blocks.remove(&7);
+    cache.blocks.remove(&8);
+    cache.blocks.get_mut(&12).unwrap().outputs.clear();
+    cache.blocks.insert(2, block(2, "fork"));
+    let summary = repair_cache(&client, &mut cache, 0, 8).unwrap();
+
+    assert_eq!(
+        summary,
+        RepairSummary {
+            missing_blocks: 2,
+            empty_blocks: 1,
+            mismatched_blocks: 1,
+        }
+    );
+    assert_eq!(cache.blocks.len(), 31);
+    for height in [2, 7, 8, 12] {
+        assert_eq!(cache.blocks[&height].hash, format!("main-{height:08}"));
+        assert!(!cache.blocks[&height].outputs.is_empty());
+    }
+    assert_eq!(
+        repair_cache(&client, &mut cache, 0, 8)
+            .unwrap()
+            .repaired_blocks(),
+        0
+    );
+}
+
+/// The options of `uview-miner-stats --watch` polling every 10 ms until `interrupt` is set.
+fn watch_options(interrupt: Arc<AtomicBool>) -> RunOptions {
+    RunOptions {
+        output_format: OutputFormat::Json,
+        detail_output: None,
+        stream_output: None,
+        influx_output: None,
+        es_output: None,
+        es_index: DEFAULT_ES_INDEX.to_string(),
+        compress_output: false,
+        decompress_output: false,
+        summary_only: false,
+        no_cache: false,
+        force_refetch: false,
+        dry_run: false,
+        color: false,
+        sort_by: MinerSortOrder::Config,
+        prune_cache: false,
+        label_width: DEFAULT_LABEL_WIDTH,
+        show_value_share: false,
+        table_no_border: false,
+        no_outliers: false,
+        show_activity: false,
+        show_maturity: false,
+        human_values: false,
+        timezone: Tz::UTC,
+        tui: false,
+        watch: Some(Duration::from_millis(10)),
+        interrupt,
+    }
+}
+
+#[test]
+fn watch_follows_the_tip_until_interrupted() {
+    let dir = std::env::temp_dir().join(format!("uview-miner-stats-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cfg = MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain_from_str("regtest").unwrap())
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ALPHA_UFVK)
+        .cache_file(dir.join("cache.json"))
+        .output_file(dir.join("report.json"))
+        .build()
+        .unwrap();
+    let interrupt = Arc::new(AtomicBool::new(false));
+    // The tip grows, then moves back by one block (a re-org), which must not end the loop.
+    let client = GrowingRpcClient {
+        inner: MockRpcClient::chain(7, "main"),
+        tips: Mutex::new(vec![5, 7, 6]),
+        interrupt: interrupt.clone(),
+    };
+    let backend = JsonFileCache {
+        path: dir.join("cache.json"),
+        compressed: false,
+        format: CacheFormat::Json,
+        readonly: false,
+    };
+    let options = watch_options(interrupt);
+
+    let err = run_with_client(&cfg, &client, &backend, &Metrics::default(), &options).unwrap_err();
+
+    assert!(matches!(err, Error::Interrupted), "{err}");
+    let cache = backend.load().unwrap().0;
+    assert_eq!(cache.last_tip, Some(6));
+    assert_eq!(cache.blocks.len(), 8);
+    let report: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(dir.join("report.json")).unwrap()).unwrap();
+    assert_eq!(report["end_height"], 6);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn statsd_miner_blocks_count_only_new_blocks_on_each_poll() {
+    let statsd = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    statsd
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    let dir = std::env::temp_dir().join(format!(
+        "uview-miner-stats-watch-statsd-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cfg = MinerStatsConfig::builder()
+        .start_height(0)
+        .chain(chain_from_str("regtest").unwrap())
+        .rpc_url("http://127.0.0.1:18232")
+        .add_miner("Alpha", ALPHA_UFVK)
+        .cache_file(dir.join("cache.json"))
+        .output_file(dir.join("report.json"))
+        .build()
+        .unwrap();
+    let blocks = regtest_chain()
+        .into_iter()
+        .map(|block| {
+            let cached = CachedBlock {
+                height: block.height,
+                hash: block.hash,
+                time: block.time,
+                outputs: vec![CoinbaseOutput {
+                    value_zat: block.value_zat,
+                    addresses: vec![block.payout_address],
+                    hex: None,
+                }],
+                subsidy_zat: None,
+            };
+            (block.height, cached)
+        })
+        .collect();
+    let interrupt = Arc::new(AtomicBool::new(false));
+    // Alpha has 3 blocks up to height 5 and 4 up to height 9; the last poll finds nothing new.
+    let client = GrowingRpcClient {
+        inner: MockRpcClient {
+            blocks,
+            failing: Vec::new(),
+        },
+        tips: Mutex::new(vec![5, 9, 9]),
+        interrupt: interrupt.clone(),
+    };
+    let backend = JsonFileCache {
+        path: dir.join("cache.json"),
+        compressed: false,
+        format: CacheFormat::Json,
+        readonly: false,
+    };
+    let metrics = Metrics::with_statsd(&statsd.local_addr().unwrap().to_string(), "test").unwrap();
+
+    let err =
+        run_with_client(&cfg, &client, &backend, &metrics, &watch_options(interrupt)).unwrap_err();
+
+    assert!(matches!(err, Error::Interrupted), "{err}");
+    let mut increments = Vec::new();
+    let mut buf = [0; 512];
+    while let Ok(len) = statsd.recv(&mut buf) {
+        let datagram = String::from_utf8_lossy(&buf[..len]).into_owned();
+        if let Some(count) = datagram
+            .strip_prefix("test.miner_blocks:")
+            .and_then(|rest| rest.strip_suffix("|c|#label:Alpha"))
+        {
+            increments.push(count.parse::<i64>().unwrap());
+        }
+    }
+    assert_eq!(increments, [3, 1]);
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,129 @@
+//! Rendering of the console summary table.
+
+use rust_decimal::Decimal;
+use unicode_width::UnicodeWidthStr;
+use uview_miner_stats_lib::{DEFAULT_LABEL_WIDTH, MinerAggregate, format_value, truncate_label};
+
+fn aggregate(label: &str) -> MinerAggregate {
+    MinerAggregate {
+        label: label.to_string(),
+        matched_blocks: 28,
+        total_value_zat: 17_500_000_000,
+        total_value_wec: Decimal::new(17_500, 2),
+        share_percent: 62.22,
+        blocks_per_day: 1.5,
+        longest_streak: 4,
+        current_streak: 0,
+        total_fees_zat: 0,
+        total_fees_wec: Decimal::ZERO,
+        value_share_percent: 58.5,
+        first_block: Some(1003),
+        last_block: Some(1240),
+        inactive_since: Some(10),
+        mature_value_zat: 15_000_000_000,
+        immature_value_zat: 2_500_000_000,
+        total_value_fiat: None,
+        fiat_currency: None,
+    }
+}
+
+#[test]
+fn wide_labels_keep_the_columns_aligned() {
+    let ascii = aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, false, false, false, false);
+    let cjk = aggregate("矿工甲号").table_row(DEFAULT_LABEL_WIDTH, false, false, false, false);
+
+    assert_eq!(cjk.width(), ascii.width(), "{cjk}\n{ascii}");
+}
+
+#[test]
+fn long_labels_are_truncated_to_the_column() {
+    let short = aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, false, false, false, false);
+    let long = aggregate("Mining Pool With A Very Long Name").table_row(
+        DEFAULT_LABEL_WIDTH,
+        false,
+        false,
+        false,
+        false,
+    );
+
+    assert_eq!(long.width(), short.width(), "{long}\n{short}");
+    assert!(long.starts_with("| Mining Pool With A …"), "{long}");
+}
+
+#[test]
+fn truncation_keeps_whole_graphemes_within_the_width() {
+    assert_eq!(truncate_label("Alpha", 5), "Alpha");
+    assert_eq!(truncate_label("矿工甲号", 6), "矿工…");
+    // "é" written as `e` plus a combining accent stays in one piece.
+    assert_eq!(truncate_label("Cafe\u{301} Pool", 6), "Cafe\u{301} …");
+}
+
+#[test]
+fn blocks_per_day_follows_the_block_count() {
+    let row = aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, false, false, false, false);
+    assert!(row.contains("|         28 |       1.50 |"), "{row}");
+}
+
+#[test]
+fn value_share_column_is_only_added_on_request() {
+    let plain = aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, false, false, false, false);
+    let with_value =
+        aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, true, false, false, false);
+
+    assert!(plain.ends_with("62.22% |"), "{plain}");
+    assert_eq!(with_value, format!("{plain}     58.50% |"));
+}
+
+#[test]
+fn activity_columns_follow_the_value_share() {
+    let plain = aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, true, false, false, false);
+    let active = aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, true, false, true, false);
+    assert_eq!(
+        active,
+        format!("{plain}       1003 |       1240 |         10 |")
+    );
+
+    let idle = MinerAggregate {
+        matched_blocks: 0,
+        first_block: None,
+        last_block: None,
+        inactive_since: None,
+        ..aggregate("Miner Beta")
+    };
+    let row = idle.table_row(DEFAULT_LABEL_WIDTH, false, false, true, false);
+    assert!(
+        row.ends_with("|          - |          - |          - |"),
+        "{row}"
+    );
+}
+
+#[test]
+fn value_mismatch_when_maturity_split_misses_the_total() {
+    assert!(!aggregate("Miner Alpha").value_mismatch());
+
+    let mismatched = MinerAggregate {
+        immature_value_zat: 2_000_000_000,
+        ..aggregate("Miner Alpha")
+    };
+    assert!(mismatched.value_mismatch());
+}
+
+#[test]
+fn maturity_columns_come_last() {
+    let active = aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, false, false, true, false);
+    let mature = aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, false, false, true, true);
+    assert_eq!(mature, format!("{active}     150.00 |      25.00 |"));
+}
+
+#[test]
+fn human_values_keep_small_amounts_visible() {
+    assert_eq!(format_value(1_250_000_000, false), "12.50");
+    assert_eq!(format_value(420_000, false), "0.00");
+    assert_eq!(format_value(1_250_000_000, true), "12.50 WEC");
+    assert_eq!(format_value(420_000, true), "0.004200 WEC");
+    assert_eq!(format_value(9_000, true), "9000 zat");
+    assert_eq!(format_value(-9_000, true), "-9000 zat");
+
+    let row = aggregate("Miner Alpha").table_row(DEFAULT_LABEL_WIDTH, false, true, false, false);
+    assert!(row.contains(" 175.00 WEC |"), "{row}");
+}